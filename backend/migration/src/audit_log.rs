@@ -0,0 +1,16 @@
+use sea_orm_migration::prelude::*;
+
+// based on audit::AuditEntry
+#[allow(unused)]
+#[derive(DeriveIden)]
+pub enum AuditLog {
+    Table,
+    Id,
+    NovelId,
+    Action,
+    Changes,
+    Data,
+    Actor,
+    Source,
+    CreatedAt,
+}
@@ -1,5 +1,7 @@
 pub use sea_orm_migration::prelude::*;
+pub use sea_orm_migration::MigrationStatus;
 
+mod audit_log;
 mod m20220101_000001_create_table;
 mod m20240620_064351_use_correct_chapter_type;
 mod m20240620_072646_use_vec_for_tags;
@@ -11,10 +13,23 @@ mod m20241227_014425_provider;
 mod m20241227_054323_fix_status;
 mod m20241227_065306_rename_providers;
 mod m20241227_224507_remove_optionals;
+mod m20261018_031542_create_tokens;
+mod m20261018_052210_create_snapshots;
+mod m20261018_071903_create_audit_log;
+mod m20261018_090247_add_deleted_at;
+mod m20261018_103512_novels_id_sequence;
+mod m20261018_121530_novel_enums;
 mod novels;
+mod snapshots;
+mod tokens;
 
 pub struct Migrator;
 
+// sqlite databases are always created from scratch, so they skip the migrations that reshaped the original postgres table
+pub(crate) fn is_postgres(manager: &SchemaManager) -> bool {
+    manager.get_database_backend() == sea_orm::DatabaseBackend::Postgres
+}
+
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
@@ -30,6 +45,12 @@ impl MigratorTrait for Migrator {
             Box::new(m20241227_054323_fix_status::Migration),
             Box::new(m20241227_065306_rename_providers::Migration),
             Box::new(m20241227_224507_remove_optionals::Migration),
+            Box::new(m20261018_031542_create_tokens::Migration),
+            Box::new(m20261018_052210_create_snapshots::Migration),
+            Box::new(m20261018_071903_create_audit_log::Migration),
+            Box::new(m20261018_090247_add_deleted_at::Migration),
+            Box::new(m20261018_103512_novels_id_sequence::Migration),
+            Box::new(m20261018_121530_novel_enums::Migration),
         ]
     }
 }
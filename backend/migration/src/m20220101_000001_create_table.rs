@@ -1,5 +1,5 @@
 use sea_orm_migration::prelude::*;
-use crate::novels::Novels;
+use crate::novels::{Novels, MAX_RATING, PROVIDERS, STATUSES};
 
 #[derive(DeriveMigrationName)]
 pub struct Migration;
@@ -7,6 +7,31 @@ pub struct Migration;
 #[async_trait::async_trait]
 impl MigrationTrait for Migration {
     async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite starts from the table the postgres migrations up to m20241227_224507_remove_optionals ended up with,
+        // with check constraints in place of the enums and rating range from m20261018_121530_novel_enums
+        if !crate::is_postgres(manager) {
+            return manager
+                .create_table(
+                    Table::create()
+                        .table(Novels::Table)
+                        .if_not_exists()
+                        .col(ColumnDef::new(Novels::Id).integer().not_null().primary_key().auto_increment())
+                        .col(ColumnDef::new(Novels::Country).string().not_null())
+                        .col(ColumnDef::new(Novels::Title).string().not_null())
+                        .col(ColumnDef::new(Novels::Chapter).string().not_null())
+                        .col(ColumnDef::new(Novels::Rating).integer().check(Expr::col(Novels::Rating).between(0, MAX_RATING)))
+                        .col(ColumnDef::new(Novels::Status).string().check(Expr::col(Novels::Status).is_in(STATUSES)))
+                        .col(ColumnDef::new(Novels::Tags).json().not_null())
+                        .col(ColumnDef::new(Novels::Notes).string().not_null())
+                        .col(ColumnDef::new(Novels::DateModified).date_time().not_null())
+                        .col(ColumnDef::new(Novels::DateStarted).date_time().null())
+                        .col(ColumnDef::new(Novels::DateCompleted).date_time().null())
+                        .col(ColumnDef::new(Novels::Provider).string().null().check(Expr::col(Novels::Provider).is_in(PROVIDERS)))
+                        .to_owned(),
+                )
+                .await;
+        }
+
         manager
             .create_table(
                 Table::create()
@@ -7,6 +7,9 @@ pub struct Migration;
 #[async_trait::async_trait]
 impl MigrationTrait for Migration {
     async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !crate::is_postgres(manager) {
+            return Ok(());
+        }
         let table = Table::alter()
             .table(Novels::Table)
             .drop_column(Novels::Tags)
@@ -16,6 +19,9 @@ impl MigrationTrait for Migration {
         }
 
     async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !crate::is_postgres(manager) {
+            return Ok(());
+        }
         let table = Table::alter()
             .table(Novels::Table)
             .drop_column(Novels::Tags)
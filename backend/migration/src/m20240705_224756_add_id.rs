@@ -7,6 +7,9 @@ pub struct Migration;
 #[async_trait::async_trait]
 impl MigrationTrait for Migration {
     async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !crate::is_postgres(manager) {
+            return Ok(());
+        }
         let table = Table::alter()
             .table(Novels::Table)
             .drop_column(Novels::Title) // need to drop and then add again since it's a primary key
@@ -17,6 +20,9 @@ impl MigrationTrait for Migration {
         }
 
     async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !crate::is_postgres(manager) {
+            return Ok(());
+        }
         let table = Table::alter()
             .table(Novels::Table)
             .drop_column(Novels::Id)
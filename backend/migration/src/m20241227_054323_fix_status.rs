@@ -14,7 +14,7 @@ impl MigrationTrait for Migration {
         let query = format!("UPDATE {table_name} SET {status_column} = NULL WHERE {status_column} = 'Invalid';");
         let db = manager.get_connection();
         db.execute(Statement::from_string(
-                sea_orm::DatabaseBackend::Postgres,
+                manager.get_database_backend(),
                 query
             ))
             .await?;
@@ -27,7 +27,7 @@ impl MigrationTrait for Migration {
         let query = format!("UPDATE {table_name} SET {status_column} = 'Invalid' WHERE {status_column} = NULL;");
         let db = manager.get_connection();
         db.execute(Statement::from_string(
-                sea_orm::DatabaseBackend::Postgres,
+                manager.get_database_backend(),
                 query
             ))
             .await?;
@@ -15,7 +15,7 @@ impl MigrationTrait for Migration {
         let query = format!("UPDATE {table_name} SET {column} = 'NovelUpdates' WHERE {column} = 'Novelupdates';");
         let db = manager.get_connection();
         db.execute(Statement::from_string(
-                sea_orm::DatabaseBackend::Postgres,
+                manager.get_database_backend(),
                 query
             ))
             .await?;
@@ -23,7 +23,7 @@ impl MigrationTrait for Migration {
         let query = format!("UPDATE {table_name} SET {column} = 'RoyalRoad' WHERE {column} = 'Royalroad';");
         let db = manager.get_connection();
         db.execute(Statement::from_string(
-                sea_orm::DatabaseBackend::Postgres,
+                manager.get_database_backend(),
                 query
             ))
             .await?;
@@ -37,7 +37,7 @@ impl MigrationTrait for Migration {
         let query = format!("UPDATE {table_name} SET {column} = 'Novelupdates' WHERE {column} = 'NovelUpdates';");
         let db = manager.get_connection();
         db.execute(Statement::from_string(
-                sea_orm::DatabaseBackend::Postgres,
+                manager.get_database_backend(),
                 query
             ))
             .await?;
@@ -45,7 +45,7 @@ impl MigrationTrait for Migration {
         let query = format!("UPDATE {table_name} SET {column} = 'Royalroad' WHERE {column} = 'RoyalRoad';");
         let db = manager.get_connection();
         db.execute(Statement::from_string(
-                sea_orm::DatabaseBackend::Postgres,
+                manager.get_database_backend(),
                 query
             ))
             .await?;
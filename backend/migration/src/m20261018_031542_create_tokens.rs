@@ -0,0 +1,31 @@
+use sea_orm_migration::prelude::*;
+use crate::tokens::Tokens;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Tokens::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Tokens::Id).integer().not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(Tokens::Name).string().not_null())
+                    .col(ColumnDef::new(Tokens::TokenHash).string().not_null().unique_key())
+                    .col(ColumnDef::new(Tokens::Scopes).json().not_null())
+                    .col(ColumnDef::new(Tokens::CreatedAt).date_time().not_null())
+                    .col(ColumnDef::new(Tokens::RevokedAt).date_time().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Tokens::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,30 @@
+use sea_orm_migration::prelude::*;
+use crate::snapshots::Snapshots;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Snapshots::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(Snapshots::Id).integer().not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(Snapshots::CreatedAt).date_time().not_null())
+                    .col(ColumnDef::new(Snapshots::Reason).string().not_null())
+                    .col(ColumnDef::new(Snapshots::NovelCount).integer().not_null())
+                    .col(ColumnDef::new(Snapshots::Data).json().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Snapshots::Table).to_owned())
+            .await
+    }
+}
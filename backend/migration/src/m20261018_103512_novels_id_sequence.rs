@@ -0,0 +1,62 @@
+use sea_orm_migration::prelude::*;
+use sea_orm::Statement;
+
+use crate::novels::Novels;
+
+/*
+m20240705_232421_nonopt_id asked for an auto increment id, but modifying an existing column never attached a sequence to it.
+This creates one owned by the column and starts it after the largest id already in use.
+*/
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !crate::is_postgres(manager) {
+            return Ok(());
+        }
+        let table_name = Novels::Table.to_string();
+        let id_column = Novels::Id.to_string();
+        let sequence = format!("{table_name}_{id_column}_seq");
+        let queries = [
+            format!("CREATE SEQUENCE IF NOT EXISTS {sequence} AS integer OWNED BY {table_name}.{id_column};"),
+            format!("SELECT setval('{sequence}', COALESCE((SELECT MAX({id_column}) FROM {table_name}), 0) + 1, false);"),
+            format!("ALTER TABLE {table_name} ALTER COLUMN {id_column} SET DEFAULT nextval('{sequence}');"),
+        ];
+
+        let db = manager.get_connection();
+        for query in queries {
+            db.execute(Statement::from_string(
+                    sea_orm::DatabaseBackend::Postgres,
+                    query
+                ))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !crate::is_postgres(manager) {
+            return Ok(());
+        }
+        let table_name = Novels::Table.to_string();
+        let id_column = Novels::Id.to_string();
+        let sequence = format!("{table_name}_{id_column}_seq");
+        let queries = [
+            format!("ALTER TABLE {table_name} ALTER COLUMN {id_column} DROP DEFAULT;"),
+            format!("DROP SEQUENCE IF EXISTS {sequence};"),
+        ];
+
+        let db = manager.get_connection();
+        for query in queries {
+            db.execute(Statement::from_string(
+                    sea_orm::DatabaseBackend::Postgres,
+                    query
+                ))
+                .await?;
+        }
+        Ok(())
+    }
+}
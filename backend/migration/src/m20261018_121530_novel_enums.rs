@@ -0,0 +1,119 @@
+use sea_orm_migration::prelude::*;
+use sea_orm::Statement;
+
+use crate::novels::{Novels, MAX_RATING, PROVIDERS, STATUSES};
+
+/*
+Status and provider were free text, so a renamed variant or a typo could be stored and break every read (see m20241227_054323_fix_status).
+This turns them into postgres enums and keeps ratings between 0 and 10.
+Values that only differ from a variant by case or whitespace are normalized first.
+Anything else fails the migration with the offending rows instead of being cleared or clamped.
+`novels doctor --fix` runs while this migration is pending and repairs them, and the migration can then be run again.
+Sqlite gets the same rules as check constraints when its table is created.
+*/
+
+#[derive(DeriveIden)]
+enum NovelStatus {
+    #[sea_orm(iden = "novel_status")]
+    Type,
+}
+
+#[derive(DeriveIden)]
+enum NovelProvider {
+    #[sea_orm(iden = "novel_provider")]
+    Type,
+}
+
+const RATING_CONSTRAINT: &str = "novels_rating_range";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !crate::is_postgres(manager) {
+            return Ok(());
+        }
+        let db = manager.get_connection();
+        let table_name = Novels::Table.to_string();
+        let rating_column = Novels::Rating.to_string();
+        let columns = [
+            (Novels::Status.to_string(), NovelStatus::Type.to_string(), STATUSES.as_slice()),
+            (Novels::Provider.to_string(), NovelProvider::Type.to_string(), PROVIDERS.as_slice()),
+        ];
+
+        let mut invalid = Vec::new();
+        for (column, _, variants) in &columns {
+            for variant in *variants {
+                execute(db, format!(
+                    "UPDATE {table_name} SET {column} = '{variant}' WHERE LOWER(REGEXP_REPLACE({column}, '\\s', '', 'g')) = LOWER('{variant}');"
+                )).await?;
+            }
+            let variants = quoted(variants);
+            invalid.extend(invalid_values(db, &format!(
+                "SELECT id, {column} AS value FROM {table_name} WHERE {column} NOT IN ({variants}) ORDER BY id;"
+            ), column).await?);
+        }
+        invalid.extend(invalid_values(db, &format!(
+            "SELECT id, CAST({rating_column} AS text) AS value FROM {table_name} WHERE {rating_column} NOT BETWEEN 0 AND {MAX_RATING} ORDER BY id;"
+        ), &rating_column).await?);
+        if !invalid.is_empty() {
+            return Err(DbErr::Migration(format!(
+                "{} stored values can't be converted: {}. Run `novels doctor --fix` to repair them, then `migrate up` again",
+                invalid.len(),
+                invalid.join(", ")
+            )));
+        }
+
+        let mut queries = Vec::new();
+        for (column, type_name, variants) in &columns {
+            let variants = quoted(variants);
+            queries.push(format!("CREATE TYPE {type_name} AS ENUM ({variants});"));
+            queries.push(format!("ALTER TABLE {table_name} ALTER COLUMN {column} TYPE {type_name} USING {column}::{type_name};"));
+        }
+        queries.push(format!("ALTER TABLE {table_name} ADD CONSTRAINT {RATING_CONSTRAINT} CHECK ({rating_column} BETWEEN 0 AND {MAX_RATING});"));
+        for query in queries {
+            execute(db, query).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !crate::is_postgres(manager) {
+            return Ok(());
+        }
+        let table_name = Novels::Table.to_string();
+        let mut queries = vec![format!("ALTER TABLE {table_name} DROP CONSTRAINT IF EXISTS {RATING_CONSTRAINT};")];
+        for (column, type_name) in [
+            (Novels::Status.to_string(), NovelStatus::Type.to_string()),
+            (Novels::Provider.to_string(), NovelProvider::Type.to_string()),
+        ] {
+            queries.push(format!("ALTER TABLE {table_name} ALTER COLUMN {column} TYPE varchar USING {column}::text;"));
+            queries.push(format!("DROP TYPE IF EXISTS {type_name};"));
+        }
+
+        let db = manager.get_connection();
+        for query in queries {
+            execute(db, query).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn execute(db: &SchemaManagerConnection<'_>, query: String) -> Result<(), DbErr> {
+    db.execute(Statement::from_string(sea_orm::DatabaseBackend::Postgres, query)).await?;
+    Ok(())
+}
+
+fn quoted(variants: &[&str]) -> String {
+    variants.iter().map(|variant| format!("'{variant}'")).collect::<Vec<_>>().join(", ")
+}
+
+// describes each row the query returns, like `status of novel 4: Webnovel`
+async fn invalid_values(db: &SchemaManagerConnection<'_>, query: &str, column: &str) -> Result<Vec<String>, DbErr> {
+    let rows = db.query_all(Statement::from_string(sea_orm::DatabaseBackend::Postgres, query)).await?;
+    rows.iter()
+        .map(|row| Ok(format!("{column} of novel {}: {}", row.try_get::<i32>("", "id")?, row.try_get::<String>("", "value")?)))
+        .collect()
+}
@@ -17,4 +17,13 @@ pub enum Novels {
     DateStarted,
     DateCompleted,
     Provider,
+    DeletedAt,
 }
+
+// the values novel_entry::Status, novel_entry::Provider and ratings are limited to
+#[allow(unused)]
+pub const STATUSES: [&str; 6] = ["Completed", "Dropped", "Hiatus", "Planning", "Reading", "Waiting"];
+#[allow(unused)]
+pub const PROVIDERS: [&str; 2] = ["NovelUpdates", "RoyalRoad"];
+#[allow(unused)]
+pub const MAX_RATING: i32 = 10;
@@ -0,0 +1,13 @@
+use sea_orm_migration::prelude::*;
+
+// based on snapshot::Snapshot
+#[allow(unused)]
+#[derive(DeriveIden)]
+pub enum Snapshots {
+    Table,
+    Id,
+    CreatedAt,
+    Reason,
+    NovelCount,
+    Data,
+}
@@ -0,0 +1,14 @@
+use sea_orm_migration::prelude::*;
+
+// based on auth personal access tokens
+#[allow(unused)]
+#[derive(DeriveIden)]
+pub enum Tokens {
+    Table,
+    Id,
+    Name,
+    TokenHash,
+    Scopes,
+    CreatedAt,
+    RevokedAt,
+}
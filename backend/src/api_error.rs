@@ -0,0 +1,292 @@
+use crate::{audit, backup, db, novel_entry, snapshot, validation};
+
+use std::fmt;
+
+use axum::{
+    extract::{
+        multipart::{MultipartError, MultipartRejection},
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequest,
+        FromRequestParts,
+    },
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use sea_orm::DbErr;
+use serde::Serialize;
+use serde_json::{json, Value};
+use strum::Display;
+
+/*
+Every handler error is sent as `{ "code": ..., "message": ..., "details": ... }`.
+`code` is stable and meant for clients to match on; `message` is for humans and may change.
+`details` carries whatever the client needs to recover, like the current rows in a conflict.
+Internal and database errors only send a generic message; the error behind them is logged with the request instead.
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq, Display, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorCode {
+    ValidationFailed,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    DbUnavailable,
+    UpstreamFailed,
+    // a stored novel the backend can't read; `novels doctor --fix` repairs it
+    InvalidStoredNovel,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::ValidationFailed => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::DbUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::UpstreamFailed => StatusCode::BAD_GATEWAY,
+            ErrorCode::InvalidStoredNovel => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Option<Value>,
+    #[serde(skip)]
+    pub cause: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { status: code.status(), code, message: message.into(), details: None, cause: None }
+    }
+
+    pub fn internal(cause: impl fmt::Display) -> Self {
+        Self::new(ErrorCode::Internal, "Internal server error").with_cause(cause)
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::ValidationFailed, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Conflict, message)
+    }
+
+    pub fn upstream(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::UpstreamFailed, message)
+    }
+
+    // keeps the code but answers with a more specific status, like 422 for a body that parsed but made no sense
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_details(mut self, details: impl Serialize) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+
+    // the alternate format includes the whole chain of an anyhow error
+    fn with_cause(mut self, cause: impl fmt::Display) -> Self {
+        self.cause = Some(format!("{cause:#}"));
+        self
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.code, self.status, self.message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if self.status.is_server_error() {
+            match &self.cause {
+                Some(cause) => tracing::error!("Request failed: {self}: {cause}"),
+                None => tracing::error!("Request failed: {self}"),
+            }
+        }
+        (self.status, Json(self)).into_response()
+    }
+}
+
+// errors from the rest of the backend are anyhow errors; the ones clients can act on get their own code
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<db::NovelConflict>() {
+            Ok(conflict) => return ApiError::conflict(conflict.to_string()).with_details(conflict.current),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<db::NovelsInTrash>() {
+            Ok(trashed) => return ApiError::conflict(trashed.to_string()).with_details(json!({ "ids": trashed.ids })),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<backup::RestoreRejected>() {
+            Ok(rejected) if rejected.report.has_conflicts() => return ApiError::conflict(rejected.to_string()).with_details(rejected.report),
+            Ok(rejected) => return ApiError::validation(rejected.to_string())
+                .with_status(StatusCode::UNPROCESSABLE_ENTITY)
+                .with_details(rejected.report),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<validation::InvalidNovels>() {
+            Ok(invalid) => return ApiError::validation(invalid.to_string())
+                .with_status(StatusCode::UNPROCESSABLE_ENTITY)
+                .with_details(invalid.rows),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<novel_entry::InvalidNovelRow>() {
+            Ok(invalid) => return ApiError::new(ErrorCode::InvalidStoredNovel, invalid.to_string())
+                .with_details(json!({ "id": invalid.id, "field": invalid.field })),
+            Err(e) => e,
+        };
+        if e.is::<audit::AuditEntryNotFound>() || e.is::<snapshot::SnapshotNotFound>() {
+            return ApiError::not_found(e.to_string());
+        }
+        if e.is::<audit::NothingToRevert>() {
+            return ApiError::validation(e.to_string()).with_status(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        match e.downcast_ref::<DbErr>() {
+            Some(db_err) => db_err_to_api(db_err),
+            None => ApiError::internal(e),
+        }
+    }
+}
+
+impl From<DbErr> for ApiError {
+    fn from(e: DbErr) -> Self {
+        db_err_to_api(&e)
+    }
+}
+
+fn db_err_to_api(e: &DbErr) -> ApiError {
+    match e {
+        DbErr::ConnectionAcquire(_) | DbErr::Conn(_) => ApiError::new(ErrorCode::DbUnavailable, "The database is unavailable").with_cause(e),
+        _ => ApiError::internal(e),
+    }
+}
+
+impl From<MultipartError> for ApiError {
+    fn from(e: MultipartError) -> Self {
+        ApiError::validation(e.body_text()).with_status(e.status())
+    }
+}
+
+impl From<MultipartRejection> for ApiError {
+    fn from(e: MultipartRejection) -> Self {
+        ApiError::validation(e.body_text()).with_status(e.status())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(e: JsonRejection) -> Self {
+        ApiError::validation(e.body_text()).with_status(e.status())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(e: QueryRejection) -> Self {
+        ApiError::validation(e.body_text()).with_status(e.status())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(e: PathRejection) -> Self {
+        ApiError::validation(e.body_text()).with_status(e.status())
+    }
+}
+
+// the axum extractors, but rejecting malformed requests with an ApiError
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct ApiQuery<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct ApiPath<T>(pub T);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::novel_entry::NovelEntry;
+    use axum::body::to_bytes;
+    use sea_orm::ConnAcquireErr;
+
+    async fn response_json(e: ApiError) -> (StatusCode, Value) {
+        let res = e.into_response();
+        let status = res.status();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn error_envelope() {
+        let (status, body) = response_json(ApiError::not_found("Novel not found: 3")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, json!({ "code": "not_found", "message": "Novel not found: 3", "details": null }));
+
+        let (status, body) = response_json(ApiError::internal("relation \"novels\" does not exist")).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body, json!({ "code": "internal", "message": "Internal server error", "details": null }));
+    }
+
+    #[tokio::test]
+    async fn conflict_details() {
+        let conflict = db::NovelConflict { current: vec![NovelEntry::empty(2)] };
+        let (status, body) = response_json(anyhow::Error::from(conflict).into()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["code"], "conflict");
+        assert_eq!(body["details"][0]["id"], 2);
+
+        let (status, body) = response_json(anyhow::Error::from(db::NovelsInTrash { ids: vec![4] }).into()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["details"], json!({ "ids": [4] }));
+    }
+
+    #[test]
+    fn map_anyhow_errors() {
+        let e: ApiError = anyhow::Error::from(DbErr::ConnectionAcquire(ConnAcquireErr::Timeout)).into();
+        assert_eq!((e.status, e.code), (StatusCode::SERVICE_UNAVAILABLE, ErrorCode::DbUnavailable));
+
+        let e: ApiError = anyhow::Error::from(DbErr::RecordNotInserted).into();
+        assert_eq!((e.status, e.code), (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Internal));
+
+        // server errors keep what went wrong to the logs
+        let e: ApiError = anyhow::Error::msg("password authentication failed for user \"novels\"").context("Failed to connect").into();
+        assert_eq!((e.code, e.message.as_str()), (ErrorCode::Internal, "Internal server error"));
+        assert_eq!(e.cause.as_deref(), Some("Failed to connect: password authentication failed for user \"novels\""));
+
+        let e: ApiError = anyhow::Error::from(snapshot::SnapshotNotFound(4)).into();
+        assert_eq!((e.status, e.code), (StatusCode::NOT_FOUND, ErrorCode::NotFound));
+
+        let invalid = novel_entry::InvalidNovelRow { id: 3, field: "tags".into(), value: "{}".into() };
+        let e: ApiError = anyhow::Error::from(invalid).into();
+        assert_eq!((e.status, e.code), (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InvalidStoredNovel));
+        assert!(e.message.starts_with("Novel 3 has an invalid tags"));
+        assert_eq!(e.details, Some(json!({ "id": 3, "field": "tags" })));
+
+        let e: ApiError = anyhow::Error::from(audit::NothingToRevert(1)).into();
+        assert_eq!((e.status, e.code), (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::ValidationFailed));
+    }
+}
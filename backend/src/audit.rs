@@ -0,0 +1,297 @@
+use crate::auth::Caller;
+use crate::db::{self, CheckConflicts, UpdateDateModified};
+use crate::entity::audit_log;
+use crate::novel_entry::{self, NovelEntry};
+
+use std::{env, fmt, str::FromStr};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sea_orm::{DatabaseConnection, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use strum::{Display, EnumString};
+
+/*
+Every write to the novels table goes through db.rs, which records an audit entry next to it.
+Each entry stores the fields that changed along with the whole row afterwards, so any entry can be reverted to.
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq, Display, EnumString, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum AuditAction {
+    Insert,
+    Update,
+    Delete,
+    // moved to or out of the trash
+    Trash,
+    Restore,
+}
+
+// where a change came from
+#[derive(Copy, Clone, Debug, PartialEq, Display, EnumString, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AuditSource {
+    Web,
+    Cli,
+    Scrape,
+    CsvImport,
+    Restore,
+    Doctor,
+}
+
+// who is making a change and how, passed down to every write
+#[derive(Clone, Debug)]
+pub struct AuditContext {
+    pub actor: String,
+    pub source: AuditSource,
+}
+
+impl AuditContext {
+    pub fn new(actor: impl Into<String>, source: AuditSource) -> Self {
+        Self { actor: actor.into(), source }
+    }
+
+    pub fn from_caller(caller: &Caller, source: AuditSource) -> Self {
+        Self::new(caller.subject.clone(), source)
+    }
+
+    // changes made from a terminal are attributed to the local user
+    pub fn local(source: AuditSource) -> Self {
+        let actor = env::var("USER").unwrap_or_else(|_| "cli".to_string());
+        Self::new(actor, source)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i32,
+    pub novel_id: i32,
+    pub action: AuditAction,
+    // field name -> { "before": ..., "after": ... }
+    pub changes: Map<String, Value>,
+    // the novel after this change; deletions have none
+    pub data: Option<NovelEntry>,
+    pub actor: String,
+    pub source: AuditSource,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AuditEntry {
+    pub fn from_model(model: audit_log::Model) -> Result<Self> {
+        let changes = match model.changes {
+            Value::Object(changes) => changes,
+            _ => Map::new(),
+        };
+        let data = model.data.map(serde_json::from_value).transpose()?;
+        Ok(Self {
+            id: model.id,
+            novel_id: model.novel_id,
+            action: AuditAction::from_str(&model.action)?,
+            changes,
+            data,
+            actor: model.actor,
+            source: AuditSource::from_str(&model.source)?,
+            created_at: model.created_at.and_utc(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct AuditEntryNotFound {
+    pub novel_id: i32,
+    pub entry_id: i32,
+}
+
+impl fmt::Display for AuditEntryNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Novel {} has no history entry {}", self.novel_id, self.entry_id)
+    }
+}
+
+impl std::error::Error for AuditEntryNotFound {}
+
+// returned when reverting to an entry that deleted or trashed the novel
+#[derive(Debug)]
+pub struct NothingToRevert(pub i32);
+
+impl fmt::Display for NothingToRevert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "History entry {} removed the novel and has no version to revert to", self.0)
+    }
+}
+
+impl std::error::Error for NothingToRevert {}
+
+pub fn audit_action(before: Option<&NovelEntry>, after: Option<&NovelEntry>) -> Option<AuditAction> {
+    match (before, after) {
+        (None, Some(_)) => Some(AuditAction::Insert),
+        (Some(_), Some(_)) => Some(AuditAction::Update),
+        (Some(_), None) => Some(AuditAction::Delete),
+        (None, None) => None,
+    }
+}
+
+// the fields that differ between two versions of a novel; a missing side counts as all nulls
+pub fn diff_novels(before: Option<&NovelEntry>, after: Option<&NovelEntry>) -> Map<String, Value> {
+    let before = to_fields(before);
+    let after = to_fields(after);
+
+    let mut changes = Map::new();
+    for field in before.keys().chain(after.keys()) {
+        let old = before.get(field).unwrap_or(&Value::Null);
+        let new = after.get(field).unwrap_or(&Value::Null);
+        if old != new && !changes.contains_key(field) {
+            changes.insert(field.clone(), json!({ "before": old, "after": new }));
+        }
+    }
+    changes
+}
+
+fn to_fields(novel: Option<&NovelEntry>) -> Map<String, Value> {
+    match novel.map(serde_json::to_value) {
+        Some(Ok(Value::Object(fields))) => fields,
+        _ => Map::new(),
+    }
+}
+
+pub async fn novel_history(conn: &DatabaseConnection, novel_id: i32) -> Result<Vec<AuditEntry>> {
+    db::fetch_novel_history(conn, novel_id).await
+}
+
+// puts the novel back the way it was right after the given entry, recreating or untrashing it if needed;
+// untrashing and reverting happen in one transaction, so a revert that fails validation leaves the novel in the trash
+pub async fn revert_novel(conn: &DatabaseConnection, novel_id: i32, entry_id: i32, ctx: &AuditContext) -> Result<NovelEntry> {
+    let entry = db::fetch_audit_entry(conn, entry_id).await?
+        .filter(|entry| entry.novel_id == novel_id)
+        .ok_or(AuditEntryNotFound { novel_id, entry_id })?;
+    let Some(mut novel) = entry.data else {
+        return Err(NothingToRevert(entry_id).into());
+    };
+
+    let txn = conn.begin().await?;
+    novel.date_modified = novel_entry::now();
+    if db::recreate_novel(&txn, &novel, ctx).await? {
+        txn.commit().await?;
+        return Ok(novel);
+    }
+    db::restore_trashed_novel(&txn, novel_id, ctx).await?;
+    let mut reverted = db::update_novel_entries(&txn, &[novel], UpdateDateModified::False, CheckConflicts::False, ctx).await?;
+    txn.commit().await?;
+    Ok(reverted.remove(0).novel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_updated_novel() {
+        let before = NovelEntry::empty(1);
+        let mut after = before.clone();
+        after.title = "Lord of the Mysteries".into();
+        after.rating = 10;
+
+        let changes = diff_novels(Some(&before), Some(&after));
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes["title"], json!({ "before": before.title, "after": "Lord of the Mysteries" }));
+        assert_eq!(changes["rating"], json!({ "before": before.rating, "after": 10 }));
+        assert_eq!(audit_action(Some(&before), Some(&after)), Some(AuditAction::Update));
+    }
+
+    #[test]
+    fn diff_unchanged_novel() {
+        let novel = NovelEntry::empty(1);
+        assert!(diff_novels(Some(&novel), Some(&novel)).is_empty());
+    }
+
+    #[test]
+    fn diff_inserted_and_deleted_novel() {
+        let novel = NovelEntry::empty(1);
+
+        let inserted = diff_novels(None, Some(&novel));
+        assert_eq!(inserted["id"], json!({ "before": null, "after": 1 }));
+        assert_eq!(audit_action(None, Some(&novel)), Some(AuditAction::Insert));
+
+        let deleted = diff_novels(Some(&novel), None);
+        assert_eq!(deleted["id"], json!({ "before": 1, "after": null }));
+        assert_eq!(audit_action(Some(&novel), None), Some(AuditAction::Delete));
+    }
+
+    #[tokio::test]
+    async fn revert_to_version() {
+        let conn = db::memory_db().await;
+        let ctx = AuditContext::new("tester", AuditSource::Web);
+        let patch = crate::novel_entry::NovelPatch { title: Some("First".into()), ..Default::default() };
+        let novel = db::create_empty_row(&conn, &patch, &ctx).await.unwrap();
+        let rename = crate::novel_entry::NovelPatch { title: Some("Second".into()), ..Default::default() };
+        db::patch_novel_entry(&conn, novel.id, &rename, None, &ctx).await.unwrap();
+        db::trash_novel_entry(&conn, novel.id, &ctx).await.unwrap();
+
+        let history = novel_history(&conn, novel.id).await.unwrap();
+        let [trashed, renamed, created] = history.as_slice() else {
+            panic!("expected three history entries: {history:?}");
+        };
+        assert_eq!(renamed.changes["title"], json!({ "before": "First", "after": "Second" }));
+        assert!(matches!(
+            revert_novel(&conn, novel.id, trashed.id, &ctx).await.unwrap_err().downcast(),
+            Ok(NothingToRevert(_))
+        ));
+        assert!(revert_novel(&conn, novel.id + 1, created.id, &ctx).await.unwrap_err().is::<AuditEntryNotFound>());
+
+        // reverting brings the novel back out of the trash
+        let reverted = revert_novel(&conn, novel.id, created.id, &ctx).await.unwrap();
+        assert_eq!(reverted.title, "First");
+        assert_eq!(db::fetch_novel_by_id(&conn, novel.id).await.unwrap(), Some(reverted));
+    }
+
+    #[tokio::test]
+    async fn failed_revert_stays_in_trash() {
+        let conn = db::memory_db().await;
+        let ctx = AuditContext::new("tester", AuditSource::Web);
+        let first = crate::novel_entry::NovelPatch { title: Some("First".into()), ..Default::default() };
+        let novel = db::create_empty_row(&conn, &first, &ctx).await.unwrap();
+        let rename = crate::novel_entry::NovelPatch { title: Some("Second".into()), ..Default::default() };
+        db::patch_novel_entry(&conn, novel.id, &rename, None, &ctx).await.unwrap();
+        db::trash_novel_entry(&conn, novel.id, &ctx).await.unwrap();
+
+        // another novel took the old title in the meantime
+        db::create_empty_row(&conn, &first, &ctx).await.unwrap();
+        let history = novel_history(&conn, novel.id).await.unwrap();
+        let created = history.last().unwrap();
+        let err = revert_novel(&conn, novel.id, created.id, &ctx).await.unwrap_err();
+        assert!(err.is::<crate::validation::InvalidNovels>());
+
+        assert!(db::fetch_novel_by_id(&conn, novel.id).await.unwrap().is_none());
+        assert_eq!(db::fetch_trashed_novels(&conn).await.unwrap()[0].novel.title, "Second");
+        assert_eq!(novel_history(&conn, novel.id).await.unwrap().len(), history.len());
+    }
+
+    #[tokio::test]
+    async fn revert_recreates_purged_novel() {
+        let conn = db::memory_db().await;
+        let ctx = AuditContext::new("tester", AuditSource::Web);
+        let patch = crate::novel_entry::NovelPatch { title: Some("First".into()), ..Default::default() };
+        let novel = db::create_empty_row(&conn, &patch, &ctx).await.unwrap();
+        db::trash_novel_entry(&conn, novel.id, &ctx).await.unwrap();
+        db::purge_trash(&conn, Utc::now() + chrono::Duration::days(1), &ctx).await.unwrap();
+        assert!(db::fetch_trashed_novels(&conn).await.unwrap().is_empty());
+
+        // the novel comes back under its old id and later novels still get new ones
+        let created = novel_history(&conn, novel.id).await.unwrap().pop().unwrap();
+        let reverted = revert_novel(&conn, novel.id, created.id, &ctx).await.unwrap();
+        assert_eq!((reverted.id, reverted.title.as_str()), (novel.id, "First"));
+        assert_eq!(db::fetch_novel_by_id(&conn, novel.id).await.unwrap(), Some(reverted));
+        let second = crate::novel_entry::NovelPatch { title: Some("Second".into()), ..Default::default() };
+        assert_eq!(db::create_empty_row(&conn, &second, &ctx).await.unwrap().id, novel.id + 1);
+    }
+
+    #[test]
+    fn convert_audit_source() {
+        assert_eq!(AuditSource::CsvImport.to_string(), "csv_import");
+        assert_eq!(AuditSource::from_str("scrape").unwrap(), AuditSource::Scrape);
+        assert_eq!(serde_json::to_value(AuditAction::Delete).unwrap(), "delete");
+    }
+}
@@ -0,0 +1,258 @@
+use crate::api_error::{ApiError, ErrorCode};
+use crate::{db, AppState};
+
+use std::marker::PhantomData;
+
+use anyhow::Result;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use rand::{distributions::Alphanumeric, Rng};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
+
+/*
+Session tokens are minted by the frontend (NextAuth) for every backend request made on behalf of a signed in user.
+They are HS256 JWTs signed with `BACKEND_AUTH_SECRET`, which both servers must share.
+
+Personal access tokens are minted through the CLI for scripts and are stored hashed in the `tokens` table.
+*/
+pub const TOKEN_PREFIX: &str = "wnl_";
+const TOKEN_LENGTH: usize = 40;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub role: Role,
+    pub exp: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    User,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Display, EnumString, EnumIter, Serialize, Deserialize)]
+pub enum Scope {
+    #[strum(serialize = "novels:read")]
+    #[serde(rename = "novels:read")]
+    NovelsRead,
+    #[strum(serialize = "novels:write")]
+    #[serde(rename = "novels:write")]
+    NovelsWrite,
+    #[strum(serialize = "backup:restore")]
+    #[serde(rename = "backup:restore")]
+    BackupRestore,
+    #[strum(serialize = "stats:read")]
+    #[serde(rename = "stats:read")]
+    StatsRead,
+    #[strum(serialize = "tetris:run")]
+    #[serde(rename = "tetris:run")]
+    TetrisRun,
+}
+
+// anyone can do these, even without credentials
+const PUBLIC_SCOPES: [Scope; 3] = [Scope::NovelsRead, Scope::StatsRead, Scope::TetrisRun];
+
+impl Role {
+    pub fn scopes(self) -> Vec<Scope> {
+        match self {
+            Role::Admin => Scope::iter().collect(),
+            Role::User => PUBLIC_SCOPES.to_vec(),
+        }
+    }
+}
+
+// the identity of whoever made the request
+#[derive(Clone, Debug)]
+pub struct Caller {
+    pub subject: String,
+    pub scopes: Vec<Scope>,
+    pub authenticated: bool,
+}
+
+impl Caller {
+    fn anonymous() -> Self {
+        Self {
+            subject: "anonymous".to_string(),
+            scopes: PUBLIC_SCOPES.to_vec(),
+            authenticated: false,
+        }
+    }
+
+    // for routes whose required scope depends on the request body
+    pub fn authorize<S: RequiredScope>(self) -> Result<Authorized<S>, ApiError> {
+        tracing::Span::current().record("caller", self.subject.as_str());
+        if !self.scopes.contains(&S::SCOPE) {
+            let code = if self.authenticated { ErrorCode::Forbidden } else { ErrorCode::Unauthorized };
+            return Err(ApiError::new(code, format!("{} is missing the {} scope", self.subject, S::SCOPE)));
+        }
+        Ok(Authorized { caller: self, scope: PhantomData })
+    }
+}
+
+// marker types so each route can declare the scope it needs in its signature
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+pub mod scope {
+    use super::{RequiredScope, Scope};
+
+    macro_rules! required_scope {
+        ($($name:ident),*) => {
+            $(
+                pub struct $name;
+                impl RequiredScope for $name {
+                    const SCOPE: Scope = Scope::$name;
+                }
+            )*
+        };
+    }
+
+    required_scope!(NovelsRead, NovelsWrite, BackupRestore, StatsRead, TetrisRun);
+}
+
+// a caller that has been granted the scope `S`
+#[derive(Clone, Debug)]
+pub struct Authorized<S> {
+    pub caller: Caller,
+    scope: PhantomData<S>,
+}
+
+pub fn decode_session(token: &str, key: &DecodingKey) -> Result<Claims> {
+    let validation = Validation::new(Algorithm::HS256);
+    let data = decode::<Claims>(token, key, &validation)?;
+    Ok(data.claims)
+}
+
+pub fn generate_token() -> String {
+    let secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+    format!("{TOKEN_PREFIX}{secret}")
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+// the plaintext token is only ever returned here; only its hash is stored
+pub async fn mint_token(db: &DatabaseConnection, name: &str, scopes: &[Scope]) -> Result<String> {
+    let token = generate_token();
+    db::insert_token(db, name, &hash_token(&token), scopes).await?;
+    Ok(token)
+}
+
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    parts.headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Caller {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(token) = bearer_token(parts) else {
+            return Ok(Caller::anonymous());
+        };
+
+        if token.starts_with(TOKEN_PREFIX) {
+            let Some(model) = db::fetch_active_token(&state.conn, &hash_token(token)).await? else {
+                return Err(ApiError::new(ErrorCode::Unauthorized, "Unknown or revoked access token"));
+            };
+            let scopes = serde_json::from_value(model.scopes)
+                .map_err(ApiError::internal)?;
+            return Ok(Caller { subject: format!("token:{}", model.name), scopes, authenticated: true });
+        }
+
+        match decode_session(token, &state.auth_key) {
+            Ok(claims) => Ok(Caller { subject: claims.sub, scopes: claims.role.scopes(), authenticated: true }),
+            Err(e) => Err(ApiError::new(ErrorCode::Unauthorized, format!("Invalid session token: {e}"))),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: RequiredScope + Send + Sync> FromRequestParts<AppState> for Authorized<S> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        Caller::from_request_parts(parts, state).await?.authorize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    const SECRET: &[u8] = b"test secret";
+
+    fn sign(role: Role, exp: u64) -> String {
+        let claims = Claims { sub: "someone@example.com".into(), role, exp };
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    fn in_one_hour() -> u64 {
+        jsonwebtoken::get_current_timestamp() + 3600
+    }
+
+    #[test]
+    fn valid_session() {
+        let token = sign(Role::Admin, in_one_hour());
+        let claims = decode_session(&token, &DecodingKey::from_secret(SECRET)).unwrap();
+        assert_eq!(claims.sub, "someone@example.com");
+        assert_eq!(claims.role, Role::Admin);
+    }
+
+    #[test]
+    fn expired_session() {
+        let token = sign(Role::Admin, 1);
+        assert!(decode_session(&token, &DecodingKey::from_secret(SECRET)).is_err());
+    }
+
+    #[test]
+    fn wrong_secret() {
+        let token = sign(Role::User, in_one_hour());
+        assert!(decode_session(&token, &DecodingKey::from_secret(b"other secret")).is_err());
+    }
+
+    #[test]
+    fn role_scopes() {
+        assert!(Role::Admin.scopes().contains(&Scope::BackupRestore));
+        assert!(Role::User.scopes().contains(&Scope::NovelsRead));
+        assert!(!Role::User.scopes().contains(&Scope::NovelsWrite));
+    }
+
+    #[test]
+    fn convert_scope() {
+        assert_eq!(Scope::NovelsWrite.to_string(), "novels:write");
+        assert_eq!(Scope::from_str("backup:restore").unwrap(), Scope::BackupRestore);
+        assert_eq!(serde_json::to_value(Scope::StatsRead).unwrap(), "stats:read");
+        assert!(Scope::from_str("novels").is_err());
+    }
+
+    #[test]
+    fn generated_tokens() {
+        let token = generate_token();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + TOKEN_LENGTH);
+        assert_ne!(token, generate_token());
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), token);
+    }
+}
@@ -0,0 +1,481 @@
+pub mod schedule;
+
+use crate::novel_entry::{self, NovelEntry};
+use crate::validation::{self, RowError};
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use anyhow::{Error, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/*
+Backups are wrapped in an envelope so their shape can keep changing without breaking old exports.
+Bare JSON arrays are treated as version 0; these come from before the envelope existed and may be in any older shape.
+Whenever `NovelEntry` changes shape, bump `SCHEMA_VERSION` and add an upgrader from the previous version to `UPGRADERS`.
+*/
+pub const SCHEMA_VERSION: u32 = 1;
+
+// UPGRADERS[v] upgrades a backup from version v to version v + 1
+const UPGRADERS: [fn(Value) -> Result<Value>; SCHEMA_VERSION as usize] = [
+    upgrade_v0_to_v1,
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub schema_version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub app_version: String,
+    pub novels: Vec<NovelEntry>,
+}
+
+impl Backup {
+    pub fn new(novels: Vec<NovelEntry>) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            exported_at: novel_entry::now(),
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            novels,
+        }
+    }
+}
+
+// parses a backup of any known version, upgrading it to the current shape
+pub fn parse_backup(bytes: &[u8]) -> Result<Backup> {
+    parse_backup_value(serde_json::from_slice(bytes)?)
+}
+
+pub fn parse_backup_value(mut value: Value) -> Result<Backup> {
+    let mut version = match &value {
+        Value::Array(_) => 0,
+        Value::Object(obj) => obj.get("schema_version")
+            .and_then(Value::as_u64)
+            .ok_or(Error::msg("Backup is missing a valid schema_version"))?
+            .try_into()?,
+        _ => return Err(Error::msg("Backup must be a JSON object or array")),
+    };
+    if version > SCHEMA_VERSION {
+        return Err(Error::msg(format!("Backup schema version {version} is newer than the supported version {SCHEMA_VERSION}")));
+    }
+
+    while version < SCHEMA_VERSION {
+        value = UPGRADERS[version as usize](value)?;
+        version += 1;
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/*
+Version 0 backups are bare arrays of novels. Depending on when they were exported, rows may:
+* have no id (novels used to be keyed by title)
+* store the chapter as a number and tags as a comma separated string
+* have nulls in fields that are no longer optional
+* use statuses and providers that have since been renamed
+* be missing the provider and start/completed date fields
+*/
+fn upgrade_v0_to_v1(value: Value) -> Result<Value> {
+    let Value::Array(rows) = value else {
+        return Err(Error::msg("Version 0 backups must be a JSON array"));
+    };
+
+    let mut next_id = rows.iter()
+        .filter_map(|row| row.get("id").and_then(Value::as_i64))
+        .max()
+        .unwrap_or(0) + 1;
+
+    let mut novels = Vec::new();
+    for row in rows {
+        let Value::Object(mut row) = row else {
+            return Err(Error::msg("Every novel in a backup must be a JSON object"));
+        };
+
+        if row.get("id").is_none_or(Value::is_null) {
+            row.insert("id".into(), json!(next_id));
+            next_id += 1;
+        }
+        for field in ["country", "title", "notes"] {
+            if row.get(field).is_none_or(Value::is_null) {
+                row.insert(field.into(), json!(""));
+            }
+        }
+        let chapter = match row.remove("chapter") {
+            Some(Value::Number(chapter)) => chapter.to_string(),
+            Some(Value::String(chapter)) => chapter,
+            _ => String::new(),
+        };
+        row.insert("chapter".into(), json!(chapter));
+
+        let rating = row.get("rating").and_then(Value::as_u64).unwrap_or(0);
+        row.insert("rating".into(), json!(rating));
+
+        let tags = match row.remove("tags") {
+            Some(Value::String(tags)) => json!(NovelEntry::parse_tags(&tags)),
+            Some(tags @ Value::Array(_)) => tags,
+            _ => json!([]),
+        };
+        row.insert("tags".into(), tags);
+
+        // see m20241227_054323_fix_status and m20241227_065306_rename_providers
+        let status = match row.remove("status") {
+            Some(Value::String(status)) if status != "Invalid" => json!(status),
+            _ => Value::Null,
+        };
+        row.insert("status".into(), status);
+        let provider = match row.remove("provider") {
+            Some(Value::String(provider)) => match provider.as_str() {
+                "Novelupdates" => json!("NovelUpdates"),
+                "Royalroad" => json!("RoyalRoad"),
+                _ => json!(provider),
+            },
+            _ => Value::Null,
+        };
+        row.insert("provider".into(), provider);
+
+        // dates used to be stored without a timezone
+        for field in ["date_modified", "date_started", "date_completed"] {
+            let date = row.remove(field).map(upgrade_date).transpose()?.flatten();
+            row.insert(field.into(), json!(date));
+        }
+        if row.get("date_modified").is_none_or(Value::is_null) {
+            row.insert("date_modified".into(), json!(novel_entry::now()));
+        }
+
+        novels.push(Value::Object(row));
+    }
+
+    Ok(json!({
+        "schema_version": 1,
+        "exported_at": novel_entry::now(),
+        "app_version": "unknown",
+        "novels": novels,
+    }))
+}
+
+fn upgrade_date(value: Value) -> Result<Option<DateTime<Utc>>> {
+    let Value::String(date) = value else {
+        return Ok(None);
+    };
+    if let Ok(date) = date.parse::<DateTime<Utc>>() {
+        return Ok(Some(date));
+    }
+    let naive = date.parse::<NaiveDateTime>()
+        .map_err(|e| Error::msg(format!("Invalid date in backup [{date}]: {e}")))?;
+    Ok(Some(naive.and_utc()))
+}
+
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestoreMode {
+    // the novel list becomes exactly the backup; novels missing from it go to the trash
+    #[default]
+    Replace,
+    // backup rows overwrite novels with the same id; everything else is kept
+    MergeById,
+    // backup rows overwrite novels with the same title; everything else is kept
+    MergeByTitle,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestoreOptions {
+    pub mode: RestoreMode,
+    pub dry_run: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RestoreConflict {
+    pub id: i32,
+    pub title: String,
+    pub reason: String,
+}
+
+// what a restore would do (or did) to the novel list
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub mode: RestoreMode,
+    pub dry_run: bool,
+    pub added: Vec<NovelEntry>,
+    // added rows whose ids belong to trashed novels, which are taken out of the trash and overwritten
+    #[serde(default)]
+    pub restored: Vec<NovelEntry>,
+    pub updated: Vec<NovelEntry>,
+    pub removed: Vec<NovelEntry>,
+    pub unchanged: usize,
+    pub conflicting: Vec<RestoreConflict>,
+    // added or updated rows that break the validation rules
+    pub invalid: Vec<RowError>,
+}
+
+impl RestoreReport {
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicting.is_empty()
+    }
+
+    pub fn has_invalid_rows(&self) -> bool {
+        !self.invalid.is_empty()
+    }
+
+    pub fn summary(&self) -> String {
+        format!("added {}, restored {}, updated {}, removed {}, unchanged {}, conflicting {}, invalid {}",
+            self.added.len(), self.restored.len(), self.updated.len(), self.removed.len(), self.unchanged, self.conflicting.len(), self.invalid.len())
+    }
+}
+
+// whether a restore goes ahead when some of its rows break the validation rules
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AllowInvalidRows {
+    True,
+    False,
+}
+
+// returned when a restore's plan has conflicts or invalid rows it can't apply; nothing was written
+#[derive(Debug)]
+pub struct RestoreRejected {
+    pub report: RestoreReport,
+}
+
+impl fmt::Display for RestoreRejected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.report.has_conflicts() {
+            write!(f, "The backup conflicts with the current novels; nothing was restored")
+        } else {
+            write!(f, "The backup has invalid novels; nothing was restored")
+        }
+    }
+}
+
+impl std::error::Error for RestoreRejected {}
+
+// diffs the backup against the current novels without touching the database
+pub fn plan_restore(existing: &[NovelEntry], backup: Vec<NovelEntry>, options: &RestoreOptions) -> RestoreReport {
+    let mut report = RestoreReport {
+        mode: options.mode,
+        dry_run: options.dry_run,
+        ..Default::default()
+    };
+
+    // rows that share an id within the backup can never be inserted together
+    let mut seen_ids = HashSet::new();
+    let mut seen_titles = HashSet::new();
+    let mut rows = Vec::new();
+    for row in backup {
+        if !seen_ids.insert(row.id) {
+            report.conflicting.push(conflict(&row, "Duplicate id in backup"));
+            continue;
+        }
+        if options.mode == RestoreMode::MergeByTitle && !seen_titles.insert(row.title.clone()) {
+            report.conflicting.push(conflict(&row, "Duplicate title in backup"));
+            continue;
+        }
+        rows.push(row);
+    }
+
+    let by_id: HashMap<i32, &NovelEntry> = existing.iter().map(|novel| (novel.id, novel)).collect();
+    let mut by_title: HashMap<&str, Vec<&NovelEntry>> = HashMap::new();
+    for novel in existing {
+        by_title.entry(novel.title.as_str()).or_default().push(novel);
+    }
+
+    for mut row in rows {
+        let current = match options.mode {
+            RestoreMode::Replace | RestoreMode::MergeById => by_id.get(&row.id).copied(),
+            RestoreMode::MergeByTitle => match by_title.get(row.title.as_str()).map(Vec::as_slice) {
+                Some([novel]) => {
+                    // keep the id already in the database
+                    row.id = novel.id;
+                    Some(*novel)
+                },
+                Some(_) => {
+                    report.conflicting.push(conflict(&row, "Title matches multiple existing novels"));
+                    continue;
+                },
+                None => {
+                    if by_id.contains_key(&row.id) {
+                        report.conflicting.push(conflict(&row, "Id is already used by a novel with a different title"));
+                        continue;
+                    }
+                    None
+                },
+            },
+        };
+
+        match current {
+            Some(current) if *current == row => report.unchanged += 1,
+            Some(_) => report.updated.push(row),
+            None => report.added.push(row),
+        }
+    }
+
+    if options.mode == RestoreMode::Replace {
+        report.removed = existing.iter()
+            .filter(|novel| !seen_ids.contains(&novel.id))
+            .cloned()
+            .collect();
+    }
+
+    // check the written rows against the novels that will still be there afterwards
+    let changed = report.added.iter().chain(&report.updated).cloned().collect::<Vec<_>>();
+    let before = existing.iter().map(|novel| (novel.id, novel.clone())).collect();
+    let remaining = existing.iter()
+        .filter(|novel| !report.removed.iter().any(|removed| removed.id == novel.id))
+        .cloned()
+        .collect::<Vec<_>>();
+    if let Err(invalid) = validation::validate_novels(&changed, &before, &remaining) {
+        report.invalid = invalid.rows;
+    }
+
+    report
+}
+
+// the plan only sees live novels; added rows that match a trashed novel the way the mode matches rows take it out of the trash
+pub fn plan_trash_restore(report: &mut RestoreReport, trashed: &[(i32, String)]) {
+    let trashed_ids: HashSet<i32> = trashed.iter().map(|(id, _)| *id).collect();
+    let mut by_title: HashMap<String, Vec<i32>> = HashMap::new();
+    for (id, title) in trashed {
+        by_title.entry(validation::title_key(title)).or_default().push(*id);
+    }
+
+    let mut added = Vec::new();
+    for mut row in std::mem::take(&mut report.added) {
+        match report.mode {
+            RestoreMode::Replace | RestoreMode::MergeById if trashed_ids.contains(&row.id) => report.restored.push(row),
+            RestoreMode::Replace | RestoreMode::MergeById => added.push(row),
+            RestoreMode::MergeByTitle => match by_title.get(&validation::title_key(&row.title)).map(Vec::as_slice) {
+                Some(&[id]) => {
+                    row.id = id;
+                    report.restored.push(row);
+                },
+                Some(_) => report.conflicting.push(conflict(&row, "Title matches multiple trashed novels")),
+                None if trashed_ids.contains(&row.id) => report.conflicting.push(conflict(&row, "Id is already used by a trashed novel with a different title")),
+                None => added.push(row),
+            },
+        }
+    }
+    report.added = added;
+}
+
+fn conflict(row: &NovelEntry, reason: &str) -> RestoreConflict {
+    RestoreConflict {
+        id: row.id,
+        title: row.title.clone(),
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn novel(id: i32, title: &str) -> NovelEntry {
+        NovelEntry {
+            title: title.to_string(),
+            ..NovelEntry::empty(id)
+        }
+    }
+
+    fn options(mode: RestoreMode) -> RestoreOptions {
+        RestoreOptions { mode, dry_run: true }
+    }
+
+    fn ids(novels: &[NovelEntry]) -> Vec<i32> {
+        novels.iter().map(|novel| novel.id).collect()
+    }
+
+    #[test]
+    fn current_backup_round_trip() {
+        let backup = Backup::new(vec![novel(1, "One"), novel(2, "Two")]);
+        let bytes = serde_json::to_vec(&backup).unwrap();
+        let parsed = parse_backup(&bytes).unwrap();
+        assert_eq!(parsed.schema_version, SCHEMA_VERSION);
+        assert_eq!(parsed.novels, backup.novels);
+    }
+
+    #[test]
+    fn bare_array_backup() {
+        let novels = vec![novel(1, "One"), novel(2, "Two")];
+        let bytes = serde_json::to_vec(&novels).unwrap();
+        let parsed = parse_backup(&bytes).unwrap();
+        assert_eq!(parsed.novels, novels);
+    }
+
+    #[test]
+    fn oldest_backup_shape() {
+        let bytes = br#"[
+            {"title": "One", "country": null, "chapter": 12, "rating": null, "status": "Invalid", "tags": "Action,Fantasy", "notes": null, "date_modified": "2024-06-20T07:40:48.123"},
+            {"id": 7, "title": "Two", "country": "KR", "chapter": "v2c3", "rating": 8, "status": "Reading", "tags": ["Magic"], "notes": "", "provider": "Royalroad", "date_modified": "2024-12-27T01:44:25Z", "date_started": null}
+        ]"#;
+        let parsed = parse_backup(bytes).unwrap();
+        let [one, two] = parsed.novels.as_slice() else {
+            panic!("expected two novels");
+        };
+
+        assert_eq!(one.id, 8);
+        assert_eq!(one.chapter.count_chapters(), 12);
+        assert_eq!(one.status, None);
+        assert_eq!(one.tags, ["Action", "Fantasy"]);
+        assert_eq!(one.date_modified.to_rfc3339(), "2024-06-20T07:40:48.123+00:00");
+
+        assert_eq!(two.id, 7);
+        assert_eq!(two.provider, Some(novel_entry::Provider::RoyalRoad));
+        assert_eq!(two.date_completed, None);
+    }
+
+    #[test]
+    fn newer_backup_rejected() {
+        let bytes = format!(r#"{{"schema_version": {}, "novels": []}}"#, SCHEMA_VERSION + 1);
+        assert!(parse_backup(bytes.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn replace() {
+        let existing = [novel(1, "One"), novel(2, "Two"), novel(3, "Three")];
+        let mut changed = existing[1].clone();
+        changed.rating = 8;
+        let backup = vec![existing[0].clone(), changed, novel(4, "Four")];
+
+        let report = plan_restore(&existing, backup, &options(RestoreMode::Replace));
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(ids(&report.updated), [2]);
+        assert_eq!(ids(&report.added), [4]);
+        assert_eq!(ids(&report.removed), [3]);
+        assert!(!report.has_conflicts());
+    }
+
+    #[test]
+    fn merge_by_id_keeps_rows() {
+        let existing = [novel(1, "One"), novel(2, "Two")];
+        let backup = vec![novel(2, "Renamed"), novel(5, "Five")];
+
+        let report = plan_restore(&existing, backup, &options(RestoreMode::MergeById));
+        assert_eq!(ids(&report.updated), [2]);
+        assert_eq!(ids(&report.added), [5]);
+        assert!(report.removed.is_empty());
+    }
+
+    #[test]
+    fn merge_by_title_uses_existing_ids() {
+        let existing = [novel(1, "One"), novel(2, "Two")];
+        let mut changed = novel(10, "Two");
+        changed.notes = "new notes".into();
+        let backup = vec![changed, novel(11, "Eleven"), novel(1, "Not One")];
+
+        let report = plan_restore(&existing, backup, &options(RestoreMode::MergeByTitle));
+        assert_eq!(ids(&report.updated), [2]);
+        assert_eq!(ids(&report.added), [11]);
+        assert_eq!(report.conflicting.len(), 1);
+        assert_eq!(report.conflicting[0].title, "Not One");
+    }
+
+    #[test]
+    fn duplicate_backup_ids() {
+        let backup = vec![novel(1, "One"), novel(1, "Also One")];
+        let report = plan_restore(&[], backup, &options(RestoreMode::Replace));
+        assert_eq!(ids(&report.added), [1]);
+        assert!(report.has_conflicts());
+    }
+}
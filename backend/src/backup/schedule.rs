@@ -0,0 +1,230 @@
+use super::Backup;
+use crate::config::BackupConfig;
+use crate::db;
+
+use std::{collections::HashSet, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use futures::TryStreamExt;
+use object_store::{aws::AmazonS3Builder, local::LocalFileSystem, path::Path, prefix::PrefixStore, ObjectStore};
+use sea_orm::DatabaseConnection;
+use tokio::time::{interval_at, Instant};
+
+/*
+Scheduled backups export the novel list in the same format as `/api/novels_backup`.
+Destinations are either a local directory or an S3 compatible bucket (`s3://bucket/optional/prefix`).
+S3 credentials and endpoints are read from the usual AWS_* variables, so MinIO works by setting
+AWS_ENDPOINT and AWS_ALLOW_HTTP.
+*/
+const FILE_PREFIX: &str = "novels_";
+const FILE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+#[derive(Clone, Debug)]
+pub struct BackupSettings {
+    pub destination: String,
+    pub interval: Duration,
+    pub keep_daily: usize,
+    pub keep_weekly: usize,
+}
+
+impl BackupSettings {
+    // scheduled backups are disabled unless a destination is configured
+    pub fn from_config(config: &BackupConfig) -> Option<Self> {
+        config.destination.clone().map(|destination| Self::with_destination(destination, config))
+    }
+
+    pub fn with_destination(destination: String, config: &BackupConfig) -> Self {
+        Self {
+            destination,
+            interval: Duration::from_secs(config.interval_hours * 60 * 60),
+            keep_daily: config.keep_daily,
+            keep_weekly: config.keep_weekly,
+        }
+    }
+}
+
+pub fn open_destination(destination: &str) -> Result<Arc<dyn ObjectStore>> {
+    if let Some(location) = destination.strip_prefix("s3://") {
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        let store = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .build()?;
+        if prefix.is_empty() {
+            return Ok(Arc::new(store));
+        }
+        return Ok(Arc::new(PrefixStore::new(store, prefix)));
+    }
+
+    let dir = PathBuf::from(destination.strip_prefix("file://").unwrap_or(destination));
+    std::fs::create_dir_all(&dir)?;
+    Ok(Arc::new(LocalFileSystem::new_with_prefix(dir)?))
+}
+
+// runs forever, backing up once per interval; failures are logged and retried on the next tick
+pub async fn run_schedule(conn: DatabaseConnection, settings: BackupSettings) {
+    let store = match open_destination(&settings.destination) {
+        Ok(store) => store,
+        Err(e) => {
+            tracing::warn!("Scheduled backups disabled; invalid destination [{}]: {e}", settings.destination);
+            return;
+        }
+    };
+    tracing::info!("Backing up to [{}] every {:?}", settings.destination, settings.interval);
+
+    let mut interval = interval_at(Instant::now() + settings.interval, settings.interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = run_backup(&conn, store.as_ref(), &settings).await {
+            tracing::warn!("Scheduled backup failed: {e}");
+        }
+    }
+}
+
+// exports one backup and prunes old ones; returns the name of the new backup
+pub async fn run_backup(conn: &DatabaseConnection, store: &dyn ObjectStore, settings: &BackupSettings) -> Result<String> {
+    let novels = db::fetch_novel_entries(conn).await?;
+    let backup = Backup::new(novels);
+    let name = write_backup(store, &backup).await?;
+    let pruned = prune_backups(store, settings.keep_daily, settings.keep_weekly).await?;
+    tracing::info!("Backed up {} novels to {name}; pruned {pruned} old backups", backup.novels.len());
+    Ok(name)
+}
+
+pub async fn write_backup(store: &dyn ObjectStore, backup: &Backup) -> Result<String> {
+    let name = format!("{FILE_PREFIX}{}.json", backup.exported_at.format(FILE_TIME_FORMAT));
+    store.put(&Path::from(name.as_str()), serde_json::to_vec(backup)?.into()).await?;
+    Ok(name)
+}
+
+pub async fn prune_backups(store: &dyn ObjectStore, keep_daily: usize, keep_weekly: usize) -> Result<usize> {
+    // anything that doesn't look like one of our backups is left alone
+    let backups: Vec<(Path, DateTime<Utc>)> = store.list(None)
+        .try_filter_map(|meta| async move {
+            let time = meta.location.filename().and_then(parse_backup_time);
+            Ok(time.map(|time| (meta.location, time)))
+        })
+        .try_collect()
+        .await?;
+
+    let times = backups.iter().map(|(_, time)| *time).collect::<Vec<_>>();
+    let keep = backups_to_keep(&times, keep_daily, keep_weekly);
+    let mut pruned = 0;
+    for (idx, (location, _)) in backups.iter().enumerate() {
+        if !keep.contains(&idx) {
+            store.delete(location).await?;
+            pruned += 1;
+        }
+    }
+    Ok(pruned)
+}
+
+fn parse_backup_time(filename: &str) -> Option<DateTime<Utc>> {
+    let time = filename.strip_prefix(FILE_PREFIX)?.strip_suffix(".json")?;
+    NaiveDateTime::parse_from_str(time, FILE_TIME_FORMAT).ok().map(|time| time.and_utc())
+}
+
+// keeps the newest backup from each of the last `keep_daily` days and the last `keep_weekly` iso weeks
+// the newest backup overall is always kept
+fn backups_to_keep(times: &[DateTime<Utc>], keep_daily: usize, keep_weekly: usize) -> HashSet<usize> {
+    let mut newest_first: Vec<usize> = (0..times.len()).collect();
+    newest_first.sort_by_key(|&idx| std::cmp::Reverse(times[idx]));
+
+    let mut keep = HashSet::new();
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    for idx in newest_first.iter().copied() {
+        let time = times[idx];
+        if days.len() < keep_daily && days.insert(time.date_naive()) {
+            keep.insert(idx);
+        }
+        let week = time.iso_week();
+        if weeks.len() < keep_weekly && weeks.insert((week.year(), week.week())) {
+            keep.insert(idx);
+        }
+    }
+    if let Some(&newest) = newest_first.first() {
+        keep.insert(newest);
+    }
+    keep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::novel_entry::NovelEntry;
+    use chrono::TimeZone;
+    use dotenv::dotenv;
+    use object_store::memory::InMemory;
+    use std::env;
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, day, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn backup_names() {
+        let time = at(18, 3);
+        let name = format!("{FILE_PREFIX}{}.json", time.format(FILE_TIME_FORMAT));
+        assert_eq!(name, "novels_20261018T030000Z.json");
+        assert_eq!(parse_backup_time(&name), Some(time));
+        assert_eq!(parse_backup_time("notes.txt"), None);
+    }
+
+    #[test]
+    fn keep_daily() {
+        // two backups a day for four days
+        let times = [at(15, 1), at(15, 13), at(16, 1), at(16, 13), at(17, 1), at(17, 13), at(18, 1), at(18, 13)];
+        let keep = backups_to_keep(&times, 2, 0);
+        assert_eq!(keep, HashSet::from([7, 5]));
+    }
+
+    #[test]
+    fn keep_weekly() {
+        // 2026-10-04 is a sunday, so these span three iso weeks
+        let times = [at(4, 0), at(5, 0), at(11, 0), at(12, 0), at(18, 0)];
+        let keep = backups_to_keep(&times, 1, 3);
+        assert_eq!(keep, HashSet::from([4, 2, 0]));
+    }
+
+    #[test]
+    fn always_keep_newest() {
+        let times = [at(1, 0), at(2, 0)];
+        assert_eq!(backups_to_keep(&times, 0, 0), HashSet::from([1]));
+        assert!(backups_to_keep(&[], 3, 3).is_empty());
+    }
+
+    #[tokio::test]
+    async fn write_and_prune() {
+        let store = InMemory::new();
+        for day in [15, 16, 17] {
+            let backup = Backup { exported_at: at(day, 0), ..Backup::new(vec![NovelEntry::empty(day as i32)]) };
+            write_backup(&store, &backup).await.unwrap();
+        }
+        store.put(&Path::from("unrelated.txt"), b"keep me".to_vec().into()).await.unwrap();
+
+        assert_eq!(prune_backups(&store, 2, 0).await.unwrap(), 1);
+        let remaining: Vec<_> = store.list(None).map_ok(|meta| meta.location.to_string()).try_collect().await.unwrap();
+        assert_eq!(remaining.len(), 3);
+        assert!(!remaining.contains(&"novels_20261015T000000Z.json".to_string()));
+    }
+
+    #[tokio::test]
+    async fn local_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = open_destination(dir.path().join("backups").to_str().unwrap()).unwrap();
+        let name = write_backup(store.as_ref(), &Backup::new(Vec::new())).await.unwrap();
+        assert!(dir.path().join("backups").join(name).exists());
+    }
+
+    // e.g. AWS_ENDPOINT=http://localhost:9000 AWS_ALLOW_HTTP=true BACKUP_TEST_S3_DESTINATION=s3://backups/test
+    #[tokio::test]
+    #[ignore = "requires MinIO setup"]
+    async fn s3_destination() {
+        dotenv().ok();
+        let store = open_destination(&env::var("BACKUP_TEST_S3_DESTINATION").unwrap()).unwrap();
+        let name = write_backup(store.as_ref(), &Backup::new(Vec::new())).await.unwrap();
+        store.head(&Path::from(name.as_str())).await.unwrap();
+        store.delete(&Path::from(name.as_str())).await.unwrap();
+    }
+}
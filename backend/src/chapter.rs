@@ -92,6 +92,20 @@ impl Chapter {
         }
     }
 
+    // the chapter after this one in the same volume; parts are dropped since a new chapter starts at the beginning
+    // chapters that aren't numbered, like "epilogue", have no next chapter
+    pub fn next_chapter(&self) -> Option<Chapter> {
+        match self {
+            Chapter::Standard { volume, chapter, .. } => {
+                let volume = volume.map(|volume| format!("v{volume}")).unwrap_or_default();
+                let chapter = chapter.map_or(1, |chapter| chapter + 1);
+                Some(Chapter::from(&format!("{volume}c{chapter}")))
+            },
+            Chapter::Other { value } if value.is_empty() => Some(Chapter::from("c1")),
+            Chapter::Other { .. } => None,
+        }
+    }
+
     pub fn unstarted(&self) -> bool {
         match self {
             Chapter::Standard { volume, chapter, part, .. } => {
@@ -152,6 +166,16 @@ impl<'de> Deserialize<'de> for Chapter {
 mod tests {
     use super::Chapter;
 
+    #[test]
+    fn next_chapter() {
+        let next = |raw: &str| Chapter::from(raw).next_chapter().map(|chapter| chapter.to_string());
+        assert_eq!(next("10").as_deref(), Some("c11"));
+        assert_eq!(next("v2c31p3").as_deref(), Some("v2c32"));
+        assert_eq!(next("v3").as_deref(), Some("v3c1"));
+        assert_eq!(next("").as_deref(), Some("c1"));
+        assert_eq!(next("epilogue"), None);
+    }
+
     #[test]
     fn empty() {
         let raw = "";
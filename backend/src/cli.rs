@@ -1,56 +1,885 @@
+mod render;
+
+use crate::audit::{self, AuditContext, AuditSource};
+use crate::auth::{self, Scope};
+use crate::backup::{self, schedule::{self, BackupSettings}, AllowInvalidRows, Backup, RestoreMode, RestoreOptions, RestoreRejected, RestoreReport};
+use crate::chapter::Chapter;
+use crate::config::Config;
 use crate::db;
+use crate::doctor;
+use crate::logging::{self, LogFormat, LogLevel};
+use crate::migrate::{self, AutoMigrate, PendingMigrations};
+use crate::novel_entry::{NovelPatch, NovelQuery, NovelSort, NovelSubsets, Provider, SortDirection, Status};
+use crate::snapshot;
+use crate::stats;
+use crate::tui;
 use crate::data_ingestion;
 
-use std::path::PathBuf;
+use std::{fmt, io::Write, path::PathBuf, process::ExitCode, sync::Arc};
 
-use anyhow::Result;
-use clap::{ArgAction, Parser, Subcommand};
+use anyhow::{Error, Result};
+use chrono::{TimeDelta, Utc};
+use clap::{ArgAction, Args, Parser, Subcommand};
+use itertools::Itertools;
+use migration::MigrationStatus;
 use sea_orm::DatabaseConnection;
+use serde::Serialize;
+use serde_json::json;
+
+/*
+`serve` runs the HTTP server; every other command does its job and exits.
+Results go to stdout, as text or as JSON with `--json`, and logs go to stderr.
+Exit codes: 0 on success, 1 when a command fails, 2 for invalid arguments (from clap),
+3 when the database has pending migrations, and 4 when a command ran but found problems it didn't resolve,
+like `novels doctor` without `--fix` or a backup with conflicts.
+*/
+const EXIT_FAILURE: u8 = 1;
+const EXIT_PENDING_MIGRATIONS: u8 = 3;
+const EXIT_PROBLEMS_FOUND: u8 = 4;
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
-    manage_novels: Option<ManageNovels>
+    command: Command,
+
+    /// Reads the config from this TOML file instead of CONFIG_PATH or config.toml
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// How much to log to stderr
+    #[clap(long, global = true, value_enum, default_value_t)]
+    log_level: LogLevel,
+
+    /// Writes logs for people (human) or for log collectors (json)
+    #[clap(long, global = true, value_enum, default_value_t)]
+    log_format: LogFormat,
+
+    /// Prints results and errors as JSON for scripting
+    #[clap(long, global = true, action=ArgAction::SetTrue)]
+    json: bool,
+
+    /// Applies pending migrations instead of refusing to start
+    #[clap(long, global = true, action=ArgAction::SetTrue)]
+    auto_migrate: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Runs the HTTP server
+    Serve,
+
+    /// Opens a full-screen terminal interface for browsing and editing the novel list
+    Tui,
+
+    /// Applies, rolls back or lists the database migrations built into this binary
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+
+    /// Manages the novels in the database
+    Novels {
+        #[command(subcommand)]
+        command: NovelsCommand,
+    },
+
+    /// Fetches novel tags from supported websites
+    Scrape {
+        #[command(subcommand)]
+        command: ScrapeCommand,
+    },
+
+    /// Imports novels or tags from files
+    Import {
+        #[command(subcommand)]
+        command: ImportCommand,
+    },
+
+    /// Exports the novel list to a file or a backup destination
+    Backup {
+        #[command(subcommand)]
+        command: BackupCommand,
+    },
+
+    /// Lists, compares and restores snapshots of the novel table
+    Snapshots {
+        #[command(subcommand)]
+        command: SnapshotsCommand,
+    },
+
+    /// Manages personal access tokens for scripting against the API
+    Tokens {
+        #[command(subcommand)]
+        command: TokensCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum MigrateCommand {
+    /// Applies pending migrations, all of them by default
+    Up {
+        #[clap(long, short)]
+        steps: Option<u32>,
+    },
+
+    /// Rolls back the newest applied migrations
+    Down {
+        #[clap(long, short, default_value_t = 1)]
+        steps: u32,
+    },
+
+    /// Lists every migration and whether it has been applied
+    Status,
+}
+
+#[derive(Debug, Subcommand)]
+enum NovelsCommand {
+    /// Lists a page of novels, with the same filters as `GET /api/novels`
+    List(ListArgs),
+
+    /// Shows every field of one novel
+    Show {
+        /// The novel's id or exact title
+        novel: String,
+    },
+
+    /// Adds a novel; a novel added without a title is named `Untitled <id>`
+    Add(NovelArgs),
+
+    /// Changes some of a novel's fields and leaves the rest alone
+    Edit {
+        id: i32,
+        #[command(flatten)]
+        fields: NovelArgs,
+        /// Adds a tag; can be repeated
+        #[clap(long = "tag+", value_name = "TAG")]
+        add_tags: Vec<String>,
+        /// Removes a tag; can be repeated
+        #[clap(long = "tag-", value_name = "TAG")]
+        remove_tags: Vec<String>,
+    },
+
+    /// Moves a novel to the trash
+    Delete {
+        id: i32
+    },
+
+    /// Shows the same statistics as `GET /api/novels_stats`
+    Stats,
+
+    /// Drops everything currently in the novel table
+    DropAll,
+
+    /// Lists novels in the trash, most recently trashed first
+    ListTrash,
+
+    /// Takes a novel back out of the trash
+    RestoreTrashed {
+        id: i32
+    },
+
+    /// Permanently deletes novels that have been in the trash for too long
+    PurgeTrash {
+        #[clap(long, default_value_t = 30)]
+        older_than_days: i64,
+    },
+
+    /// Shows every recorded change to a novel, newest first
+    History {
+        id: i32
+    },
+
+    /// Reverts a novel to how it was right after one of its history entries
+    Revert {
+        id: i32,
+        entry: i32,
+    },
+
+    /// Checks every novel for values the backend can't read or that break validation, like duplicate titles;
+    /// runs before pending migrations are applied, to repair values that keep them from applying
+    Doctor {
+        /// Repairs every problem found
+        #[clap(long, action=ArgAction::SetTrue)]
+        fix: bool,
+    },
+}
+
+// the filters of `NovelQuery`, with tags given one flag at a time instead of comma separated
+#[derive(Debug, Args)]
+struct ListArgs {
+    /// Pages start at 0; ignored when a cursor is given
+    #[clap(long, default_value_t = 0)]
+    page: u64,
+    #[clap(long)]
+    limit: Option<u64>,
+    /// The id of the last novel on the previous page; only works when sorting by id
+    #[clap(long)]
+    cursor: Option<i32>,
+    #[clap(long, value_enum, default_value_t)]
+    sort: NovelSort,
+    #[clap(long, value_enum, default_value_t)]
+    direction: SortDirection,
+    /// `not-sus` hides the novels the public can't see
+    #[clap(long, value_enum)]
+    subset: Option<NovelSubsets>,
+    #[clap(long)]
+    status: Option<Status>,
+    #[clap(long)]
+    provider: Option<Provider>,
+    #[clap(long)]
+    country: Option<String>,
+    #[clap(long)]
+    min_rating: Option<u32>,
+    #[clap(long)]
+    max_rating: Option<u32>,
+    /// Only lists novels with this tag; can be repeated
+    #[clap(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Skips novels with this tag; can be repeated
+    #[clap(long = "exclude-tag", value_name = "TAG")]
+    exclude_tags: Vec<String>,
+    /// Only lists novels whose title contains this, ignoring case
+    #[clap(long)]
+    title: Option<String>,
+}
+
+impl ListArgs {
+    fn query(self) -> NovelQuery {
+        let join = |tags: Vec<String>| (!tags.is_empty()).then(|| tags.join(","));
+        NovelQuery {
+            page: self.page,
+            limit: self.limit,
+            cursor: self.cursor,
+            sort: self.sort,
+            direction: self.direction,
+            subset: self.subset,
+            status: self.status,
+            provider: self.provider,
+            country: self.country,
+            min_rating: self.min_rating,
+            max_rating: self.max_rating,
+            tags: join(self.tags),
+            exclude_tags: join(self.exclude_tags),
+            title: self.title,
+        }
+    }
+}
+
+// novel fields for `add` and `edit`; fields that aren't passed are left alone
+#[derive(Debug, Args)]
+struct NovelArgs {
+    #[clap(long)]
+    title: Option<String>,
+    #[clap(long)]
+    country: Option<String>,
+    /// Ex: v2c31, c120 or any other text
+    #[clap(long)]
+    chapter: Option<String>,
+    #[clap(long)]
+    rating: Option<u32>,
+    #[clap(long)]
+    status: Option<Status>,
+    #[clap(long)]
+    provider: Option<Provider>,
+    /// Replaces all of the novel's tags; can be repeated
+    #[clap(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    #[clap(long)]
+    notes: Option<String>,
+}
+
+impl NovelArgs {
+    fn patch(self) -> NovelPatch {
+        NovelPatch {
+            country: self.country,
+            title: self.title,
+            chapter: self.chapter.as_deref().map(Chapter::from),
+            rating: self.rating,
+            status: self.status.map(Some),
+            tags: (!self.tags.is_empty()).then_some(self.tags),
+            notes: self.notes,
+            provider: self.provider.map(Some),
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug, Subcommand)]
-enum ManageNovels {
-    /// Fetches all novel information from supported websites
-    FetchAllNovels {
+enum ScrapeCommand {
+    /// Fetches tags for every novel with a provider
+    All {
+        /// Also refetches novels that already have tags
         #[clap(long, short, action=ArgAction::SetTrue)]
         reset_novels: bool
     },
 
-    /// Manually fetches a single novel's information
-    FetchSingle {
+    /// Fetches a single novel's tags
+    Single {
         title: String,
         url: Option<String>
     },
+}
 
+#[derive(Debug, Subcommand)]
+enum ImportCommand {
     /// Imports novel tags and genres from a csv file (see <https://github.com/shaido987/novel-dataset>)
-    ImportCsv {
+    Csv {
         file: PathBuf
     },
 
-    /// Drops everything currently in the novel table
-    DropAllNovels,
+    /// Restores novels from a JSON backup file; older backup formats are upgraded automatically
+    Backup {
+        file: PathBuf,
+        #[clap(long, value_enum, default_value_t)]
+        mode: RestoreMode,
+        /// Only report what would change without writing anything
+        #[clap(long, action=ArgAction::SetTrue)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum BackupCommand {
+    /// Runs a scheduled backup right now, including pruning old backups
+    Run {
+        /// Overrides the configured destination (a directory or s3://bucket/prefix)
+        #[clap(long)]
+        destination: Option<String>,
+    },
+
+    /// Exports every novel to a JSON backup file
+    Export {
+        file: PathBuf
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum SnapshotsCommand {
+    /// Lists snapshots of the novel table, newest first
+    List,
+
+    /// Shows what changed between two snapshots
+    Diff {
+        from: i32,
+        to: i32,
+    },
+
+    /// Replaces the novel table with a snapshot; the current table is snapshotted first
+    Restore {
+        id: i32
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TokensCommand {
+    /// Mints a personal access token
+    Mint {
+        name: String,
+        /// Scopes granted to the token (ex: novels:read, novels:write, backup:restore, stats:read, tetris:run)
+        #[clap(long = "scope", short, required = true)]
+        scopes: Vec<Scope>,
+    },
+
+    /// Lists all personal access tokens
+    List,
+
+    /// Revokes a personal access token by id
+    Revoke {
+        id: i32
+    },
 }
 
-pub async fn run_cli(conn: &DatabaseConnection) -> Result<()> {
+// returned when a command finished but left problems for the caller; exits with EXIT_PROBLEMS_FOUND
+#[derive(Debug)]
+pub struct ProblemsFound(pub String);
+
+impl fmt::Display for ProblemsFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ProblemsFound {}
+
+// where command results go; with `--json` every command prints exactly one JSON value
+#[derive(Copy, Clone, Debug)]
+struct Output {
+    json: bool,
+}
+
+impl Output {
+    // `text` pushes the lines people see without `--json`
+    fn print<T: Serialize>(&self, value: &T, text: impl FnOnce(&T, &mut Vec<String>)) -> Result<()> {
+        let output = if self.json {
+            serde_json::to_string_pretty(value)?
+        } else {
+            let mut lines = Vec::new();
+            text(value, &mut lines);
+            if lines.is_empty() {
+                return Ok(());
+            }
+            lines.join("\n")
+        };
+
+        // piping into something like `head` closes stdout early, which isn't an error
+        match writeln!(std::io::stdout().lock(), "{output}") {
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn error(&self, e: &Error, code: u8) {
+        if self.json {
+            eprintln!("{}", json!({ "error": format!("{e:#}"), "exit_code": code }));
+        } else {
+            eprintln!("Error: {e:#}");
+        }
+    }
+}
+
+fn exit_code(e: &Error) -> u8 {
+    if e.is::<PendingMigrations>() {
+        EXIT_PENDING_MIGRATIONS
+    } else if e.is::<ProblemsFound>() {
+        EXIT_PROBLEMS_FOUND
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+pub async fn run() -> ExitCode {
     let cli = Cli::parse();
+    // the tui draws over the whole terminal, so anything logged would garble it
+    if !matches!(cli.command, Command::Tui) {
+        logging::init(cli.log_level, cli.log_format);
+    }
+    let output = Output { json: cli.json };
+    match run_command(cli, output).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let code = exit_code(&e);
+            output.error(&e, code);
+            ExitCode::from(code)
+        },
+    }
+}
+
+async fn run_command(cli: Cli, output: Output) -> Result<()> {
+    let config = Arc::new(Config::load(cli.config.as_deref())?);
+    if let Command::Serve = cli.command {
+        config.validate_server()?;
+    }
+    let conn = db::connect(&config.database_url, &config.pool).await?;
+
+    // migrations run against an out of date schema by definition
+    if let Command::Migrate { command } = cli.command {
+        return run_migrate(&conn, command, output).await;
+    }
+
+    // doctor repairs the values that keep migrations from applying, so it runs against an out of date schema; see doctor.rs
+    let auto_migrate = if cli.auto_migrate { AutoMigrate::True } else { AutoMigrate::False };
+    let doctor = matches!(&cli.command, Command::Novels { command: NovelsCommand::Doctor { .. } });
+    if !doctor || auto_migrate == AutoMigrate::True {
+        migrate::ensure_migrated(&conn, auto_migrate).await?;
+    }
+
+    match cli.command {
+        Command::Migrate { .. } => unreachable!("migrations are run above"),
+        Command::Serve => crate::serve(conn, config).await,
+        Command::Tui => tui::run(&conn).await,
+        Command::Novels { command } => run_novels(&conn, &config, command, output).await,
+        Command::Scrape { command } => run_scrape(&conn, &config, command, output).await,
+        Command::Import { command } => run_import(&conn, command, output).await,
+        Command::Backup { command } => run_backup(&conn, &config, command, output).await,
+        Command::Snapshots { command } => run_snapshots(&conn, command, output).await,
+        Command::Tokens { command } => run_tokens(&conn, command, output).await,
+    }
+}
 
-    if let Some(command) = cli.manage_novels {
-        match command {
-            ManageNovels::FetchAllNovels { reset_novels } => data_ingestion::fetch_novel_tags(conn, reset_novels).await?,
-            ManageNovels::FetchSingle { title, url } => data_ingestion::single_fetch_novel_tags(conn, &title, url).await?,
-            ManageNovels::ImportCsv { file } => {
-                let rows = data_ingestion::csv::read_novel_tags_csv(&file)?;
-                db::update_novel_tags(conn, &rows).await?;
-            },
-            ManageNovels::DropAllNovels => db::drop_all_novels(conn).await?,
+async fn run_migrate(conn: &DatabaseConnection, command: MigrateCommand, output: Output) -> Result<()> {
+    match command {
+        MigrateCommand::Up { steps } => {
+            let applied = migrate::migrate_up(conn, steps).await?;
+            output.print(&json!({ "applied": applied }), |_, out| {
+                for name in &applied {
+                    out.push(format!("Applied {name}"));
+                }
+                out.push(format!("Applied {} migrations", applied.len()));
+            })
+        },
+        MigrateCommand::Down { steps } => {
+            let rolled_back = migrate::migrate_down(conn, steps).await?;
+            output.print(&json!({ "rolled_back": rolled_back }), |_, out| {
+                for name in &rolled_back {
+                    out.push(format!("Rolled back {name}"));
+                }
+                out.push(format!("Rolled back {} migrations", rolled_back.len()));
+            })
+        },
+        MigrateCommand::Status => {
+            let migrations = migrate::migration_status(conn).await?;
+            let value = migrations.iter()
+                .map(|(name, status)| json!({ "name": name, "applied": *status == MigrationStatus::Applied }))
+                .collect_vec();
+            output.print(&value, |_, out| {
+                for (name, status) in &migrations {
+                    out.push(format!("{status}\t{name}"));
+                }
+            })
+        },
+    }
+}
+
+async fn run_novels(conn: &DatabaseConnection, config: &Config, command: NovelsCommand, output: Output) -> Result<()> {
+    let ctx = AuditContext::local(AuditSource::Cli);
+    match command {
+        NovelsCommand::List(args) => {
+            let query = args.query();
+            query.validate()?;
+            let page = db::query_novel_entries(conn, &query, &config.sus_tags).await?;
+            output.print(&page, |page, out| out.push(render::novel_page(page)))
+        },
+        NovelsCommand::Show { novel } => {
+            // titles that look like ids are found by title once no novel has that id
+            let by_id = match novel.parse() {
+                Ok(id) => db::fetch_novel_by_id(conn, id).await?,
+                Err(_) => None,
+            };
+            let novel = match by_id {
+                Some(novel) => novel,
+                None => db::fetch_single_novel(conn, &novel).await?,
+            };
+            output.print(&novel, |novel, out| out.push(render::novel(novel)))
+        },
+        NovelsCommand::Add(fields) => {
+            let novel = db::create_empty_row(conn, &fields.patch(), &ctx).await?;
+            output.print(&novel, |novel, out| out.push(format!("Added [{}] (id {})", novel.title, novel.id)))
+        },
+        NovelsCommand::Edit { id, fields, add_tags, remove_tags } => {
+            let Some(current) = db::fetch_novel_by_id(conn, id).await? else {
+                return Err(Error::msg(format!("Novel not found in db: {id}")));
+            };
+            let mut patch = fields.patch();
+            if !add_tags.is_empty() || !remove_tags.is_empty() {
+                let tags = patch.tags.take().unwrap_or_else(|| current.tags.clone());
+                patch.tags = Some(edit_tags(tags, add_tags, &remove_tags));
+            }
+
+            // conditional on the novel we just read, so an edit from the web in between isn't overwritten
+            let Some(novel) = db::patch_novel_entry(conn, id, &patch, Some(current.date_modified), &ctx).await? else {
+                return Err(Error::msg(format!("Novel not found in db: {id}")));
+            };
+            output.print(&novel, |novel, out| out.push(render::novel(novel)))
+        },
+        NovelsCommand::Delete { id } => {
+            let Some(novel) = db::fetch_novel_by_id(conn, id).await? else {
+                return Err(Error::msg(format!("Novel not found in db: {id}")));
+            };
+            db::trash_novel_entry(conn, id, &ctx).await?;
+            output.print(&json!({ "trashed": id }), |_, out| {
+                out.push(format!("Moved [{}] (id {id}) to the trash; `novels restore-trashed {id}` brings it back", novel.title));
+            })
+        },
+        NovelsCommand::Stats => {
+            output.print(&stats::get_stats(conn).await?, |stats, out| out.push(render::stats(stats)))
+        },
+        NovelsCommand::DropAll => {
+            let info = snapshot::take_snapshot(conn, "before dropping all novels").await?;
+            db::drop_all_novels(conn, &ctx).await?;
+            output.print(&json!({ "dropped": info.novel_count, "snapshot": info.id }), |_, out| {
+                out.push(format!("Dropped {} novels; snapshot {} has a copy", info.novel_count, info.id));
+            })
+        },
+        NovelsCommand::ListTrash => {
+            output.print(&db::fetch_trashed_novels(conn).await?, |trash, out| {
+                for trashed in trash {
+                    out.push(format!("{}\t{}\ttrashed {}", trashed.novel.id, trashed.novel.title, trashed.deleted_at));
+                }
+            })
+        },
+        NovelsCommand::RestoreTrashed { id } => {
+            let Some(novel) = db::restore_trashed_novel(conn, id, &ctx).await? else {
+                return Err(Error::msg(format!("Novel not in the trash: {id}")));
+            };
+            output.print(&novel, |novel, out| out.push(format!("Restored [{}] (id {id}) from the trash", novel.title)))
+        },
+        NovelsCommand::PurgeTrash { older_than_days } => {
+            let cutoff = Utc::now() - TimeDelta::days(older_than_days);
+            let purged = db::purge_trash(conn, cutoff, &ctx).await?;
+            output.print(&json!({ "purged": purged, "cutoff": cutoff }), |_, out| {
+                out.push(format!("Purged {purged} novels trashed before {cutoff}"));
+            })
+        },
+        NovelsCommand::History { id } => {
+            output.print(&audit::novel_history(conn, id).await?, |history, out| {
+                for entry in history {
+                    let fields = entry.changes.keys().join(", ");
+                    out.push(format!("{}\t{}\t{}\t{} ({})\t{fields}", entry.id, entry.created_at, entry.action, entry.actor, entry.source));
+                }
+            })
+        },
+        NovelsCommand::Revert { id, entry } => {
+            let novel = audit::revert_novel(conn, id, entry, &ctx).await?;
+            output.print(&novel, |novel, out| out.push(format!("Reverted [{}] (id {id}) to history entry {entry}", novel.title)))
+        },
+        NovelsCommand::Doctor { fix } => {
+            let diagnosis = doctor::run_doctor(conn, fix, &AuditContext::local(AuditSource::Doctor)).await?;
+            let problems = diagnosis.problems().count();
+            let json = json!({
+                "problems": problems,
+                "fixed": fix,
+                "columns": diagnosis.columns,
+                "repairs": diagnosis.repairs,
+                "pending_migrations": diagnosis.pending_migrations,
+            });
+            output.print(&json, |_, out| {
+                for (id, title, problem) in diagnosis.problems() {
+                    out.push(format!("{id} [{title}] {}: {} {} -> {}", problem.kind, problem.field, problem.value, problem.fix));
+                }
+                out.push(format!("Found {problems} problems in {} novels", diagnosis.novel_count()));
+                if fix {
+                    out.push(format!("Repaired {} novels", diagnosis.novel_count()));
+                }
+                if !diagnosis.pending_migrations.is_empty() {
+                    out.push(format!(
+                        "Only checked statuses, providers and ratings; the other checks run once the {} pending migrations are applied with `migrate up`",
+                        diagnosis.pending_migrations.len()
+                    ));
+                }
+            })?;
+            if !fix && problems > 0 {
+                return Err(ProblemsFound(format!("Found {problems} problems; run `novels doctor --fix` to repair them")).into());
+            }
+            Ok(())
+        },
+    }
+}
+
+async fn run_scrape(conn: &DatabaseConnection, config: &Config, command: ScrapeCommand, output: Output) -> Result<()> {
+    match command {
+        ScrapeCommand::All { reset_novels } => {
+            let report = data_ingestion::fetch_novel_tags(conn, reset_novels, &config.scraper).await?;
+            output.print(&report, |report, out| {
+                for failure in &report.failed {
+                    out.push(format!("Failed: [{}]: {}", failure.title, failure.error));
+                }
+                out.push(format!("Scraped: updated {}, unchanged {}, failed {}", report.updated.len(), report.unchanged.len(), report.failed.len()));
+            })?;
+            if !report.failed.is_empty() {
+                return Err(ProblemsFound(format!("Failed to scrape {} novels", report.failed.len())).into());
+            }
+            Ok(())
+        },
+        ScrapeCommand::Single { title, url } => {
+            let novel = data_ingestion::single_fetch_novel_tags(conn, &title, url, &config.scraper).await?;
+            output.print(&novel, |novel, out| out.push(format!("Fetched {} tags for [{}]", novel.tags.len(), novel.title)))
+        },
+    }
+}
+
+async fn run_import(conn: &DatabaseConnection, command: ImportCommand, output: Output) -> Result<()> {
+    match command {
+        ImportCommand::Csv { file } => {
+            let rows = data_ingestion::csv::read_novel_tags_csv(&file)?;
+            snapshot::take_snapshot(conn, "before csv import").await?;
+            let results = db::update_novel_tags(conn, &rows, &AuditContext::local(AuditSource::CsvImport)).await?;
+            output.print(&results, |results, out| {
+                let not_found = results.iter().filter(|row| row.outcome == db::RowOutcome::NotFound).count();
+                out.push(format!("{not_found} csv rows did not match any novel"));
+            })
+        },
+        ImportCommand::Backup { file, mode, dry_run } => {
+            let backup = backup::parse_backup(&std::fs::read(&file)?)?;
+            let options = RestoreOptions { mode, dry_run };
+            let ctx = AuditContext::local(AuditSource::Restore);
+            match db::restore_novels(conn, backup.novels, &options, AllowInvalidRows::False, "before backup restore", &ctx).await {
+                Ok(report) => output.print(&report, print_restore_report),
+                Err(e) => {
+                    let rejected = e.downcast::<RestoreRejected>()?;
+                    output.print(&rejected.report, print_restore_report)?;
+                    Err(ProblemsFound(rejected.to_string()).into())
+                },
+            }
+        },
+    }
+}
+
+// removals go first, so a tag both removed and added ends up at the end of the list
+fn edit_tags(mut tags: Vec<String>, add: Vec<String>, remove: &[String]) -> Vec<String> {
+    tags.retain(|tag| !remove.contains(tag));
+    for tag in add {
+        if !tags.contains(&tag) {
+            tags.push(tag);
         }
     }
+    tags
+}
+
+fn print_restore_report(report: &RestoreReport, out: &mut Vec<String>) {
+    for conflict in &report.conflicting {
+        out.push(format!("Conflict: [{}] (id {}): {}", conflict.title, conflict.id, conflict.reason));
+    }
+    for row in &report.invalid {
+        let errors = row.errors.iter().map(|e| format!("{} {}", e.field, e.message)).join(", ");
+        out.push(format!("Invalid: [{}] (id {}): {errors}", row.title, row.id));
+    }
+    if !report.removed.is_empty() {
+        let verb = if report.dry_run { "would go" } else { "went" };
+        out.push(format!("{} novels missing from the backup {verb} to the trash", report.removed.len()));
+    }
+    out.push(format!("Restore: {}", report.summary()));
+}
 
-    Ok(())
+async fn run_backup(conn: &DatabaseConnection, config: &Config, command: BackupCommand, output: Output) -> Result<()> {
+    match command {
+        BackupCommand::Run { destination } => {
+            let Some(destination) = destination.or_else(|| config.backup.destination.clone()) else {
+                return Err(Error::msg("No backup destination; set BACKUP_DESTINATION or pass --destination"));
+            };
+            let settings = BackupSettings::with_destination(destination, &config.backup);
+            let store = schedule::open_destination(&settings.destination)?;
+            let name = schedule::run_backup(conn, store.as_ref(), &settings).await?;
+            output.print(&json!({ "destination": settings.destination, "name": name }), |_, out| {
+                out.push(format!("Backed up to {name} in [{}]", settings.destination));
+            })
+        },
+        BackupCommand::Export { file } => {
+            let novels = db::fetch_novel_entries(conn).await?;
+            let backup = Backup::new(novels);
+            std::fs::write(&file, serde_json::to_vec(&backup)?)?;
+            output.print(&json!({ "file": file, "novels": backup.novels.len() }), |_, out| {
+                out.push(format!("Exported {} novels to {}", backup.novels.len(), file.display()));
+            })
+        },
+    }
+}
+
+async fn run_snapshots(conn: &DatabaseConnection, command: SnapshotsCommand, output: Output) -> Result<()> {
+    match command {
+        SnapshotsCommand::List => {
+            output.print(&db::fetch_snapshot_infos(conn).await?, |infos, out| {
+                for info in infos {
+                    out.push(format!("{}\t{}\t{} novels\t{}", info.id, info.created_at, info.novel_count, info.reason));
+                }
+            })
+        },
+        SnapshotsCommand::Diff { from, to } => {
+            output.print(&snapshot::diff_snapshots(conn, from, to).await?, |report, out| {
+                for novel in &report.added {
+                    out.push(format!("+ [{}] (id {})", novel.title, novel.id));
+                }
+                for novel in &report.removed {
+                    out.push(format!("- [{}] (id {})", novel.title, novel.id));
+                }
+                for novel in &report.updated {
+                    out.push(format!("~ [{}] (id {})", novel.title, novel.id));
+                }
+                out.push(format!("Diff: {}", report.summary()));
+            })
+        },
+        SnapshotsCommand::Restore { id } => {
+            let report = snapshot::restore_snapshot(conn, id, &AuditContext::local(AuditSource::Restore)).await?;
+            output.print(&report, |report, out| out.push(format!("Restored snapshot {id}: {}", report.summary())))
+        },
+    }
+}
+
+async fn run_tokens(conn: &DatabaseConnection, command: TokensCommand, output: Output) -> Result<()> {
+    match command {
+        TokensCommand::Mint { name, scopes } => {
+            let token = auth::mint_token(conn, &name, &scopes).await?;
+            output.print(&json!({ "name": name, "token": token }), |_, out| {
+                out.push(format!("Minted token [{name}]; it will not be shown again:\n{token}"));
+            })
+        },
+        TokensCommand::List => {
+            let tokens = db::fetch_tokens(conn).await?;
+            let value = tokens.iter()
+                .map(|token| json!({
+                    "id": token.id,
+                    "name": token.name,
+                    "scopes": token.scopes,
+                    "created_at": token.created_at.and_utc(),
+                    "revoked_at": token.revoked_at.map(|date| date.and_utc()),
+                }))
+                .collect_vec();
+            output.print(&value, |_, out| {
+                for token in &tokens {
+                    let status = match token.revoked_at {
+                        Some(date) => format!("revoked {date}"),
+                        None => "active".to_string(),
+                    };
+                    out.push(format!("{}\t{}\t{}\tcreated {}\t{status}", token.id, token.name, token.scopes, token.created_at));
+                }
+            })
+        },
+        TokensCommand::Revoke { id } => {
+            db::revoke_token(conn, id).await?;
+            output.print(&json!({ "revoked": id }), |_, out| out.push(format!("Revoked token {id}")))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn verify_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn global_flags() {
+        let cli = Cli::try_parse_from(["webnovel-list", "novels", "doctor", "--json", "--log-level", "debug", "--config", "a.toml"]).unwrap();
+        assert!(cli.json);
+        assert_eq!(cli.log_level, LogLevel::Debug);
+        assert_eq!(cli.log_format, LogFormat::Human);
+        assert_eq!(cli.config, Some(PathBuf::from("a.toml")));
+        assert!(matches!(cli.command, Command::Novels { command: NovelsCommand::Doctor { fix: false } }));
+
+        let cli = Cli::try_parse_from(["webnovel-list", "--log-format", "json", "serve"]).unwrap();
+        assert_eq!(cli.log_format, LogFormat::Json);
+
+        // a subcommand is required; starting the server is explicit
+        assert!(Cli::try_parse_from(["webnovel-list"]).is_err());
+    }
+
+    #[test]
+    fn novel_flags() {
+        let cli = Cli::try_parse_from(["webnovel-list", "novels", "edit", "3", "--status", "completed", "--tag+", "Magic", "--tag-", "Harem", "--tag-", "Gore"]).unwrap();
+        let Command::Novels { command: NovelsCommand::Edit { id, fields, add_tags, remove_tags } } = cli.command else {
+            panic!("expected an edit");
+        };
+        assert_eq!((id, add_tags, remove_tags), (3, vec!["Magic".to_string()], vec!["Harem".to_string(), "Gore".to_string()]));
+        let patch = fields.patch();
+        assert_eq!(patch.status, Some(Some(Status::Completed)));
+        assert!(patch.tags.is_none() && patch.title.is_none());
+
+        let cli = Cli::try_parse_from(["webnovel-list", "novels", "list", "--sort", "date_modified", "--tag", "Magic", "--tag", "Academy", "--subset", "not-sus"]).unwrap();
+        let Command::Novels { command: NovelsCommand::List(args) } = cli.command else {
+            panic!("expected a list");
+        };
+        let query = args.query();
+        assert_eq!(query.sort, NovelSort::DateModified);
+        assert_eq!(query.subset, Some(NovelSubsets::NotSus));
+        assert_eq!(query.include_tags(), ["Magic", "Academy"]);
+        assert!(query.exclude_tags.is_none());
+    }
+
+    #[test]
+    fn tag_edits() {
+        let tags = ["Magic", "Harem", "Academy"].map(String::from).to_vec();
+        let edited = edit_tags(tags, vec!["Gore".to_string(), "Magic".to_string()], &["Harem".to_string()]);
+        assert_eq!(edited, ["Magic", "Academy", "Gore"]);
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(exit_code(&Error::msg("oops")), EXIT_FAILURE);
+        assert_eq!(exit_code(&PendingMigrations { names: vec!["m1".to_string()] }.into()), EXIT_PENDING_MIGRATIONS);
+        assert_eq!(exit_code(&ProblemsFound("found some".to_string()).into()), EXIT_PROBLEMS_FOUND);
+    }
 }
@@ -0,0 +1,147 @@
+use crate::novel_entry::{NovelEntry, NovelPage};
+use crate::stats::{self, Stats};
+
+use itertools::Itertools;
+
+/*
+Text output for people reading a terminal; scripts should use `--json` instead of parsing these.
+Long cells are cut short so a table stays one line per row.
+*/
+const MAX_CELL_WIDTH: usize = 40;
+
+// left aligned columns under a header, separated by two spaces
+pub fn format_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let rows = rows.iter()
+        .map(|row| row.iter().map(|cell| truncate(cell, MAX_CELL_WIDTH)).collect_vec())
+        .collect_vec();
+    let mut widths = header.iter().map(|name| name.chars().count()).collect_vec();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: &[String]| cells.iter()
+        .zip(&widths)
+        .map(|(cell, width)| format!("{cell:<width$}"))
+        .join("  ")
+        .trim_end()
+        .to_string();
+    let header = header.iter().map(ToString::to_string).collect_vec();
+    std::iter::once(line(&header))
+        .chain(rows.iter().map(|row| line(row)))
+        .join("\n")
+}
+
+// one `name  value` line per field, with the values lined up
+pub fn format_fields(fields: &[(&str, String)]) -> String {
+    let width = fields.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    fields.iter()
+        .map(|(name, value)| format!("{name:<width$}  {value}").trim_end().to_string())
+        .join("\n")
+}
+
+pub fn novel_page(page: &NovelPage) -> String {
+    let rows = page.novels.iter()
+        .map(|novel| vec![
+            novel.id.to_string(),
+            novel.title.clone(),
+            novel.chapter.to_string(),
+            novel.rating.to_string(),
+            optional(novel.status.as_ref()),
+            optional(novel.provider.as_ref()),
+            novel.country.clone(),
+            novel.tags.join(", "),
+        ])
+        .collect_vec();
+    let table = format_table(&["ID", "TITLE", "CHAPTER", "RATING", "STATUS", "PROVIDER", "COUNTRY", "TAGS"], &rows);
+
+    let mut footer = match page.novels.len() {
+        0 => format!("No novels out of {}", page.total),
+        shown => format!("Showing {shown} novels out of {} (page {})", page.total, page.page),
+    };
+    if page.skipped > 0 {
+        footer.push_str(&format!("; {} unreadable novels skipped (see `novels doctor`)", page.skipped));
+    }
+    if let Some(cursor) = page.next_cursor {
+        footer.push_str(&format!("; next page: --cursor {cursor}"));
+    }
+    format!("{table}\n{footer}")
+}
+
+pub fn novel(novel: &NovelEntry) -> String {
+    format_fields(&[
+        ("id", novel.id.to_string()),
+        ("title", novel.title.clone()),
+        ("country", novel.country.clone()),
+        ("chapter", novel.chapter.to_string()),
+        ("rating", novel.rating.to_string()),
+        ("status", optional(novel.status.as_ref())),
+        ("provider", optional(novel.provider.as_ref())),
+        ("tags", novel.tags.join(", ")),
+        ("notes", novel.notes.clone()),
+        ("date_modified", novel.date_modified.to_string()),
+        ("date_started", optional(novel.date_started.as_ref())),
+        ("date_completed", optional(novel.date_completed.as_ref())),
+    ])
+}
+
+pub fn stats(stats: &Stats) -> String {
+    let totals = format_fields(&[
+        ("novels", stats.novel_count.to_string()),
+        ("chapters", stats.chapter_count.to_string()),
+        ("volumes completed", stats.volumes_completed.to_string()),
+        ("not started", stats.novels_not_started.to_string()),
+        ("average rating", format!("{:.2}", stats.average_rating)),
+    ]);
+    let ratings = stats.rating_dist.iter()
+        .enumerate()
+        .map(|(idx, count)| vec![(idx + 1).to_string(), count.to_string()])
+        .collect_vec();
+    let rows = |dist: Vec<(&str, u32)>| dist.into_iter().map(|(name, count)| vec![name.to_string(), count.to_string()]).collect_vec();
+
+    [
+        totals,
+        format_table(&["RATING", "NOVELS"], &ratings),
+        format_table(&["STATUS", "NOVELS"], &rows(stats::most_common(&stats.status_dist))),
+        format_table(&["CHAPTERS", "NOVELS"], &rows(stats.chapter_buckets())),
+        format_table(&["COUNTRY", "NOVELS"], &rows(stats::most_common(&stats.country_dist))),
+    ].join("\n\n")
+}
+
+fn optional(value: Option<&impl ToString>) -> String {
+    value.map(ToString::to_string).unwrap_or_default()
+}
+
+fn truncate(cell: &str, max: usize) -> String {
+    if cell.chars().count() <= max {
+        return cell.to_string();
+    }
+    let mut cut: String = cell.chars().take(max - 3).collect();
+    cut.push_str("...");
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table() {
+        let rows = vec![
+            vec!["1".to_string(), "Lord of the Mysteries".to_string(), "".to_string()],
+            vec!["12".to_string(), "x".repeat(50), "Reading".to_string()],
+        ];
+        let table = format_table(&["ID", "TITLE", "STATUS"], &rows);
+        let lines = table.lines().collect_vec();
+        assert_eq!(lines[0], format!("ID  {:<40}  STATUS", "TITLE"));
+        assert_eq!(lines[1], "1   Lord of the Mysteries");
+        assert_eq!(lines[2], format!("12  {}...  Reading", "x".repeat(37)));
+    }
+
+    #[test]
+    fn fields() {
+        let fields = format_fields(&[("id", "3".to_string()), ("status", String::new()), ("title", "Solo Leveling".to_string())]);
+        assert_eq!(fields, "id      3\nstatus\ntitle   Solo Leveling");
+    }
+}
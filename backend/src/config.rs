@@ -0,0 +1,345 @@
+use crate::novel_entry::SUS_TAGS;
+
+use std::{env, fmt::Display, path::{Path, PathBuf}, str::FromStr};
+
+use anyhow::{Error, Result};
+use serde::Deserialize;
+
+/*
+Everything the backend can be configured with, loaded once at startup and validated before anything runs.
+Only `serve` needs the server settings (domain, auth secret, image_to_tetris), so the other commands only check the rest.
+Values come from the defaults below, then a TOML file (`--config`, CONFIG_PATH, or config.toml if it exists), then environment variables.
+The environment variable names are the ones the backend has always read, like DATABASE_URL; see `apply_overrides` for the full list.
+*/
+const DEFAULT_PATH: &str = "config.toml";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // where the server listens, ex: 127.0.0.1:5000
+    pub domain: String,
+    pub database_url: String,
+    // shared with the frontend to verify session tokens
+    pub auth_secret: String,
+    pub image_to_tetris_path: PathBuf,
+    // largest request body the server accepts, in bytes
+    pub payload_limit: usize,
+    // novels with any of these tags are hidden from the public
+    pub sus_tags: Vec<String>,
+    pub pool: PoolConfig,
+    pub scraper: ScraperConfig,
+    pub backup: BackupConfig,
+}
+
+// sqlite always uses a single connection, so only the timeouts apply to it
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub connect_timeout_secs: u64,
+    pub acquire_timeout_secs: u64,
+    pub idle_timeout_secs: u64,
+    pub max_lifetime_secs: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScraperConfig {
+    // chrome is found automatically when this isn't set
+    pub chrome_path: Option<PathBuf>,
+    // how long to wait for each page to load
+    pub novelupdates_delay_secs: u64,
+    pub royalroad_delay_secs: u64,
+    // how long to back off after a novel fails to scrape
+    pub failure_delay_secs: u64,
+    // how many times to try a novel before giving up on it
+    pub attempts: u32,
+}
+
+// scheduled backups are disabled unless a destination is set
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    // a local directory or s3://bucket/optional/prefix
+    pub destination: Option<String>,
+    pub interval_hours: u64,
+    pub keep_daily: usize,
+    pub keep_weekly: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            domain: String::new(),
+            database_url: String::new(),
+            auth_secret: String::new(),
+            image_to_tetris_path: PathBuf::new(),
+            payload_limit: 5_000_000, // 5 megabytes
+            sus_tags: SUS_TAGS.iter().map(ToString::to_string).collect(),
+            pool: PoolConfig::default(),
+            scraper: ScraperConfig::default(),
+            backup: BackupConfig::default(),
+        }
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 5,
+            min_connections: 5,
+            connect_timeout_secs: 5,
+            acquire_timeout_secs: 5,
+            idle_timeout_secs: 5,
+            max_lifetime_secs: 5,
+        }
+    }
+}
+
+impl Default for ScraperConfig {
+    fn default() -> Self {
+        Self {
+            chrome_path: None,
+            novelupdates_delay_secs: 5,
+            royalroad_delay_secs: 3,
+            failure_delay_secs: 15,
+            attempts: 1,
+        }
+    }
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            destination: None,
+            interval_hours: 24,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+impl Config {
+    // `path` comes from `--config` and takes precedence over CONFIG_PATH
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = path.map(Path::to_path_buf).or_else(|| env::var_os("CONFIG_PATH").map(PathBuf::from));
+        let mut config = match path {
+            Some(path) => Self::from_file(&path)?,
+            None if Path::new(DEFAULT_PATH).exists() => Self::from_file(Path::new(DEFAULT_PATH))?,
+            None => Self::default(),
+        };
+        config.apply_overrides(|key| env::var(key).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| Error::msg(format!("Failed to read config file {}: {e}", path.display())))?;
+        toml::from_str(&contents)
+            .map_err(|e| Error::msg(format!("Invalid config file {}: {e}", path.display())))
+    }
+
+    // `var` looks up an environment variable; anything it finds replaces the file's value
+    pub fn apply_overrides(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let var = &var;
+        override_with(var, "DOMAIN", &mut self.domain)?;
+        override_with(var, "DATABASE_URL", &mut self.database_url)?;
+        override_with(var, "BACKEND_AUTH_SECRET", &mut self.auth_secret)?;
+        override_with(var, "IMAGE_TO_TETRIS_PATH", &mut self.image_to_tetris_path)?;
+        override_with(var, "PAYLOAD_LIMIT", &mut self.payload_limit)?;
+        if let Some(tags) = var("SUS_TAGS") {
+            self.sus_tags = tags.split(',').map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty()).collect();
+        }
+
+        override_with(var, "DB_MAX_CONNECTIONS", &mut self.pool.max_connections)?;
+        override_with(var, "DB_MIN_CONNECTIONS", &mut self.pool.min_connections)?;
+        override_with(var, "DB_CONNECT_TIMEOUT_SECS", &mut self.pool.connect_timeout_secs)?;
+        override_with(var, "DB_ACQUIRE_TIMEOUT_SECS", &mut self.pool.acquire_timeout_secs)?;
+        override_with(var, "DB_IDLE_TIMEOUT_SECS", &mut self.pool.idle_timeout_secs)?;
+        override_with(var, "DB_MAX_LIFETIME_SECS", &mut self.pool.max_lifetime_secs)?;
+
+        override_optional_with(var, "CHROME_PATH", &mut self.scraper.chrome_path)?;
+        override_with(var, "SCRAPER_NOVELUPDATES_DELAY_SECS", &mut self.scraper.novelupdates_delay_secs)?;
+        override_with(var, "SCRAPER_ROYALROAD_DELAY_SECS", &mut self.scraper.royalroad_delay_secs)?;
+        override_with(var, "SCRAPER_FAILURE_DELAY_SECS", &mut self.scraper.failure_delay_secs)?;
+        override_with(var, "SCRAPER_ATTEMPTS", &mut self.scraper.attempts)?;
+
+        override_optional_with(var, "BACKUP_DESTINATION", &mut self.backup.destination)?;
+        override_with(var, "BACKUP_INTERVAL_HOURS", &mut self.backup.interval_hours)?;
+        override_with(var, "BACKUP_KEEP_DAILY", &mut self.backup.keep_daily)?;
+        override_with(var, "BACKUP_KEEP_WEEKLY", &mut self.backup.keep_weekly)?;
+        Ok(())
+    }
+
+    // what every command needs; reports every problem at once so a broken deploy can be fixed in one go
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        if self.database_url.trim().is_empty() {
+            problems.push("database_url (DATABASE_URL) is not set".to_string());
+        } else if !["postgres://", "postgresql://", "sqlite:"].iter().any(|scheme| self.database_url.starts_with(scheme)) {
+            problems.push("database_url (DATABASE_URL) must start with postgres:// or sqlite:".to_string());
+        }
+        if self.pool.max_connections == 0 || self.pool.min_connections > self.pool.max_connections {
+            problems.push(format!(
+                "pool.min_connections ({}) must be at most pool.max_connections ({}), which must be at least 1",
+                self.pool.min_connections,
+                self.pool.max_connections
+            ));
+        }
+        if let Some(chrome_path) = self.scraper.chrome_path.as_ref().filter(|path| !path.is_file()) {
+            problems.push(format!("scraper.chrome_path (CHROME_PATH) does not exist: {}", chrome_path.display()));
+        }
+        if self.scraper.attempts == 0 {
+            problems.push("scraper.attempts (SCRAPER_ATTEMPTS) must be greater than 0".to_string());
+        }
+        report(problems)
+    }
+
+    // what only `serve` needs, so management commands run with just a database url
+    pub fn validate_server(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        if self.domain.trim().is_empty() {
+            problems.push("domain (DOMAIN) is not set".to_string());
+        }
+        if self.auth_secret.is_empty() {
+            problems.push("auth_secret (BACKEND_AUTH_SECRET) is not set".to_string());
+        }
+        if self.image_to_tetris_path.as_os_str().is_empty() {
+            problems.push("image_to_tetris_path (IMAGE_TO_TETRIS_PATH) is not set".to_string());
+        } else if !self.image_to_tetris_path.is_file() {
+            problems.push(format!("image_to_tetris_path (IMAGE_TO_TETRIS_PATH) does not exist: {}", self.image_to_tetris_path.display()));
+        }
+        if self.payload_limit == 0 {
+            problems.push("payload_limit must be greater than 0".to_string());
+        }
+        if self.backup.interval_hours == 0 {
+            problems.push("backup.interval_hours must be greater than 0".to_string());
+        }
+        report(problems)
+    }
+}
+
+fn report(problems: Vec<String>) -> Result<()> {
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::msg(format!("Invalid configuration:\n  {}", problems.join("\n  "))))
+    }
+}
+
+fn override_with<T: FromStr>(var: impl Fn(&str) -> Option<String>, key: &str, field: &mut T) -> Result<()>
+where
+    T::Err: Display,
+{
+    if let Some(value) = var(key) {
+        *field = parse_var(key, &value)?;
+    }
+    Ok(())
+}
+
+// an empty value clears the field
+fn override_optional_with<T: FromStr>(var: impl Fn(&str) -> Option<String>, key: &str, field: &mut Option<T>) -> Result<()>
+where
+    T::Err: Display,
+{
+    match var(key) {
+        Some(value) if value.is_empty() => *field = None,
+        Some(value) => *field = Some(parse_var(key, &value)?),
+        None => {},
+    }
+    Ok(())
+}
+
+fn parse_var<T: FromStr>(key: &str, value: &str) -> Result<T>
+where
+    T::Err: Display,
+{
+    value.parse().map_err(|e| Error::msg(format!("Invalid value for {key}: {value} ({e})")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const FILE: &str = r#"
+        domain = "127.0.0.1:5000"
+        database_url = "sqlite::memory:"
+        auth_secret = "secret"
+        image_to_tetris_path = "Cargo.toml"
+        sus_tags = ["Adult"]
+
+        [pool]
+        max_connections = 10
+
+        [scraper]
+        royalroad_delay_secs = 1
+    "#;
+
+    fn overrides(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn file_then_env() {
+        let mut config: Config = toml::from_str(FILE).unwrap();
+        assert_eq!((config.pool.max_connections, config.pool.min_connections), (10, 5));
+        assert_eq!((config.scraper.royalroad_delay_secs, config.scraper.novelupdates_delay_secs), (1, 5));
+        assert_eq!(config.sus_tags, ["Adult"]);
+        assert!(config.validate().is_ok());
+        assert!(config.validate_server().is_ok());
+
+        let vars = overrides(&[("DB_MAX_CONNECTIONS", "3"), ("DB_MIN_CONNECTIONS", "1"), ("SUS_TAGS", "Ecchi, Adult,"), ("BACKUP_DESTINATION", "/tmp/backups")]);
+        config.apply_overrides(vars).unwrap();
+        assert_eq!((config.pool.max_connections, config.pool.min_connections), (3, 1));
+        assert_eq!(config.sus_tags, ["Ecchi", "Adult"]);
+        assert_eq!(config.backup.destination.as_deref(), Some("/tmp/backups"));
+
+        config.apply_overrides(overrides(&[("BACKUP_DESTINATION", "")])).unwrap();
+        assert_eq!(config.backup.destination, None);
+
+        let e = config.apply_overrides(overrides(&[("PAYLOAD_LIMIT", "lots")])).unwrap_err();
+        assert!(e.to_string().starts_with("Invalid value for PAYLOAD_LIMIT: lots"));
+    }
+
+    #[test]
+    fn example_config() {
+        let config = Config::from_file(Path::new("config.example.toml")).unwrap();
+        assert_eq!(config.sus_tags, SUS_TAGS);
+        assert_eq!(config.scraper.failure_delay_secs, ScraperConfig::default().failure_delay_secs);
+        assert_eq!(config.scraper.attempts, ScraperConfig::default().attempts);
+    }
+
+    #[test]
+    fn invalid_config() {
+        assert!(toml::from_str::<Config>("unknown_key = 1").is_err());
+
+        let config = Config {
+            database_url: "mysql://localhost".into(),
+            image_to_tetris_path: "does/not/exist".into(),
+            pool: PoolConfig { min_connections: 6, ..PoolConfig::default() },
+            scraper: ScraperConfig { attempts: 0, ..ScraperConfig::default() },
+            ..Config::default()
+        };
+        let e = config.validate().unwrap_err().to_string();
+        for field in ["database_url", "pool.min_connections", "scraper.attempts"] {
+            assert!(e.contains(&format!("\n  {field} ")), "{field} missing from {e}");
+        }
+        let e = config.validate_server().unwrap_err().to_string();
+        for field in ["domain", "auth_secret", "image_to_tetris_path"] {
+            assert!(e.contains(&format!("\n  {field} ")), "{field} missing from {e}");
+        }
+    }
+
+    #[test]
+    fn management_config() {
+        // everything but `serve` only needs to reach the database
+        let mut config = Config::default();
+        config.apply_overrides(overrides(&[("DATABASE_URL", "postgres://localhost/novels")])).unwrap();
+        assert!(config.validate().is_ok());
+        assert!(config.validate_server().is_err());
+    }
+}
@@ -3,38 +3,56 @@ pub mod novelupdates;
 pub mod royalroad;
 pub mod csv;
 
-use crate::db::{self, UpdateDateModified};
-use crate::novel_entry::{NovelEntry, NovelSubsets, Provider};
+use crate::audit::{AuditContext, AuditSource};
+use crate::config::ScraperConfig;
+use crate::db::{self, CheckConflicts, UpdateDateModified};
+use crate::novel_entry::{NovelEntry, Provider};
+use crate::snapshot;
 
 use anyhow::{Error, Result};
 use itertools::Itertools;
 use sea_orm::DatabaseConnection;
+use serde::Serialize;
 use tokio::time::sleep;
+use tracing::Instrument;
 
 use std::time::Duration;
 
-pub async fn fetch_novel_tags(conn: &DatabaseConnection, reset_novels: bool) -> Result<()> {
-    let novels = db::fetch_novel_entries(conn, NovelSubsets::All).await?;
+// what a bulk scrape did to each novel it looked at, by title
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ScrapeReport {
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub failed: Vec<ScrapeFailure>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ScrapeFailure {
+    pub title: String,
+    pub error: String,
+}
+
+pub async fn fetch_novel_tags(conn: &DatabaseConnection, reset_novels: bool, config: &ScraperConfig) -> Result<ScrapeReport> {
+    let novels = db::list_novel_entries(conn).await?;
     let novels_to_fetch = novels.iter()
         .filter(|novel| novel.tags.is_empty() || reset_novels)
         .filter(|novel| novel.provider.is_some())
         .collect_vec();
-    println!("Fetching tags for {} novels out of {}...", novels_to_fetch.len(), novels.len());
+    tracing::info!("Fetching tags for {} novels out of {}...", novels_to_fetch.len(), novels.len());
 
+    let mut report = ScrapeReport::default();
     let mut modified_novels = Vec::new();
     for (idx, novel) in novels_to_fetch.into_iter().enumerate() {
-        // scrape as required
-        let scraped_tags = match novel.provider.as_ref().expect("novels without providers should not be here") {
-            Provider::NovelUpdates => novelupdates::scrape_genres_and_tags(&novel.title, 5, None).await,
-            Provider::RoyalRoad => royalroad::scrape_tags(&novel.title, 3).await,
-        };
+        let provider = novel.provider.as_ref().expect("novels without providers should not be here");
+        let scraped_tags = scrape_tags(&novel.title, provider, None, config).await;
 
         match scraped_tags {
             Ok(new_tags) => {
                 // only update if the tags have been modified
                 // this prevents updating `date_modified` unnecessarily
                 if new_tags == novel.tags {
-                    println!("{}. Unmodified: [{}]", idx + 1, novel.title);
+                    tracing::info!("{}. Unmodified: [{}]", idx + 1, novel.title);
+                    report.unchanged.push(novel.title.clone());
                     continue;
                 }
 
@@ -43,37 +61,66 @@ pub async fn fetch_novel_tags(conn: &DatabaseConnection, reset_novels: bool) ->
                     ..novel.clone()
                 };
                 modified_novels.push(new_novel);
-                println!("{}. Success: [{}]", idx + 1, novel.title);
+                report.updated.push(novel.title.clone());
+                tracing::info!("{}. Success: [{}]", idx + 1, novel.title);
             },
 
             Err(e) => {
-                println!("Failure:\n  Novel: {}\n  Provider: {:?}\n  Error: {}",
-                novel.title, novel.provider, e);
-                sleep(Duration::from_secs(15)).await;
+                tracing::warn!("{}. Failure: [{}]", idx + 1, novel.title);
+                report.failed.push(ScrapeFailure { title: novel.title.clone(), error: e.to_string() });
+                sleep(Duration::from_secs(config.failure_delay_secs)).await;
             },
         }
     }
 
-    db::update_novel_entries(conn, &modified_novels, UpdateDateModified::False).await?;
-    println!("Finished modifying {} novels", modified_novels.len());
-    Ok(())
+    if !modified_novels.is_empty() {
+        snapshot::take_snapshot(conn, "before fetching all novel tags").await?;
+    }
+    db::update_novel_entries(conn, &modified_novels, UpdateDateModified::False, CheckConflicts::False, &AuditContext::local(AuditSource::Scrape)).await?;
+    tracing::info!("Finished modifying {} novels", modified_novels.len());
+    Ok(report)
 }
 
-pub async fn single_fetch_novel_tags(conn: &DatabaseConnection, title: &str, url: Option<String>) -> Result<()> {
-    println!("Attempting to fetch tags for [{title}]");
+// returns the novel with its new tags
+pub async fn single_fetch_novel_tags(conn: &DatabaseConnection, title: &str, url: Option<String>, config: &ScraperConfig) -> Result<NovelEntry> {
+    tracing::info!("Attempting to fetch tags for [{title}]");
 
     let novel = db::fetch_single_novel(conn, title).await?;
-    let scraped_tags = match novel.provider {
-        Some(Provider::NovelUpdates) => novelupdates::scrape_genres_and_tags(title, 2, url).await?,
-        Some(Provider::RoyalRoad) => royalroad::scrape_tags(title, 3).await?,
-        None => Err(Error::msg(format!("Novel doesn't contain a provider: {}", novel.title)))?
+    let Some(provider) = novel.provider.as_ref() else {
+        return Err(Error::msg(format!("Novel doesn't contain a provider: {}", novel.title)));
     };
-    let new_novel = [NovelEntry {
+    let scraped_tags = scrape_tags(title, provider, url.as_deref(), config).await?;
+    let new_novel = NovelEntry {
         tags: scraped_tags,
-        ..novel.clone()
-    }];
-    db::update_novel_entries(conn, &new_novel, UpdateDateModified::False).await?;
+        ..novel
+    };
+    db::update_novel_entries(conn, std::slice::from_ref(&new_novel), UpdateDateModified::False, CheckConflicts::False, &AuditContext::local(AuditSource::Scrape)).await?;
+
+    tracing::info!("Success: [{title}]");
+    Ok(new_novel)
+}
 
-    println!("Success: [{title}]");
-    Ok(())
+// each attempt gets its own span, so a failed fetch can be traced back to the novel, provider and try it came from
+async fn scrape_tags(title: &str, provider: &Provider, url: Option<&str>, config: &ScraperConfig) -> Result<Vec<String>> {
+    let mut attempt = 1;
+    loop {
+        let span = tracing::info_span!("scrape", novel = title, %provider, attempt);
+        let scraped_tags = match provider {
+            Provider::NovelUpdates => novelupdates::scrape_genres_and_tags(title, config, url.map(ToOwned::to_owned)).instrument(span.clone()).await,
+            Provider::RoyalRoad => royalroad::scrape_tags(title, config).instrument(span.clone()).await,
+        };
+
+        match scraped_tags {
+            Err(e) => {
+                span.in_scope(|| tracing::warn!("Failed to scrape: {e}"));
+                // the caller backs off after a novel fails, so the last attempt doesn't sleep
+                if attempt >= config.attempts {
+                    return Err(e);
+                }
+                sleep(Duration::from_secs(config.failure_delay_secs)).await;
+                attempt += 1;
+            },
+            Ok(tags) => return Ok(tags),
+        }
+    }
 }
@@ -1,12 +1,12 @@
 use anyhow::Result;
 use headless_chrome::{Browser, LaunchOptions, Tab, protocol::cdp::Page};
 
-use std::{env, path::PathBuf};
+use std::path::Path;
 
-pub fn init() -> Result<Browser> {
-    let chrome_binary = Some(PathBuf::from(env::var("CHROME_PATH")?));
+// chrome is found automatically without a path
+pub fn init(chrome_path: Option<&Path>) -> Result<Browser> {
     let launch_options = LaunchOptions::default_builder()
-        .path(chrome_binary)
+        .path(chrome_path.map(Path::to_path_buf))
         .sandbox(false)
         .build()?;
     Browser::new(launch_options)
@@ -44,14 +44,14 @@ pub fn read_novel_tags_csv(csv_file: &Path) -> Result<Vec<NovelTagsRecordParsed>
     let mut data = Vec::new();
 
     let headers = rdr.headers()?;
-    println!("Headers read: {headers:?}");
+    tracing::debug!("Headers read: {headers:?}");
 
     for res in rdr.deserialize() {
         let t: NovelTagsCsvRecord;
         match res {
             Ok(r) => t = r,
             Err(err) => {
-                println!("{err}");
+                tracing::warn!("Skipping csv row: {err}");
                 continue
             },
         }
@@ -63,11 +63,12 @@ pub fn read_novel_tags_csv(csv_file: &Path) -> Result<Vec<NovelTagsRecordParsed>
         };
         parsed.tags.append(&mut NovelEntry::parse_tags(&t.genres.unwrap_or_default()));
         strip_novel_tags(&mut parsed.tags);
+        parsed.tags.retain(|tag| !tag.is_empty());
 
         data.push(parsed);
     }
 
-    println!("Entries read: {}", data.len());
+    tracing::info!("Entries read: {}", data.len());
     Ok(data)
 }
 
@@ -1,4 +1,5 @@
 use super::browser;
+use crate::config::ScraperConfig;
 
 use anyhow::{Error, Result};
 use scraper::{Html, Selector};
@@ -8,14 +9,14 @@ use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
 
 use std::time::Duration;
 
-pub async fn scrape_genres_and_tags(title: &str, sleep_duration: u64, from_url: Option<String>) -> Result<Vec<String>> {
-    let browser = browser::init()?;
+pub async fn scrape_genres_and_tags(title: &str, config: &ScraperConfig, from_url: Option<String>) -> Result<Vec<String>> {
+    let browser = browser::init(config.chrome_path.as_deref())?;
     let novel_info_url: String = from_url.unwrap_or(construct_url(title)?);
     let tab = browser.new_tab()?;
     browser::configure_tab(&tab)?;
 
     tab.navigate_to(&novel_info_url)?;
-    sleep(Duration::from_secs(sleep_duration)).await;
+    sleep(Duration::from_secs(config.novelupdates_delay_secs)).await;
 
     let html = tab.get_content()?;
     tab.close_with_unload()?;
@@ -206,7 +207,7 @@ mod tests {
     #[ignore = "network intensive"]
     async fn scrape_lotm() {
         dotenv().ok();
-        let res = scrape_genres_and_tags("Lord of the Mysteries", 5, None).await.unwrap();
+        let res = scrape_genres_and_tags("Lord of the Mysteries", &ScraperConfig::default(), None).await.unwrap();
         assert!(res.len() > 50);
     }
 
@@ -214,7 +215,7 @@ mod tests {
     #[ignore = "network intensive"]
     async fn scrape_invalid() {
         dotenv().ok();
-        let res = scrape_genres_and_tags("laksjdflkajsdglh", 2, None).await;
+        let res = scrape_genres_and_tags("laksjdflkajsdglh", &ScraperConfig::default(), None).await;
         assert!(res.is_err());
     }
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
 use super::browser;
+use crate::config::ScraperConfig;
 
 use anyhow::{Error, Result};
 use headless_chrome::Tab;
@@ -9,8 +10,9 @@ use tokio::time::sleep;
 
 use std::{io::Write, time::Duration};
 
-pub async fn scrape_tags(title: &str, sleep_duration: u64) -> Result<Vec<String>> {
-    let browser = browser::init()?;
+pub async fn scrape_tags(title: &str, config: &ScraperConfig) -> Result<Vec<String>> {
+    let sleep_duration = config.royalroad_delay_secs;
+    let browser = browser::init(config.chrome_path.as_deref())?;
     let tab = browser.new_tab()?;
     browser::configure_tab(&tab)?;
 
@@ -89,7 +91,7 @@ mod tests {
     #[ignore = "network intensive"]
     async fn scrape_orellen() {
         dotenv().ok();
-        let res = scrape_tags("The Last Orellen", 3).await.unwrap();
+        let res = scrape_tags("The Last Orellen", &ScraperConfig::default()).await.unwrap();
         assert_eq!(res.len(), 10);
     }
 
@@ -97,7 +99,7 @@ mod tests {
     #[ignore = "network intensive"]
     async fn scrape_carousel() {
         dotenv().ok();
-        let res = scrape_tags("The Game at Carousel: A Horror Movie LitRPG", 3).await.unwrap();
+        let res = scrape_tags("The Game at Carousel: A Horror Movie LitRPG", &ScraperConfig::default()).await.unwrap();
         assert_eq!(res.len(), 13);
     }
 
@@ -105,7 +107,7 @@ mod tests {
     #[ignore = "network intensive"]
     async fn scrape_blood_and_fur() {
         dotenv().ok();
-        let res = scrape_tags("Blood & Fur", 3).await.unwrap();
+        let res = scrape_tags("Blood & Fur", &ScraperConfig::default()).await.unwrap();
         assert_eq!(res.len(), 13);
     }
 
@@ -113,7 +115,7 @@ mod tests {
     #[ignore = "network intensive"]
     async fn scrape_invalid() {
         dotenv().ok();
-        let res = scrape_tags("lkasjdfklasjdflajklsdf", 3).await;
+        let res = scrape_tags("lkasjdfklasjdflajklsdf", &ScraperConfig::default()).await;
         assert!(res.is_err());
     }
 }
\ No newline at end of file
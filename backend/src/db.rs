@@ -1,29 +1,56 @@
-use crate::entity::{novels, prelude::Novels};
-use crate::novel_entry::{filter_sus_novels, NovelEntry, NovelSubsets, NovelTagsRecordParsed};
+use crate::audit::{self, AuditAction, AuditContext, AuditEntry};
+use crate::auth::Scope;
+use crate::snapshot::{self, SnapshotInfo};
+use crate::backup::{self, AllowInvalidRows, Backup, RestoreMode, RestoreOptions, RestoreRejected, RestoreReport};
+use crate::config::PoolConfig;
+use crate::doctor::{ColumnRepair, Repair, StoredColumns};
+use crate::entity::{audit_log, novels, snapshots, tokens, prelude::{AuditLog, Novels, Snapshots, Tokens}};
+use crate::novel_entry::{
+    self,
+    NovelEntry,
+    NovelPage,
+    NovelPatch,
+    NovelQuery,
+    NovelSort,
+    NovelSubsets,
+    NovelTagsRecordParsed,
+    SortDirection,
+    TrashedNovel,
+};
+use crate::validation::{self, title_key};
 use std::{
-    env,
+    collections::HashMap,
+    fmt,
     time::Duration,
-    sync::LazyLock,
 };
 
 use anyhow::{Result, Error};
-use chrono::Local;
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
 use itertools::Itertools;
-use sea_orm::{TryIntoModel, Unchanged};
-use serde_json::from_value;
+use sea_orm::{ActiveValue::NotSet, DbBackend, Statement, Unchanged};
+use serde::Serialize;
 use sea_orm::{
     ActiveModelTrait,
     ColumnTrait,
+    ConnectionTrait,
     ConnectOptions,
     entity::Set,
     Database,
     DatabaseConnection,
+    DatabaseTransaction,
     EntityTrait,
+    FromQueryResult,
     IntoActiveModel,
+    JsonValue,
+    Order,
+    PaginatorTrait,
     QueryFilter,
     QueryOrder,
+    QuerySelect,
+    Select,
+    TransactionTrait,
+    sea_query::{Alias, Expr, Func, LikeExpr, OnConflict, Query},
 };
-use tokio::sync::Mutex;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum UpdateDateModified {
@@ -31,155 +58,1049 @@ pub enum UpdateDateModified {
     False,
 }
 
-pub async fn init() -> Result<DatabaseConnection> {
-    // init database
-    let database_url = env::var("DATABASE_URL")?;
-    println!("Connecting to: {database_url}");
+// whether updates should be rejected when a row's `date_modified` no longer matches the database
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CheckConflicts {
+    True,
+    False,
+}
+
+// returned when a row was modified by someone else since the caller fetched it
+#[derive(Debug)]
+pub struct NovelConflict {
+    pub current: Vec<NovelEntry>,
+}
+
+impl fmt::Display for NovelConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ids = self.current.iter().map(|novel| novel.id).join(", ");
+        write!(f, "Novels were modified since they were fetched: [{ids}]")
+    }
+}
+
+impl std::error::Error for NovelConflict {}
+
+// returned when a write targets novels that are in the trash; they have to be restored first
+#[derive(Debug)]
+pub struct NovelsInTrash {
+    pub ids: Vec<i32>,
+}
+
+impl fmt::Display for NovelsInTrash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ids = self.ids.iter().join(", ");
+        write!(f, "Novels are in the trash, restore them before editing: [{ids}]")
+    }
+}
+
+impl std::error::Error for NovelsInTrash {}
+
+// rows per statement for batched writes; postgres allows at most 65535 bind parameters per statement
+const BATCH_SIZE: usize = 1000;
+
+// what happened to a single row of a batch write
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowOutcome {
+    Inserted,
+    Updated,
+    Unchanged,
+    NotFound,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct UpsertedNovel {
+    pub novel: NovelEntry,
+    pub outcome: RowOutcome,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TagsUpdate {
+    pub title: String,
+    pub outcome: RowOutcome,
+}
+
+// the backend is picked from the url scheme: postgres://... or sqlite:...
+pub async fn connect(database_url: &str, pool: &PoolConfig) -> Result<DatabaseConnection> {
+    tracing::info!("Connecting to: {database_url}");
 
     let mut conn_opt = ConnectOptions::new(database_url);
-    conn_opt.max_connections(5)
-            .min_connections(5)
-            .connect_timeout(Duration::from_secs(5))
-            .acquire_timeout(Duration::from_secs(5))
-            .idle_timeout(Duration::from_secs(5))
-            .max_lifetime(Duration::from_secs(5))
-            .set_schema_search_path("public");
+    if database_url.starts_with("sqlite:") {
+        // sqlite allows a single writer, and every connection to an in-memory database gets its own copy
+        conn_opt.max_connections(1)
+                .min_connections(1)
+                .connect_timeout(Duration::from_secs(pool.connect_timeout_secs))
+                .acquire_timeout(Duration::from_secs(pool.acquire_timeout_secs));
+    } else {
+        conn_opt.max_connections(pool.max_connections)
+                .min_connections(pool.min_connections)
+                .connect_timeout(Duration::from_secs(pool.connect_timeout_secs))
+                .acquire_timeout(Duration::from_secs(pool.acquire_timeout_secs))
+                .idle_timeout(Duration::from_secs(pool.idle_timeout_secs))
+                .max_lifetime(Duration::from_secs(pool.max_lifetime_secs))
+                .set_schema_search_path("public");
+    }
     let db = Database::connect(conn_opt).await?;
-    println!("Connected");
+    tracing::info!("Connected");
 
     Ok(db)
 }
 
-pub async fn fetch_novel_entries(db: &DatabaseConnection, subset: NovelSubsets) -> Result<Vec<NovelEntry>> {
-    let models = Novels::find()
+// every novel that isn't in the trash
+fn live_novels() -> Select<Novels> {
+    Novels::find().filter(novels::Column::DeletedAt.is_null())
+}
+
+// a migrated in-memory sqlite database
+#[cfg(test)]
+pub async fn memory_db() -> DatabaseConnection {
+    use migration::MigratorTrait;
+
+    let db = connect("sqlite::memory:", &PoolConfig::default()).await.expect("sqlite should always open in memory");
+    migration::Migrator::up(&db, None).await.expect("migrations should apply to sqlite");
+    db
+}
+
+// every live novel, failing on rows that can't be decoded; backups, snapshots and restores need the whole list
+pub async fn fetch_novel_entries<C: ConnectionTrait>(db: &C) -> Result<Vec<NovelEntry>> {
+    let models = live_novels()
         .all(db)
         .await?;
 
     let mut novel_entries = Vec::new();
     for model in models {
-        novel_entries.push(NovelEntry::from_model(model));
+        novel_entries.push(NovelEntry::from_model(model)?);
     }
+    Ok(novel_entries)
+}
+
+// every live novel for showing to people; rows that can't be decoded are logged and skipped until `doctor --fix` repairs them
+pub async fn list_novel_entries(db: &DatabaseConnection) -> Result<Vec<NovelEntry>> {
+    let models = live_novels()
+        .all(db)
+        .await?;
+    Ok(decode_novels(models))
+}
 
-    if subset == NovelSubsets::NotSus {
-        novel_entries = filter_sus_novels(&novel_entries);
+fn decode_novels(models: Vec<novels::Model>) -> Vec<NovelEntry> {
+    models.into_iter()
+        .filter_map(|model| NovelEntry::from_model(model).inspect_err(|e| tracing::warn!("Skipping novel: {e}")).ok())
+        .collect()
+}
+
+pub async fn query_novel_entries(db: &DatabaseConnection, query: &NovelQuery, sus_tags: &[String]) -> Result<NovelPage> {
+    query.validate()?;
+    let limit = query.limit();
+    let select = novel_select(query, sus_tags);
+    // the total counts every match, not just the ones after the cursor
+    let total = select.clone().count(db).await?;
+
+    let mut select = after_cursor(select, query).limit(limit);
+    if query.cursor.is_none() {
+        select = select.offset(query.page * limit);
     }
+    let models = select.all(db).await?;
 
-    Ok(novel_entries)
+    // cursors continue from the last id in id order, whether or not that row could be read
+    let next_cursor = match models.last() {
+        Some(model) if query.sort == NovelSort::Id && models.len() as u64 == limit => Some(model.id),
+        _ => None,
+    };
+    let fetched = models.len();
+    let novels = decode_novels(models);
+    let skipped = (fetched - novels.len()) as u64;
+
+    Ok(NovelPage { novels, total, page: query.page, limit, next_cursor, skipped })
+}
+
+// keeps the rows past the cursor; only ids are cursors, so it's left out of the totals
+fn after_cursor(select: Select<Novels>, query: &NovelQuery) -> Select<Novels> {
+    match (query.cursor, query.direction) {
+        (Some(cursor), SortDirection::Asc) => select.filter(novels::Column::Id.gt(cursor)),
+        (Some(cursor), SortDirection::Desc) => select.filter(novels::Column::Id.lt(cursor)),
+        (None, _) => select,
+    }
+}
+
+// builds the filtered and sorted select for a novel query, without any pagination
+fn novel_select(query: &NovelQuery, sus_tags: &[String]) -> Select<Novels> {
+    let mut select = live_novels();
+
+    if query.subset == Some(NovelSubsets::NotSus) {
+        // novels without tags aren't vetted
+        select = select.filter(tags_text().ne("[]"));
+        for tag in sus_tags {
+            select = select.filter(tags_text().not_like(tag_pattern(tag)));
+        }
+    }
+    if let Some(status) = &query.status {
+        select = select.filter(novels::Column::Status.eq(status.clone()));
+    }
+    if let Some(provider) = &query.provider {
+        select = select.filter(novels::Column::Provider.eq(provider.clone()));
+    }
+    if let Some(country) = &query.country {
+        select = select.filter(Expr::expr(Func::lower(Expr::col(novels::Column::Country))).eq(country.to_lowercase()));
+    }
+    if let Some(min_rating) = query.min_rating {
+        select = select.filter(novels::Column::Rating.gte(min_rating));
+    }
+    if let Some(max_rating) = query.max_rating {
+        select = select.filter(novels::Column::Rating.lte(max_rating));
+    }
+    for tag in query.include_tags() {
+        select = select.filter(tags_text().like(tag_pattern(&tag)));
+    }
+    for tag in query.exclude_tags() {
+        select = select.filter(tags_text().not_like(tag_pattern(&tag)));
+    }
+    if let Some(title) = &query.title {
+        let pattern = format!("%{}%", escape_like(&title.to_lowercase()));
+        select = select.filter(Expr::expr(Func::lower(Expr::col(novels::Column::Title))).like(like_expr(pattern)));
+    }
+
+    let order = match query.direction {
+        SortDirection::Asc => Order::Asc,
+        SortDirection::Desc => Order::Desc,
+    };
+    let column = match query.sort {
+        NovelSort::Id => novels::Column::Id,
+        NovelSort::Title => novels::Column::Title,
+        NovelSort::Country => novels::Column::Country,
+        NovelSort::Rating => novels::Column::Rating,
+        NovelSort::Status => novels::Column::Status,
+        NovelSort::Provider => novels::Column::Provider,
+        NovelSort::DateModified => novels::Column::DateModified,
+        NovelSort::DateStarted => novels::Column::DateStarted,
+        NovelSort::DateCompleted => novels::Column::DateCompleted,
+    };
+
+    // ids break ties so pages are stable
+    select = select.order_by(column, order.clone());
+    if query.sort != NovelSort::Id {
+        select = select.order_by(novels::Column::Id, order);
+    }
+    select
+}
+
+// tags are stored as a json array of strings; matching on its text keeps filtering portable across databases
+fn tags_text() -> Expr {
+    Expr::expr(Expr::col(novels::Column::Tags).cast_as(Alias::new("text")))
+}
+
+fn tag_pattern(tag: &str) -> LikeExpr {
+    let quoted = serde_json::to_string(tag).expect("strings should always serialize");
+    like_expr(format!("%{}%", escape_like(&quoted)))
+}
+
+// searches are matched literally, so `%` and `_` from the user only match themselves
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// sqlite has no default escape character, so it is always spelled out
+fn like_expr(pattern: String) -> LikeExpr {
+    LikeExpr::new(pattern).escape('\\')
 }
 
 pub async fn fetch_single_novel(db: &DatabaseConnection, title: &str) -> Result<NovelEntry> {
-    let query = Novels::find()
+    let query = live_novels()
         .filter(novels::Column::Title.eq(title))
         .one(db)
         .await?;
     match query {
-        Some(model) => Ok(NovelEntry::from_model(model)),
+        Some(model) => NovelEntry::from_model(model),
         None => Err(Error::msg(format!{"Novel not found in db: {title}"}))
     }
 }
 
-pub async fn insert_novel_entries(db: &DatabaseConnection, rows: &Vec<NovelEntry>) -> Result<()>{
-    let mut to_insert = Vec::new();
-    for row in rows {
-        to_insert.push(row.to_active_model());
+pub async fn fetch_novel_by_id(db: &DatabaseConnection, id: i32) -> Result<Option<NovelEntry>> {
+    let model = live_novels()
+        .filter(novels::Column::Id.eq(id))
+        .one(db)
+        .await?;
+    model.map(NovelEntry::from_model).transpose()
+}
+
+// `expected` is the `date_modified` the caller last saw, if they want the patch to be conditional
+pub async fn patch_novel_entry(
+    db: &DatabaseConnection,
+    id: i32,
+    patch: &NovelPatch,
+    expected: Option<DateTime<Utc>>,
+    ctx: &AuditContext,
+) -> Result<Option<NovelEntry>> {
+    let Some(mut novel) = fetch_novel_by_id(db, id).await? else {
+        return Ok(None);
+    };
+    if expected.is_some_and(|expected| expected != novel.date_modified) {
+        return Err(NovelConflict { current: vec![novel] }.into());
     }
 
-    let _ = Novels::insert_many(to_insert).exec(db).await?;
+    let before = novel.clone();
+    patch.apply(&mut novel);
+    novel.date_modified = novel_entry::now();
+    validate_writes(db, std::slice::from_ref(&novel), &HashMap::from([(id, before.clone())])).await?;
+
+    let mut active_model = novel.to_active_model().reset_all();
+    active_model.id = Unchanged(id);
+    let res = Novels::update_many()
+        .set(active_model)
+        .filter(novels::Column::Id.eq(id))
+        .filter(novels::Column::DateModified.eq(before.date_modified.naive_utc()))
+        .exec(db)
+        .await?;
+
+    // someone else modified the row in between the fetch and the update
+    if res.rows_affected == 0 {
+        let current = fetch_novel_by_id(db, id).await?.into_iter().collect();
+        return Err(NovelConflict { current }.into());
+    }
+    record_change(db, ctx, Some(&before), Some(&novel)).await?;
+    Ok(Some(novel))
+}
+
+/*
+Plans and applies a restore in one transaction that holds the novels table, so a write can't land between the plan and the restore.
+Dry runs only plan; plans with conflicts, or invalid rows unless they are allowed, are rejected with the report.
+The current list is snapshotted before anything is written, so the restore itself can be undone.
+*/
+pub async fn restore_novels(
+    db: &DatabaseConnection,
+    backup: Vec<NovelEntry>,
+    options: &RestoreOptions,
+    allow_invalid: AllowInvalidRows,
+    snapshot_reason: &str,
+    ctx: &AuditContext,
+) -> Result<RestoreReport> {
+    let txn = db.begin().await?;
+    lock_novels(&txn).await?;
+
+    let existing = fetch_novel_entries(&txn).await?;
+    let mut report = backup::plan_restore(&existing, backup, options);
+
+    // merging by title can match any trashed novel, the other modes only the ones sharing an id with an added row
+    let trashed_novels = Novels::find()
+        .select_only()
+        .columns([novels::Column::Id, novels::Column::Title])
+        .filter(novels::Column::DeletedAt.is_not_null());
+    let mut trashed = Vec::new();
+    if options.mode == RestoreMode::MergeByTitle {
+        trashed = trashed_novels.into_tuple().all(&txn).await?;
+    } else {
+        let added_ids = report.added.iter().map(|novel| novel.id).collect_vec();
+        for chunk in added_ids.chunks(BATCH_SIZE) {
+            let rows: Vec<(i32, String)> = trashed_novels.clone()
+                .filter(novels::Column::Id.is_in(chunk.iter().copied()))
+                .into_tuple()
+                .all(&txn)
+                .await?;
+            trashed.extend(rows);
+        }
+    }
+    backup::plan_trash_restore(&mut report, &trashed);
+
+    if options.dry_run {
+        return Ok(report);
+    }
+    if report.has_conflicts() || (report.has_invalid_rows() && allow_invalid == AllowInvalidRows::False) {
+        return Err(RestoreRejected { report }.into());
+    }
+
+    snapshot::take_snapshot(&txn, snapshot_reason).await?;
+    apply_restore(&txn, &report, ctx).await?;
+    txn.commit().await?;
+    Ok(report)
+}
+
+// other writers wait until the transaction ends while readers carry on; sqlite only ever has one connection, so it needs no lock
+async fn lock_novels(txn: &DatabaseTransaction) -> Result<()> {
+    if txn.get_database_backend() == DbBackend::Postgres {
+        txn.execute_unprepared("LOCK TABLE novels IN EXCLUSIVE MODE").await?;
+    }
     Ok(())
 }
 
-#[allow(clippy::cast_possible_wrap)]
-pub async fn update_novel_entries(db: &DatabaseConnection, rows: &[NovelEntry], update_date_modified: UpdateDateModified) -> Result<Vec<NovelEntry>> {
-    let mut updated_novels: Vec<NovelEntry> = Vec::new();
-    for row in rows {
-        let model = Novels::find()
-            .filter(novels::Column::Id.eq(row.id))
-            .one(db)
+async fn apply_restore(txn: &DatabaseTransaction, report: &RestoreReport, ctx: &AuditContext) -> Result<()> {
+    // novels left out of a replacing backup go to the trash, so the restore can be undone novel by novel
+    let deleted_at = novel_entry::now();
+    let removed_ids = report.removed.iter().map(|novel| novel.id).collect_vec();
+    for chunk in removed_ids.chunks(BATCH_SIZE) {
+        Novels::update_many()
+            .col_expr(novels::Column::DeletedAt, Expr::value(deleted_at.naive_utc()))
+            .filter(novels::Column::Id.is_in(chunk.iter().copied()))
+            .exec(txn)
             .await?;
+    }
+    for row in &report.removed {
+        record_trash(txn, ctx, row, None, Some(deleted_at)).await?;
+    }
+
+    for row in &report.updated {
+        let before = Novels::find_by_id(row.id).one(txn).await?.map(NovelEntry::from_model).transpose()?;
+        let mut active_model = row.to_active_model().reset_all();
+        active_model.id = Unchanged(row.id);
+        active_model.update(txn).await?;
+        record_change(txn, ctx, before.as_ref(), Some(row)).await?;
+    }
+
+    // trashed novels are untrashed and overwritten, and their history shows both
+    for row in &report.restored {
+        let Some(trashed) = Novels::find_by_id(row.id).one(txn).await?.map(trashed_novel).transpose()?.flatten() else {
+            return Err(Error::msg(format!("Novel is no longer in the trash: {}", row.id)));
+        };
+        let mut active_model = row.to_active_model().reset_all();
+        active_model.id = Unchanged(row.id);
+        active_model.deleted_at = Set(None);
+        active_model.update(txn).await?;
+        record_trash(txn, ctx, &trashed.novel, Some(trashed.deleted_at), None).await?;
+        record_change(txn, ctx, Some(&trashed.novel), Some(row)).await?;
+    }
+
+    if !report.added.is_empty() {
+        let to_insert = report.added.iter().map(NovelEntry::to_active_model).collect_vec();
+        Novels::insert_many(to_insert).exec(txn).await?;
+    }
+    for row in &report.added {
+        record_change(txn, ctx, None, Some(row)).await?;
+    }
+    if !report.added.is_empty() {
+        sync_novel_id_sequence(txn).await?;
+    }
+    Ok(())
+}
+
+// rows are written in one transaction with batched upserts; a conflict or error leaves nothing applied
+pub async fn update_novel_entries<C: ConnectionTrait + TransactionTrait>(
+    db: &C,
+    rows: &[NovelEntry],
+    update_date_modified: UpdateDateModified,
+    check_conflicts: CheckConflicts,
+    ctx: &AuditContext,
+) -> Result<Vec<UpsertedNovel>> {
+    let txn = db.begin().await?;
 
-        if model.is_some() {
-            // create the active model; everything should be updated already except for date_modified
-            // date_modified is handeled by the backend to ensure time consistency
-            let mut active_model = row.to_active_model().reset_all();
-            if update_date_modified == UpdateDateModified::True {
-                active_model.date_modified = Set(Local::now().naive_utc());
+    // lock the existing rows so nobody can modify them between the conflict check and the upsert
+    let mut existing = HashMap::new();
+    let mut trashed = Vec::new();
+    for chunk in rows.chunks(BATCH_SIZE) {
+        let ids = chunk.iter().map(|row| row.id).collect_vec();
+        let models = Novels::find()
+            .filter(novels::Column::Id.is_in(ids))
+            .lock_exclusive()
+            .all(&txn)
+            .await?;
+        for model in models {
+            if model.deleted_at.is_some() {
+                trashed.push(model.id);
+            } else {
+                existing.insert(model.id, NovelEntry::from_model(model)?);
             }
-            active_model.id = Unchanged(row.id);
+        }
+    }
+    if !trashed.is_empty() {
+        trashed.sort();
+        trashed.dedup();
+        return Err(NovelsInTrash { ids: trashed }.into());
+    }
+    let rows = dedup_existing(rows, &existing);
 
-            // update the results
-            updated_novels.push(NovelEntry::from_model(active_model.clone().try_into_model()?));
+    if check_conflicts == CheckConflicts::True {
+        let conflicts = rows.iter()
+            .filter_map(|row| existing.get(&row.id).filter(|current| current.date_modified != row.date_modified))
+            .cloned()
+            .collect_vec();
+        if !conflicts.is_empty() {
+            return Err(NovelConflict { current: conflicts }.into());
+        }
+    }
+
+    let mut results = Vec::new();
+    for row in rows {
+        let current = existing.get(&row.id);
+        let outcome = match current {
+            Some(current) if *current == row => RowOutcome::Unchanged,
+            Some(_) => RowOutcome::Updated,
+            None => RowOutcome::Inserted,
+        };
 
-            // update the database 
-            active_model.update(db).await?;
+        // date_modified is handeled by the backend to ensure time consistency
+        let mut novel = row;
+        if outcome == RowOutcome::Updated && update_date_modified == UpdateDateModified::True {
+            novel.date_modified = novel_entry::now();
         }
-        else {
-            updated_novels.push(row.clone());
-            Novels::insert(row.to_active_model()).exec(db).await?;
+        results.push(UpsertedNovel { novel, outcome });
+    }
+
+    let changed = results.iter().filter(|row| row.outcome != RowOutcome::Unchanged).map(|row| row.novel.clone()).collect_vec();
+    validate_writes(&txn, &changed, &existing).await?;
+    let updated = results.iter().filter(|row| row.outcome == RowOutcome::Updated).map(|row| &row.novel).collect_vec();
+    upsert_novels(&txn, &updated).await?;
+
+    insert_new_novels(&txn, &mut results).await?;
+    let changes = results.iter()
+        .filter(|row| row.outcome != RowOutcome::Unchanged)
+        .map(|row| (existing.get(&row.novel.id).filter(|_| row.outcome == RowOutcome::Updated), Some(&row.novel)))
+        .collect_vec();
+    record_changes(&txn, ctx, &changes).await?;
+
+    txn.commit().await?;
+    Ok(results)
+}
+
+// later rows for an existing id win, since upserting the same id twice in one statement is an error;
+// every other row is a new novel and gets its own id, whatever id it was sent with
+fn dedup_existing(rows: &[NovelEntry], existing: &HashMap<i32, NovelEntry>) -> Vec<NovelEntry> {
+    let mut positions: HashMap<i32, usize> = HashMap::new();
+    let mut deduped: Vec<NovelEntry> = Vec::new();
+    for row in rows {
+        match positions.get(&row.id) {
+            Some(&position) => deduped[position] = row.clone(),
+            None => {
+                if existing.contains_key(&row.id) {
+                    positions.insert(row.id, deduped.len());
+                }
+                deduped.push(row.clone());
+            },
         }
     }
+    deduped
+}
 
-    Ok(updated_novels)
+// new rows take their ids from the column default, whatever id the caller sent
+async fn insert_new_novels<C: ConnectionTrait>(db: &C, results: &mut [UpsertedNovel]) -> Result<()> {
+    let mut inserted = results.iter_mut().filter(|row| row.outcome == RowOutcome::Inserted).collect_vec();
+    for chunk in inserted.chunks_mut(BATCH_SIZE) {
+        let models = chunk.iter().map(|row| {
+            let mut model = row.novel.to_active_model();
+            model.id = NotSet;
+            model
+        });
+        // the ids are drawn in insertion order, so sorting them hands each row its own
+        let ids = Novels::insert_many(models)
+            .exec_with_returning_many(db)
+            .await?
+            .into_iter()
+            .map(|model| model.id)
+            .sorted();
+        for (row, id) in chunk.iter_mut().zip(ids) {
+            row.novel.id = id;
+        }
+    }
+    Ok(())
 }
 
-pub async fn update_novel_tags(db: &DatabaseConnection, rows: &[NovelTagsRecordParsed]) -> Result<()> {
-    let mut novels_updated = 0;
+// `deleted_at` is left out so upserts never move a novel in or out of the trash
+async fn upsert_novels<C: ConnectionTrait>(db: &C, rows: &[&NovelEntry]) -> Result<()> {
+    for chunk in rows.chunks(BATCH_SIZE) {
+        let models = chunk.iter().map(|row| row.to_active_model());
+        let on_conflict = OnConflict::column(novels::Column::Id)
+            .update_columns([
+                novels::Column::Country,
+                novels::Column::Title,
+                novels::Column::Chapter,
+                novels::Column::Rating,
+                novels::Column::Status,
+                novels::Column::Tags,
+                novels::Column::Notes,
+                novels::Column::Provider,
+                novels::Column::DateModified,
+                novels::Column::DateStarted,
+                novels::Column::DateCompleted,
+            ])
+            .to_owned();
+        Novels::insert_many(models)
+            .on_conflict(on_conflict)
+            .exec_without_returning(db)
+            .await?;
+    }
+    Ok(())
+}
 
-    for row in rows {
-        let model = Novels::find()
-            .filter(novels::Column::Title.eq(&row.title))
-            .one(db)
+// merges csv tags into the novels with matching titles in one transaction
+pub async fn update_novel_tags(db: &DatabaseConnection, rows: &[NovelTagsRecordParsed], ctx: &AuditContext) -> Result<Vec<TagsUpdate>> {
+    let txn = db.begin().await?;
+
+    let titles = rows.iter().map(|row| row.title.as_str()).unique().collect_vec();
+    let mut by_title: HashMap<String, NovelEntry> = HashMap::new();
+    for chunk in titles.chunks(BATCH_SIZE) {
+        let models = live_novels()
+            .filter(novels::Column::Title.is_in(chunk.iter().copied()))
+            .order_by_asc(novels::Column::Id)
+            .lock_exclusive()
+            .all(&txn)
             .await?;
-        
-        if let Some(model) = model {
-            novels_updated += 1;
+        for model in models {
+            if !by_title.contains_key(&model.title) {
+                by_title.insert(model.title.clone(), NovelEntry::from_model(model)?);
+            }
+        }
+    }
+    let originals = by_title.clone();
+
+    let mut results = Vec::new();
+    for row in rows {
+        let outcome = match by_title.get_mut(&row.title) {
+            Some(novel) => {
+                // add the new tags to the old ones and make them unique
+                let new_tags = novel.tags.iter().chain(&row.tags).unique().cloned().collect_vec();
+                if new_tags == novel.tags {
+                    RowOutcome::Unchanged
+                } else {
+                    novel.tags = new_tags;
+                    RowOutcome::Updated
+                }
+            },
+            None => RowOutcome::NotFound,
+        };
+        results.push(TagsUpdate { title: row.title.clone(), outcome });
+    }
+
+    let changed = by_title.values()
+        .filter(|novel| originals.get(&novel.title) != Some(*novel))
+        .collect_vec();
+    let before = originals.values().map(|novel| (novel.id, novel.clone())).collect();
+    validate_writes(&txn, &changed.iter().copied().cloned().collect_vec(), &before).await?;
+    upsert_novels(&txn, &changed).await?;
+    let changes = changed.iter().map(|novel| (originals.get(&novel.title), Some(*novel))).collect_vec();
+    record_changes(&txn, ctx, &changes).await?;
+
+    txn.commit().await?;
+    tracing::info!("Updated {} novels", changed.len());
+    Ok(results)
+}
+
+pub async fn drop_all_novels(db: &DatabaseConnection, ctx: &AuditContext) -> Result<()> {
+    let txn = db.begin().await?;
+    let dropped = Novels::find().all(&txn).await?;
+    let _ = Novels::delete_many().exec(&txn).await?;
+    for model in dropped {
+        record_change(&txn, ctx, Some(&NovelEntry::from_model(model)?), None).await?;
+    }
+    txn.commit().await?;
+    Ok(())
+}
+
+// moves a novel to the trash; returns whether a novel was actually trashed
+pub async fn trash_novel_entry(db: &DatabaseConnection, id: i32, ctx: &AuditContext) -> Result<bool> {
+    let Some(novel) = fetch_novel_by_id(db, id).await? else {
+        return Ok(false);
+    };
+    let deleted_at = novel_entry::now();
+    let res = Novels::update_many()
+        .col_expr(novels::Column::DeletedAt, Expr::value(deleted_at.naive_utc()))
+        .filter(novels::Column::Id.eq(id))
+        .filter(novels::Column::DeletedAt.is_null())
+        .exec(db)
+        .await?;
+    if res.rows_affected > 0 {
+        record_trash(db, ctx, &novel, None, Some(deleted_at)).await?;
+    }
+    Ok(res.rows_affected > 0)
+}
+
+// most recently trashed first
+pub async fn fetch_trashed_novels(db: &DatabaseConnection) -> Result<Vec<TrashedNovel>> {
+    let models = Novels::find()
+        .filter(novels::Column::DeletedAt.is_not_null())
+        .order_by_desc(novels::Column::DeletedAt)
+        .all(db)
+        .await?;
+    let trashed = models.into_iter()
+        .filter_map(|model| trashed_novel(model).inspect_err(|e| tracing::warn!("Skipping trashed novel: {e}")).ok().flatten())
+        .collect();
+    Ok(trashed)
+}
+
+fn trashed_novel(model: novels::Model) -> Result<Option<TrashedNovel>> {
+    let Some(deleted_at) = model.deleted_at else {
+        return Ok(None);
+    };
+    Ok(Some(TrashedNovel { novel: NovelEntry::from_model(model)?, deleted_at: deleted_at.and_utc() }))
+}
+
+// takes a novel back out of the trash; none if it wasn't trashed
+pub async fn restore_trashed_novel<C: ConnectionTrait>(db: &C, id: i32, ctx: &AuditContext) -> Result<Option<NovelEntry>> {
+    let Some(trashed) = Novels::find_by_id(id).one(db).await?.map(trashed_novel).transpose()?.flatten() else {
+        return Ok(None);
+    };
+    let res = Novels::update_many()
+        .col_expr(novels::Column::DeletedAt, Expr::value(Option::<NaiveDateTime>::None))
+        .filter(novels::Column::Id.eq(id))
+        .filter(novels::Column::DeletedAt.is_not_null())
+        .exec(db)
+        .await?;
+    if res.rows_affected == 0 {
+        return Ok(None);
+    }
+    record_trash(db, ctx, &trashed.novel, Some(trashed.deleted_at), None).await?;
+    Ok(Some(trashed.novel))
+}
+
+// permanently deletes novels trashed before `cutoff` and returns how many were purged
+pub async fn purge_trash(db: &DatabaseConnection, cutoff: DateTime<Utc>, ctx: &AuditContext) -> Result<u64> {
+    let txn = db.begin().await?;
+    let expired = Novels::find()
+        .filter(novels::Column::DeletedAt.lt(cutoff.naive_utc()))
+        .all(&txn)
+        .await?;
+    let expired_ids = expired.iter().map(|model| model.id).collect_vec();
+    if expired_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let res = Novels::delete_many()
+        .filter(novels::Column::Id.is_in(expired_ids))
+        .exec(&txn)
+        .await?;
+    for model in expired {
+        record_change(&txn, ctx, Some(&NovelEntry::from_model(model)?), None).await?;
+    }
+    txn.commit().await?;
+    Ok(res.rows_affected)
+}
+
+/*
+Recreates a novel that was deleted for good under its old id, so its history stays attached.
+Returns false without writing anything if a novel with the id is still stored, trashed or not.
+*/
+pub async fn recreate_novel(txn: &DatabaseTransaction, novel: &NovelEntry, ctx: &AuditContext) -> Result<bool> {
+    if Novels::find_by_id(novel.id).one(txn).await?.is_some() {
+        return Ok(false);
+    }
+    lock_novels(txn).await?;
+
+    validate_writes(txn, std::slice::from_ref(novel), &HashMap::new()).await?;
+    novel.to_active_model().insert(txn).await?;
+    sync_novel_id_sequence(txn).await?;
+    record_change(txn, ctx, None, Some(novel)).await?;
+    Ok(true)
+}
+
+// the id is taken before the insert, so novels created without a title are named after it and validated before anything is written
+pub async fn create_empty_row(db: &DatabaseConnection, initial: &NovelPatch, ctx: &AuditContext) -> Result<NovelEntry> {
+    let txn = db.begin().await?;
+
+    let id = next_novel_id(&txn).await?;
+    let mut novel = NovelEntry::empty(id);
+    initial.apply(&mut novel);
+    if initial.title.is_none() {
+        novel.title = format!("Untitled {id}");
+    }
+    validate_writes(&txn, std::slice::from_ref(&novel), &HashMap::new()).await?;
+
+    novel.to_active_model().insert(&txn).await?;
+    record_change(&txn, ctx, None, Some(&novel)).await?;
+    txn.commit().await?;
+    Ok(novel)
+}
+
+// postgres hands out the id from the novels id sequence; sqlite's one connection means nobody can insert in between
+async fn next_novel_id(txn: &DatabaseTransaction) -> Result<i32> {
+    let query = match txn.get_database_backend() {
+        DbBackend::Postgres => "SELECT CAST(nextval('novels_id_seq') AS integer) AS id",
+        _ => "SELECT CAST(COALESCE(MAX(id), 0) + 1 AS integer) AS id FROM novels",
+    };
+    let row = txn.query_one(Statement::from_string(txn.get_database_backend(), query)).await?
+        .ok_or_else(|| Error::msg("The next novel id query returned nothing"))?;
+    Ok(row.try_get("", "id")?)
+}
+
+// every stored novel's status, provider and rating, read as text so this works before m20261018_121530_novel_enums; see doctor.rs
+pub async fn fetch_stored_columns(db: &DatabaseConnection) -> Result<Vec<StoredColumns>> {
+    let query = "SELECT id, title, CAST(status AS text) AS status, CAST(provider AS text) AS provider, rating FROM novels ORDER BY id";
+    let statement = Statement::from_string(db.get_database_backend(), query);
+    Ok(StoredColumns::find_by_statement(statement).all(db).await?)
+}
+
+/*
+Writes the column fixes doctor found in one transaction, touching only the fixed columns.
+The rows may not be readable yet, so the audit entries only record the changes and have no version to revert to.
+*/
+pub async fn repair_stored_columns(db: &DatabaseConnection, repairs: &[ColumnRepair], ctx: &AuditContext) -> Result<()> {
+    let txn = db.begin().await?;
+    let mut audit_models = Vec::new();
+    for repair in repairs {
+        let mut update = Query::update();
+        update.table(Novels).and_where(Expr::col(novels::Column::Id).eq(repair.id));
+        let mut changes = serde_json::Map::new();
+        for problem in &repair.problems {
+            let value = match &problem.fix {
+                JsonValue::Number(rating) => Expr::val(rating.as_i64().and_then(|rating| i32::try_from(rating).ok())),
+                fix => Expr::val(fix.as_str().map(ToOwned::to_owned)),
+            };
+            update.value(Alias::new(&problem.field), value);
+            changes.insert(problem.field.clone(), serde_json::json!({ "before": problem.value, "after": problem.fix }));
+        }
+        txn.execute(txn.get_database_backend().build(&update)).await?;
+        audit_models.push(audit_model(ctx, repair.id, AuditAction::Update, changes, None)?);
+    }
+    insert_audit_models(&txn, audit_models).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+// every stored novel including trashed ones, without decoding them; see doctor.rs
+pub async fn fetch_novel_models(db: &DatabaseConnection) -> Result<Vec<novels::Model>> {
+    Ok(Novels::find().order_by_asc(novels::Column::Id).all(db).await?)
+}
 
-            // add the new tags to the old ones
-            let mut new_tags: Vec<String> = from_value(model.tags.clone()).expect("update_novel_tags: JSON value is not an array");
-            new_tags.extend(row.tags.clone());
+// writes the rows doctor fixed in one transaction; the stored values can't be decoded, so the audit entries use them as is
+pub async fn repair_novels(db: &DatabaseConnection, repairs: &[Repair], ctx: &AuditContext) -> Result<()> {
+    let txn = db.begin().await?;
+    let mut audit_models = Vec::new();
+    for repair in repairs {
+        let mut active_model = repair.fixed.clone().into_active_model().reset_all();
+        active_model.id = Unchanged(repair.id);
+        active_model.deleted_at = NotSet;
+        active_model.update(&txn).await?;
 
-            // make tags unique
-            new_tags = new_tags.into_iter().unique().collect();
+        let mut changes = serde_json::Map::new();
+        for problem in &repair.problems {
+            changes.insert(problem.field.clone(), serde_json::json!({ "before": problem.value, "after": problem.fix }));
+        }
+        let novel = NovelEntry::from_model(repair.fixed.clone())?;
+        audit_models.push(audit_model(ctx, repair.id, AuditAction::Update, changes, Some(&novel))?);
+    }
+    insert_audit_models(&txn, audit_models).await?;
+    txn.commit().await?;
+    Ok(())
+}
 
-            // update the model
-            let mut active_model = model.into_active_model();
-            active_model.tags = Set(serde_json::to_value(new_tags)?);
-            active_model.update(db).await?;
+// rejects the write if any row breaks the rules in validation.rs
+async fn validate_writes<C: ConnectionTrait>(db: &C, rows: &[NovelEntry], before: &HashMap<i32, NovelEntry>) -> Result<()> {
+    // only novels holding one of the new titles can collide with the rows
+    let titles = rows.iter()
+        .filter(|row| before.get(&row.id).is_none_or(|before| title_key(&before.title) != title_key(&row.title)))
+        .map(|row| title_key(&row.title))
+        .unique()
+        .collect_vec();
+    let mut others = Vec::new();
+    for chunk in titles.chunks(BATCH_SIZE) {
+        let title = Func::lower(Func::cust(Alias::new("TRIM")).arg(Expr::col(novels::Column::Title)));
+        let models = live_novels()
+            .filter(Expr::expr(title).is_in(chunk.iter().cloned()))
+            .all(db)
+            .await?;
+        for model in models {
+            others.push(NovelEntry::from_model(model)?);
         }
     }
-    println!("Updated {novels_updated} novels");
 
+    validation::validate_novels(rows, before, &others)?;
     Ok(())
 }
 
-pub async fn drop_all_novels(db: &DatabaseConnection) -> Result<()> {
-    let _ = Novels::delete_many().exec(db).await?;
+/*
+Rows inserted with explicit ids (restores, recreated novels) don't advance the id sequence, so it is moved just past them.
+This runs once at the end of a transaction holding the novels lock, so no insert can take an id from the sequence in between.
+The next id is the larger of the one after the largest id and the one the sequence would hand out anyway, so no id is skipped.
+*/
+async fn sync_novel_id_sequence(txn: &DatabaseTransaction) -> Result<()> {
+    // sqlite's autoincrement already continues from the largest id
+    if txn.get_database_backend() != DbBackend::Postgres {
+        return Ok(());
+    }
+
+    // see m20261018_103512_novels_id_sequence
+    let query = "SELECT setval('novels_id_seq', GREATEST(\
+        (SELECT COALESCE(MAX(id), 0) + 1 FROM novels), \
+        (SELECT CASE WHEN is_called THEN last_value + 1 ELSE last_value END FROM novels_id_seq)), false);";
+    txn.execute(Statement::from_string(DbBackend::Postgres, query)).await?;
     Ok(())
 }
 
-pub async fn delete_novel_entry(db: &DatabaseConnection, id: i32) -> Result<()> {
-    let _ = Novels::delete_by_id(id).exec(db).await?;
+// records one change to a novel; `before` is none for inserts and `after` is none for deletes
+async fn record_change<C: ConnectionTrait>(db: &C, ctx: &AuditContext, before: Option<&NovelEntry>, after: Option<&NovelEntry>) -> Result<()> {
+    record_changes(db, ctx, &[(before, after)]).await
+}
+
+async fn record_changes<C: ConnectionTrait>(db: &C, ctx: &AuditContext, changes: &[(Option<&NovelEntry>, Option<&NovelEntry>)]) -> Result<()> {
+    let mut models = Vec::new();
+    for &(before, after) in changes {
+        let Some(action) = audit::audit_action(before, after) else {
+            continue;
+        };
+        let diff = audit::diff_novels(before, after);
+        if diff.is_empty() {
+            continue;
+        }
+        let novel_id = after.or(before).map_or(0, |novel| novel.id);
+        models.push(audit_model(ctx, novel_id, action, diff, after)?);
+    }
+    insert_audit_models(db, models).await
+}
+
+// trashing only touches `deleted_at`, which isn't part of a novel entry; trashed novels have no version to revert to
+async fn record_trash<C: ConnectionTrait>(
+    db: &C,
+    ctx: &AuditContext,
+    novel: &NovelEntry,
+    before: Option<DateTime<Utc>>,
+    after: Option<DateTime<Utc>>,
+) -> Result<()> {
+    let action = if after.is_some() { AuditAction::Trash } else { AuditAction::Restore };
+    let mut changes = serde_json::Map::new();
+    changes.insert("deleted_at".to_string(), serde_json::json!({ "before": before, "after": after }));
+    let data = if after.is_some() { None } else { Some(novel) };
+    insert_audit_models(db, vec![audit_model(ctx, novel.id, action, changes, data)?]).await
+}
+
+fn audit_model(
+    ctx: &AuditContext,
+    novel_id: i32,
+    action: AuditAction,
+    changes: serde_json::Map<String, JsonValue>,
+    data: Option<&NovelEntry>,
+) -> Result<audit_log::ActiveModel> {
+    Ok(audit_log::ActiveModel {
+        novel_id: Set(novel_id),
+        action: Set(action.to_string()),
+        changes: Set(JsonValue::Object(changes)),
+        data: Set(data.map(serde_json::to_value).transpose()?),
+        actor: Set(ctx.actor.clone()),
+        source: Set(ctx.source.to_string()),
+        created_at: Set(novel_entry::now().naive_utc()),
+        ..Default::default()
+    })
+}
+
+async fn insert_audit_models<C: ConnectionTrait>(db: &C, models: Vec<audit_log::ActiveModel>) -> Result<()> {
+    for chunk in models.chunks(BATCH_SIZE) {
+        AuditLog::insert_many(chunk.to_vec()).exec_without_returning(db).await?;
+    }
     Ok(())
 }
 
-pub async fn create_empty_row(db: &DatabaseConnection) -> Result<NovelEntry> {
-    // only allow one thread to create an empty row at a time
-    static LOCK: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
-    let _guard = LOCK.lock().await;
+// newest first
+pub async fn fetch_novel_history(db: &DatabaseConnection, novel_id: i32) -> Result<Vec<AuditEntry>> {
+    let models = AuditLog::find()
+        .filter(audit_log::Column::NovelId.eq(novel_id))
+        .order_by_desc(audit_log::Column::Id)
+        .all(db)
+        .await?;
+    models.into_iter().map(AuditEntry::from_model).collect()
+}
+
+pub async fn fetch_audit_entry(db: &DatabaseConnection, id: i32) -> Result<Option<AuditEntry>> {
+    let model = AuditLog::find_by_id(id)
+        .one(db)
+        .await?;
+    model.map(AuditEntry::from_model).transpose()
+}
+
+pub async fn insert_token(db: &DatabaseConnection, name: &str, token_hash: &str, scopes: &[Scope]) -> Result<tokens::Model> {
+    let model = tokens::ActiveModel {
+        name: Set(name.to_string()),
+        token_hash: Set(token_hash.to_string()),
+        scopes: Set(serde_json::to_value(scopes)?),
+        created_at: Set(Local::now().naive_utc()),
+        revoked_at: Set(None),
+        ..Default::default()
+    };
+    Ok(model.insert(db).await?)
+}
+
+pub async fn fetch_tokens(db: &DatabaseConnection) -> Result<Vec<tokens::Model>> {
+    let models = Tokens::find()
+        .order_by_asc(tokens::Column::Id)
+        .all(db)
+        .await?;
+    Ok(models)
+}
 
-    // we manually create the next id because I screwed up the postgres settings and autoincrement doesn't work
-    let curr_max_id = Novels::find()
-        .order_by_desc(novels::Column::Id)
+pub async fn fetch_active_token(db: &DatabaseConnection, token_hash: &str) -> Result<Option<tokens::Model>> {
+    let model = Tokens::find()
+        .filter(tokens::Column::TokenHash.eq(token_hash))
+        .filter(tokens::Column::RevokedAt.is_null())
         .one(db)
         .await?;
-    let next_id = match curr_max_id {
-        Some(model) => model.id + 1,
-        None => 1,
+    Ok(model)
+}
+
+pub async fn revoke_token(db: &DatabaseConnection, id: i32) -> Result<()> {
+    let Some(model) = Tokens::find_by_id(id).one(db).await? else {
+        return Err(Error::msg(format!("Token not found in db: {id}")));
     };
 
-    let novel = NovelEntry::empty(next_id);
-    let model = novel.to_active_model();
-    let _ = model.insert(db).await?;
-    Ok(novel)
+    let mut active_model = model.into_active_model();
+    active_model.revoked_at = Set(Some(Local::now().naive_utc()));
+    active_model.update(db).await?;
+    Ok(())
+}
+
+pub async fn insert_snapshot<C: ConnectionTrait>(db: &C, reason: &str, backup: &Backup) -> Result<SnapshotInfo> {
+    let model = snapshots::ActiveModel {
+        created_at: Set(backup.exported_at.naive_utc()),
+        reason: Set(reason.to_string()),
+        novel_count: Set(i32::try_from(backup.novels.len())?),
+        data: Set(serde_json::to_value(backup)?),
+        ..Default::default()
+    };
+    let model = model.insert(db).await?;
+    Ok(SnapshotInfo {
+        id: model.id,
+        created_at: model.created_at.and_utc(),
+        reason: model.reason,
+        novel_count: model.novel_count,
+    })
+}
+
+// newest first; the data column is left out since it holds the whole novel list
+pub async fn fetch_snapshot_infos(db: &DatabaseConnection) -> Result<Vec<SnapshotInfo>> {
+    let rows: Vec<(i32, NaiveDateTime, String, i32)> = Snapshots::find()
+        .select_only()
+        .columns([
+            snapshots::Column::Id,
+            snapshots::Column::CreatedAt,
+            snapshots::Column::Reason,
+            snapshots::Column::NovelCount,
+        ])
+        .order_by_desc(snapshots::Column::Id)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let infos = rows.into_iter()
+        .map(|(id, created_at, reason, novel_count)| SnapshotInfo { id, created_at: created_at.and_utc(), reason, novel_count })
+        .collect();
+    Ok(infos)
+}
+
+pub async fn fetch_snapshot_data(db: &DatabaseConnection, id: i32) -> Result<Option<JsonValue>> {
+    let model = Snapshots::find_by_id(id)
+        .one(db)
+        .await?;
+    Ok(model.map(|model| model.data))
+}
+
+// keeps only the newest `keep` snapshots and returns how many were deleted
+pub async fn prune_snapshots<C: ConnectionTrait>(db: &C, keep: u64) -> Result<u64> {
+    // sqlite doesn't allow an offset without a limit, so skip the newest ids here instead
+    let ids: Vec<i32> = Snapshots::find()
+        .select_only()
+        .column(snapshots::Column::Id)
+        .order_by_desc(snapshots::Column::Id)
+        .into_tuple()
+        .all(db)
+        .await?;
+    let stale_ids = ids.into_iter().skip(usize::try_from(keep)?).collect_vec();
+    if stale_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let res = Snapshots::delete_many()
+        .filter(snapshots::Column::Id.is_in(stale_ids))
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected)
 }
 
 #[cfg(test)]
@@ -187,10 +1108,416 @@ mod tests {
     use super::*;
     use dotenv::dotenv;
 
+    use crate::audit::{AuditAction, AuditSource};
+    use crate::chapter::Chapter;
+    use crate::config::Config;
+    use crate::novel_entry::{Provider, Status};
+    use sea_orm::QueryTrait;
+
+    fn ctx() -> AuditContext {
+        AuditContext::new("tester", AuditSource::Cli)
+    }
+
+    fn patch(title: &str) -> NovelPatch {
+        NovelPatch { title: Some(title.into()), ..Default::default() }
+    }
+
+    fn novel(id: i32, title: &str, tags: &[&str]) -> NovelEntry {
+        NovelEntry {
+            title: title.into(),
+            chapter: Chapter::from("c12"),
+            tags: tags.iter().map(ToString::to_string).collect(),
+            ..NovelEntry::empty(id)
+        }
+    }
+
+    async fn titles(db: &DatabaseConnection) -> Vec<String> {
+        fetch_novel_entries(db).await.unwrap().into_iter().map(|novel| novel.title).sorted().collect()
+    }
+
     #[tokio::test]
     #[ignore = "requires DB setup"]
     async fn test_init() {
         dotenv().ok();
-        init().await.unwrap();
+        let config = Config::load(None).unwrap();
+        connect(&config.database_url, &config.pool).await.unwrap();
+    }
+
+    fn sus_tags() -> Vec<String> {
+        Config::default().sus_tags
+    }
+
+    fn to_sql(query: &NovelQuery) -> String {
+        novel_select(query, &sus_tags()).build(DbBackend::Postgres).to_string()
+    }
+
+    #[test]
+    fn default_novel_select() {
+        let sql = to_sql(&NovelQuery::default());
+        assert!(sql.contains(r#"WHERE "novels"."deleted_at" IS NULL ORDER BY"#));
+        assert!(sql.ends_with(r#"ORDER BY "novels"."id" ASC"#));
+    }
+
+    #[test]
+    fn filtered_novel_select() {
+        let query = NovelQuery {
+            status: Some(Status::Reading),
+            min_rating: Some(7),
+            tags: Some("Fantasy,Magic".into()),
+            exclude_tags: Some("Harem".into()),
+            title: Some("Lord".into()),
+            sort: NovelSort::Rating,
+            direction: SortDirection::Desc,
+            ..Default::default()
+        };
+        let sql = to_sql(&query);
+        assert!(sql.contains(r#""novels"."status" = (CAST('Reading' AS "novel_status")"#));
+        assert!(sql.contains(r#""novels"."rating" >= 7"#));
+        assert!(sql.contains(r#"LIKE E'%\"Fantasy\"%'"#));
+        assert!(sql.contains(r#"LIKE E'%\"Magic\"%'"#));
+        assert!(sql.contains(r#"NOT LIKE E'%\"Harem\"%'"#));
+        assert!(sql.contains(r#"LOWER("title") LIKE '%lord%'"#));
+        assert!(sql.ends_with(r#"ORDER BY "novels"."rating" DESC, "novels"."id" DESC"#));
+    }
+
+    #[test]
+    fn not_sus_novel_select() {
+        let query = NovelQuery { subset: Some(NovelSubsets::NotSus), ..Default::default() };
+        let sql = to_sql(&query);
+        assert!(sql.contains("<> '[]'"));
+        for tag in sus_tags() {
+            assert!(sql.contains(&format!(r#"NOT LIKE E'%\"{tag}\"%'"#)));
+        }
+    }
+
+    #[tokio::test]
+    async fn create_and_patch() {
+        let db = memory_db().await;
+        let one = create_empty_row(&db, &patch("One"), &ctx()).await.unwrap();
+        let two = create_empty_row(&db, &patch("Two"), &ctx()).await.unwrap();
+        assert_eq!((one.id, two.id), (1, 2));
+        assert_eq!(fetch_single_novel(&db, "Two").await.unwrap(), two);
+
+        let rating = NovelPatch { rating: Some(9), ..Default::default() };
+        let patched = patch_novel_entry(&db, one.id, &rating, Some(one.date_modified), &ctx()).await.unwrap().unwrap();
+        assert_eq!(patched.rating, 9);
+        assert_eq!(fetch_novel_by_id(&db, one.id).await.unwrap(), Some(patched.clone()));
+
+        // the first patch already moved date_modified on
+        let err = patch_novel_entry(&db, one.id, &rating, Some(one.date_modified), &ctx()).await.unwrap_err();
+        assert_eq!(err.downcast::<NovelConflict>().unwrap().current, [patched]);
+        assert!(patch_novel_entry(&db, 42, &rating, None, &ctx()).await.unwrap().is_none());
+
+        let history = fetch_novel_history(&db, one.id).await.unwrap();
+        assert_eq!(history.iter().map(|entry| entry.action).collect_vec(), [AuditAction::Update, AuditAction::Insert]);
+        assert_eq!(history[0].changes.keys().collect_vec(), ["date_modified", "rating"]);
+        assert_eq!(history[0].actor, "tester");
+    }
+
+    #[tokio::test]
+    async fn create_validates_before_inserting() {
+        let db = memory_db().await;
+        let one = create_empty_row(&db, &patch("One"), &ctx()).await.unwrap();
+
+        // an invalid novel writes neither a row nor an audit entry
+        let err = create_empty_row(&db, &patch(" one "), &ctx()).await.unwrap_err();
+        assert!(err.is::<validation::InvalidNovels>());
+        let rating = NovelPatch { rating: Some(11), ..Default::default() };
+        assert!(create_empty_row(&db, &rating, &ctx()).await.unwrap_err().is::<validation::InvalidNovels>());
+        assert_eq!(fetch_novel_entries(&db).await.unwrap(), [one]);
+        assert!(fetch_novel_history(&db, 2).await.unwrap().is_empty());
+
+        // an untitled novel is inserted under its final name with a single audit entry
+        let untitled = create_empty_row(&db, &NovelPatch::default(), &ctx()).await.unwrap();
+        assert_eq!((untitled.id, untitled.title.as_str()), (2, "Untitled 2"));
+        let history = fetch_novel_history(&db, untitled.id).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].data, Some(untitled));
+    }
+
+    #[tokio::test]
+    async fn stored_values_are_constrained() {
+        let db = memory_db().await;
+        let one = create_empty_row(&db, &patch("One"), &ctx()).await.unwrap();
+
+        // even writes that skip NovelEntry can't store an unknown status or provider, or an out of range rating
+        for set in ["status = 'Invalid'", "provider = 'Webnovel'", "rating = 11", "rating = -1"] {
+            let sql = format!("UPDATE novels SET {set} WHERE id = {}", one.id);
+            assert!(db.execute_unprepared(&sql).await.is_err(), "{set}");
+        }
+        db.execute_unprepared("UPDATE novels SET status = 'Reading', provider = 'RoyalRoad', rating = 10").await.unwrap();
+        let novel = fetch_novel_by_id(&db, one.id).await.unwrap().unwrap();
+        assert_eq!((novel.status, novel.provider, novel.rating), (Some(Status::Reading), Some(Provider::RoyalRoad), 10));
+    }
+
+    #[tokio::test]
+    async fn batch_update_outcomes() {
+        let db = memory_db().await;
+        let one = create_empty_row(&db, &patch("One"), &ctx()).await.unwrap();
+        let two = create_empty_row(&db, &patch("Two"), &ctx()).await.unwrap();
+
+        let rows = [NovelEntry { rating: 7, ..one.clone() }, two.clone(), novel(10, "Ten", &[])];
+        let results = update_novel_entries(&db, &rows, UpdateDateModified::True, CheckConflicts::True, &ctx()).await.unwrap();
+        let outcomes = results.iter().map(|row| row.outcome).collect_vec();
+        assert_eq!(outcomes, [RowOutcome::Updated, RowOutcome::Unchanged, RowOutcome::Inserted]);
+        assert!(results[0].novel.date_modified > one.date_modified);
+        assert_eq!(results[1].novel, two);
+        // the inserted row gets the next id rather than the one it was sent with
+        assert_eq!(results[2].novel.id, 3);
+        assert_eq!(fetch_single_novel(&db, "Ten").await.unwrap(), results[2].novel);
+
+        // the stale first row rejects the whole batch, including the valid second row
+        let stale = [NovelEntry { rating: 1, ..one }, NovelEntry { rating: 2, ..two }];
+        let err = update_novel_entries(&db, &stale, UpdateDateModified::True, CheckConflicts::True, &ctx()).await.unwrap_err();
+        assert_eq!(err.downcast::<NovelConflict>().unwrap().current, [results[0].novel.clone()]);
+        assert_eq!(fetch_novel_by_id(&db, 2).await.unwrap().unwrap().rating, 0);
+
+        assert_eq!(create_empty_row(&db, &patch("Four"), &ctx()).await.unwrap().id, 4);
+    }
+
+    #[tokio::test]
+    async fn batch_inserts_and_trashed_ids() {
+        let db = memory_db().await;
+        let one = create_empty_row(&db, &patch("One"), &ctx()).await.unwrap();
+
+        // new rows sent with the same placeholder id are all kept
+        let rows = [novel(0, "New", &[]), NovelEntry { rating: 3, ..one.clone() }, novel(0, "Also new", &[])];
+        let results = update_novel_entries(&db, &rows, UpdateDateModified::False, CheckConflicts::False, &ctx()).await.unwrap();
+        let written = results.iter().map(|row| (row.novel.id, row.novel.title.as_str(), row.outcome)).collect_vec();
+        assert_eq!(written, [(2, "New", RowOutcome::Inserted), (1, "One", RowOutcome::Updated), (3, "Also new", RowOutcome::Inserted)]);
+        assert_eq!(fetch_single_novel(&db, "Also new").await.unwrap().id, 3);
+
+        // writes to a trashed novel are rejected instead of editing it out of sight
+        trash_novel_entry(&db, 1, &ctx()).await.unwrap();
+        let rows = [NovelEntry { rating: 5, ..results[1].novel.clone() }];
+        let err = update_novel_entries(&db, &rows, UpdateDateModified::False, CheckConflicts::False, &ctx()).await.unwrap_err();
+        assert_eq!(err.downcast::<NovelsInTrash>().unwrap().ids, [1]);
+        assert_eq!(fetch_trashed_novels(&db).await.unwrap()[0].novel.rating, 3);
+    }
+
+    #[tokio::test]
+    async fn concurrent_inserts_share_the_id_sequence() {
+        let db = memory_db().await;
+        let rows = (1..=5).map(|i| novel(100 + i, &format!("Bulk {i}"), &[])).collect_vec();
+        let ctx = ctx();
+        let (created, bulk) = tokio::join!(
+            async {
+                let mut created = Vec::new();
+                for i in 1..=5 {
+                    created.push(create_empty_row(&db, &patch(&format!("Created {i}")), &ctx).await.unwrap().id);
+                }
+                created
+            },
+            update_novel_entries(&db, &rows, UpdateDateModified::False, CheckConflicts::False, &ctx),
+        );
+
+        let mut ids = created.into_iter().chain(bulk.unwrap().into_iter().map(|row| row.novel.id)).collect_vec();
+        ids.sort();
+        assert_eq!(ids, (1..=10).collect_vec());
+        let stored = fetch_novel_entries(&db).await.unwrap().into_iter().map(|novel| novel.id).collect_vec();
+        assert_eq!(stored, ids);
+    }
+
+    #[tokio::test]
+    async fn merge_csv_tags() {
+        let db = memory_db().await;
+        let rows = [novel(1, "One", &["A"]), novel(2, "Two", &["B"])];
+        update_novel_entries(&db, &rows, UpdateDateModified::False, CheckConflicts::False, &ctx()).await.unwrap();
+
+        let records = [
+            NovelTagsRecordParsed { title: "One".into(), tags: vec!["B".into(), "A".into()] },
+            NovelTagsRecordParsed { title: "One".into(), tags: vec!["C".into()] },
+            NovelTagsRecordParsed { title: "Two".into(), tags: vec!["B".into()] },
+            NovelTagsRecordParsed { title: "Missing".into(), tags: vec!["X".into()] },
+        ];
+        let results = update_novel_tags(&db, &records, &ctx()).await.unwrap();
+        let outcomes = results.iter().map(|row| row.outcome).collect_vec();
+        assert_eq!(outcomes, [RowOutcome::Updated, RowOutcome::Updated, RowOutcome::Unchanged, RowOutcome::NotFound]);
+        assert_eq!(fetch_single_novel(&db, "One").await.unwrap().tags, ["A", "B", "C"]);
+        assert_eq!(fetch_single_novel(&db, "Two").await.unwrap().tags, ["B"]);
+
+        // one invalid row fails the whole import
+        let records = [
+            NovelTagsRecordParsed { title: "One".into(), tags: vec!["D".into()] },
+            NovelTagsRecordParsed { title: "Two".into(), tags: vec![" ".into()] },
+        ];
+        let err = update_novel_tags(&db, &records, &ctx()).await.unwrap_err();
+        assert_eq!(err.downcast::<validation::InvalidNovels>().unwrap().rows[0].id, 2);
+        assert_eq!(fetch_single_novel(&db, "One").await.unwrap().tags, ["A", "B", "C"]);
+    }
+
+    #[tokio::test]
+    async fn query_novels() {
+        let db = memory_db().await;
+        let rows = [
+            NovelEntry { rating: 9, status: Some(Status::Reading), ..novel(1, "Lord of the Mysteries", &["Mystery", "Fantasy"]) },
+            NovelEntry { rating: 6, ..novel(2, "Overlord", &["Fantasy", "Adult"]) },
+            novel(3, "Untagged", &[]),
+        ];
+        update_novel_entries(&db, &rows, UpdateDateModified::False, CheckConflicts::False, &ctx()).await.unwrap();
+
+        let query = NovelQuery { tags: Some("Fantasy".into()), sort: NovelSort::Rating, ..Default::default() };
+        let page = query_novel_entries(&db, &query, &sus_tags()).await.unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.novels.iter().map(|novel| novel.id).collect_vec(), [2, 1]);
+
+        let query = NovelQuery { subset: Some(NovelSubsets::NotSus), ..Default::default() };
+        let page = query_novel_entries(&db, &query, &sus_tags()).await.unwrap();
+        assert_eq!(page.novels.iter().map(|novel| novel.id).collect_vec(), [1]);
+        let page = query_novel_entries(&db, &query, &["Mystery".to_string()]).await.unwrap();
+        assert_eq!(page.novels.iter().map(|novel| novel.id).collect_vec(), [2]);
+
+        let query = NovelQuery { title: Some("LORD".into()), status: Some(Status::Reading), ..Default::default() };
+        assert_eq!(query_novel_entries(&db, &query, &sus_tags()).await.unwrap().total, 1);
+
+        let query = NovelQuery { limit: Some(2), ..Default::default() };
+        let page = query_novel_entries(&db, &query, &sus_tags()).await.unwrap();
+        assert_eq!(page.next_cursor, Some(2));
+        let query = NovelQuery { limit: Some(2), cursor: page.next_cursor, ..Default::default() };
+        let next = query_novel_entries(&db, &query, &sus_tags()).await.unwrap();
+        assert_eq!((next.novels[0].id, next.total), (3, page.total));
+    }
+
+    #[tokio::test]
+    async fn literal_title_search() {
+        let db = memory_db().await;
+        let rows = [novel(1, "100% Reincarnated", &["Sci_Fi"]), novel(2, "Reincarnated", &["SciXFi"]), novel(3, "Snake_Case", &[])];
+        update_novel_entries(&db, &rows, UpdateDateModified::False, CheckConflicts::False, &ctx()).await.unwrap();
+
+        let ids = |query: NovelQuery| {
+            let db = &db;
+            async move { query_novel_entries(db, &query, &[]).await.unwrap().novels.iter().map(|novel| novel.id).collect_vec() }
+        };
+        assert_eq!(ids(NovelQuery { title: Some("%".into()), ..Default::default() }).await, [1]);
+        assert_eq!(ids(NovelQuery { title: Some("e_c".into()), ..Default::default() }).await, [3]);
+        assert_eq!(ids(NovelQuery { tags: Some("Sci_Fi".into()), ..Default::default() }).await, [1]);
+    }
+
+    #[tokio::test]
+    async fn trash_restore_and_purge() {
+        let db = memory_db().await;
+        let one = create_empty_row(&db, &patch("One"), &ctx()).await.unwrap();
+        create_empty_row(&db, &patch("Two"), &ctx()).await.unwrap();
+
+        assert!(trash_novel_entry(&db, one.id, &ctx()).await.unwrap());
+        assert!(!trash_novel_entry(&db, one.id, &ctx()).await.unwrap());
+        assert_eq!(titles(&db).await, ["Two"]);
+        assert!(fetch_novel_by_id(&db, one.id).await.unwrap().is_none());
+        assert_eq!(query_novel_entries(&db, &NovelQuery::default(), &sus_tags()).await.unwrap().total, 1);
+        assert_eq!(fetch_trashed_novels(&db).await.unwrap()[0].novel, one);
+
+        assert_eq!(restore_trashed_novel(&db, one.id, &ctx()).await.unwrap(), Some(one.clone()));
+        assert!(restore_trashed_novel(&db, one.id, &ctx()).await.unwrap().is_none());
+        assert_eq!(titles(&db).await, ["One", "Two"]);
+
+        trash_novel_entry(&db, one.id, &ctx()).await.unwrap();
+        assert_eq!(purge_trash(&db, Utc::now() - chrono::TimeDelta::days(1), &ctx()).await.unwrap(), 0);
+        assert_eq!(purge_trash(&db, Utc::now() + chrono::TimeDelta::days(1), &ctx()).await.unwrap(), 1);
+        assert!(fetch_trashed_novels(&db).await.unwrap().is_empty());
+
+        let actions = fetch_novel_history(&db, one.id).await.unwrap().into_iter().map(|entry| entry.action).collect_vec();
+        assert_eq!(actions, [AuditAction::Delete, AuditAction::Trash, AuditAction::Restore, AuditAction::Trash, AuditAction::Insert]);
+    }
+
+    #[tokio::test]
+    async fn restore_backup() {
+        let db = memory_db().await;
+        let rows = [novel(1, "One", &[]), novel(2, "Two", &[])];
+        update_novel_entries(&db, &rows, UpdateDateModified::False, CheckConflicts::False, &ctx()).await.unwrap();
+
+        let backup = vec![NovelEntry { rating: 3, ..novel(2, "Two", &[]) }, novel(5, "Five", &[])];
+        let restore = |backup: Vec<NovelEntry>, dry_run: bool| {
+            let db = &db;
+            async move {
+                let options = RestoreOptions { mode: RestoreMode::Replace, dry_run };
+                restore_novels(db, backup, &options, AllowInvalidRows::False, "before test restore", &ctx()).await
+            }
+        };
+
+        // dry runs and rejected plans leave everything as it was
+        let report = restore(backup.clone(), true).await.unwrap();
+        assert_eq!((report.added.len(), report.updated.len(), report.removed.len()), (1, 1, 1));
+        let err = restore(vec![novel(5, "Five", &[]), novel(6, " ", &[])], false).await.unwrap_err();
+        assert!(err.downcast::<RestoreRejected>().unwrap().report.has_invalid_rows());
+        assert_eq!(titles(&db).await, ["One", "Two"]);
+        assert!(fetch_snapshot_infos(&db).await.unwrap().is_empty());
+
+        restore(backup, false).await.unwrap();
+        assert_eq!(titles(&db).await, ["Five", "Two"]);
+        assert_eq!(fetch_trashed_novels(&db).await.unwrap()[0].novel.title, "One");
+        assert_eq!(fetch_snapshot_infos(&db).await.unwrap()[0].reason, "before test restore");
+
+        // merging a trashed novel's title back in takes it out of the trash rather than deleting it
+        trash_novel_entry(&db, 5, &ctx()).await.unwrap();
+        let options = RestoreOptions { mode: RestoreMode::MergeByTitle, dry_run: false };
+        let backup = vec![NovelEntry { rating: 8, ..novel(5, "Five", &[]) }];
+        let report = restore_novels(&db, backup, &options, AllowInvalidRows::False, "before test restore", &ctx()).await.unwrap();
+        assert_eq!((report.added.len(), report.restored.len()), (0, 1));
+        assert_eq!(fetch_novel_by_id(&db, 5).await.unwrap().unwrap().rating, 8);
+        let actions = fetch_novel_history(&db, 5).await.unwrap().into_iter().map(|entry| entry.action).collect_vec();
+        assert_eq!(actions, [AuditAction::Update, AuditAction::Restore, AuditAction::Trash, AuditAction::Insert]);
+        assert_eq!(fetch_novel_by_id(&db, 2).await.unwrap().unwrap().rating, 3);
+        assert_eq!(create_empty_row(&db, &patch("Six"), &ctx()).await.unwrap().id, 6);
+
+        // merging by title never matches a trashed novel by its id alone
+        trash_novel_entry(&db, 2, &ctx()).await.unwrap();
+        let backup = vec![novel(2, "Seven", &[]), NovelEntry { rating: 4, ..novel(9, "two", &[]) }];
+        let err = restore_novels(&db, backup, &options, AllowInvalidRows::False, "before test restore", &ctx()).await.unwrap_err();
+        let report = err.downcast::<RestoreRejected>().unwrap().report;
+        assert_eq!(report.conflicting.iter().map(|row| (row.id, row.reason.as_str())).collect_vec(), [(2, "Id is already used by a trashed novel with a different title")]);
+        assert_eq!(report.restored.iter().map(|row| (row.id, row.title.as_str())).collect_vec(), [(2, "two")]);
+        let backup = vec![NovelEntry { rating: 4, ..novel(9, "two", &[]) }];
+        restore_novels(&db, backup, &options, AllowInvalidRows::False, "before test restore", &ctx()).await.unwrap();
+        assert_eq!(fetch_novel_by_id(&db, 2).await.unwrap().unwrap().rating, 4);
+        assert!(fetch_novel_by_id(&db, 9).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn snapshots() {
+        let db = memory_db().await;
+        let backup = Backup::new(vec![novel(1, "One", &[])]);
+        for reason in ["first", "second", "third"] {
+            insert_snapshot(&db, reason, &backup).await.unwrap();
+        }
+        assert_eq!(prune_snapshots(&db, 2).await.unwrap(), 1);
+
+        let infos = fetch_snapshot_infos(&db).await.unwrap();
+        assert_eq!(infos.iter().map(|info| info.reason.as_str()).collect_vec(), ["third", "second"]);
+        assert_eq!(infos[0].novel_count, 1);
+        assert!(fetch_snapshot_data(&db, infos[0].id).await.unwrap().is_some());
+        assert!(fetch_snapshot_data(&db, 1).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn tokens() {
+        let db = memory_db().await;
+        let token = insert_token(&db, "script", "hash", &[Scope::NovelsRead]).await.unwrap();
+        assert_eq!(fetch_active_token(&db, "hash").await.unwrap(), Some(token.clone()));
+
+        revoke_token(&db, token.id).await.unwrap();
+        assert!(fetch_active_token(&db, "hash").await.unwrap().is_none());
+        assert!(fetch_tokens(&db).await.unwrap()[0].revoked_at.is_some());
+        assert!(revoke_token(&db, 42).await.is_err());
+    }
+
+    #[test]
+    fn dedup_rows_by_id() {
+        let rows = [
+            NovelEntry { title: "first".into(), ..NovelEntry::empty(1) },
+            NovelEntry { title: "new".into(), ..NovelEntry::empty(0) },
+            NovelEntry { title: "second".into(), ..NovelEntry::empty(1) },
+            NovelEntry { title: "also new".into(), ..NovelEntry::empty(0) },
+        ];
+        let existing = HashMap::from([(1, NovelEntry::empty(1))]);
+        let deduped = dedup_existing(&rows, &existing);
+        assert_eq!(deduped.iter().map(|row| row.title.as_str()).collect_vec(), ["second", "new", "also new"]);
+    }
+
+    #[test]
+    fn cursor_novel_select() {
+        let query = NovelQuery { cursor: Some(42), direction: SortDirection::Desc, ..Default::default() };
+        assert!(!to_sql(&query).contains("42"));
+        let sql = after_cursor(novel_select(&query, &sus_tags()), &query).build(DbBackend::Postgres).to_string();
+        assert!(sql.contains(r#""novels"."id" < 42"#));
     }
 }
\ No newline at end of file
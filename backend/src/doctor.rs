@@ -0,0 +1,348 @@
+use crate::audit::AuditContext;
+use crate::db;
+use crate::entity::novels;
+use crate::migrate;
+use crate::novel_entry::{self, NovelEntry, Provider, Status};
+use crate::validation::{title_key, MAX_RATING};
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sea_orm::{DatabaseConnection, FromQueryResult};
+use serde_json::{json, Value};
+use strum::{Display, IntoEnumIterator};
+
+/*
+Finds stored novels that the rest of the backend can't read or that break the validation rules.
+These come from older versions of the backend, manual edits and imports from before validation existed.
+Every problem comes with a fix, so `doctor --fix` can repair the whole table in one go.
+Trashed novels are checked too, except for duplicate titles since they may be restored with a new one.
+
+Statuses, providers and ratings are checked first, on the raw columns read as text.
+Those are the values that keep m20261018_121530_novel_enums from applying, so doctor runs while migrations are pending
+and only does this first check until the database is migrated; the rest needs the current schema.
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq, Display, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ProblemKind {
+    InvalidStatus,
+    InvalidProvider,
+    InvalidTags,
+    InvalidRating,
+    EmptyTitle,
+    DuplicateTitle,
+    ImpossibleDates,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub field: String,
+    // the stored value and what `--fix` replaces it with
+    pub value: Value,
+    pub fix: Value,
+}
+
+// everything wrong with one row, and the row with all of it fixed
+#[derive(Clone, Debug, Serialize)]
+pub struct Repair {
+    pub id: i32,
+    pub title: String,
+    pub problems: Vec<Problem>,
+    #[serde(skip)]
+    pub fixed: novels::Model,
+}
+
+// a novel's status, provider and rating as stored; the enums are read as text so unknown values can be read at all
+#[derive(Clone, Debug, FromQueryResult)]
+pub struct StoredColumns {
+    pub id: i32,
+    pub title: String,
+    pub status: Option<String>,
+    pub provider: Option<String>,
+    pub rating: Option<i32>,
+}
+
+// the problems with a novel's raw columns; the fixes are in the problems, since the rest of the row may not be readable yet
+#[derive(Clone, Debug, Serialize)]
+pub struct ColumnRepair {
+    pub id: i32,
+    pub title: String,
+    pub problems: Vec<Problem>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Diagnosis {
+    pub columns: Vec<ColumnRepair>,
+    pub repairs: Vec<Repair>,
+    // the rest of the checks wait until these are applied
+    pub pending_migrations: Vec<String>,
+}
+
+impl Diagnosis {
+    // every problem found, with the id and title of its novel
+    pub fn problems(&self) -> impl Iterator<Item = (i32, &str, &Problem)> {
+        let columns = self.columns.iter().flat_map(|repair| repair.problems.iter().map(|problem| (repair.id, repair.title.as_str(), problem)));
+        let repairs = self.repairs.iter().flat_map(|repair| repair.problems.iter().map(|problem| (repair.id, repair.title.as_str(), problem)));
+        columns.chain(repairs)
+    }
+
+    pub fn novel_count(&self) -> usize {
+        self.columns.len() + self.repairs.len()
+    }
+}
+
+// finds every problem and, with `fix`, repairs them; each of the two checks writes its repairs in one transaction
+pub async fn run_doctor(conn: &DatabaseConnection, fix: bool, ctx: &AuditContext) -> Result<Diagnosis> {
+    let columns = examine_columns(&db::fetch_stored_columns(conn).await?);
+    if fix && !columns.is_empty() {
+        db::repair_stored_columns(conn, &columns, ctx).await?;
+    }
+    let pending_migrations = migrate::pending_migrations(conn).await?;
+    if !pending_migrations.is_empty() {
+        return Ok(Diagnosis { columns, repairs: Vec::new(), pending_migrations });
+    }
+
+    let models = db::fetch_novel_models(conn).await?;
+    let repairs = examine(&models, novel_entry::now().naive_utc());
+    if fix && !repairs.is_empty() {
+        db::repair_novels(conn, &repairs, ctx).await?;
+    }
+    Ok(Diagnosis { columns, repairs, pending_migrations })
+}
+
+pub fn examine_columns(rows: &[StoredColumns]) -> Vec<ColumnRepair> {
+    let mut repairs = Vec::new();
+    for row in rows {
+        let mut problems = Vec::new();
+        if let Some(status) = row.status.as_deref().filter(|status| !is_variant::<Status>(status)) {
+            let fix = closest_variant::<Status>(status);
+            problems.push(Problem { kind: ProblemKind::InvalidStatus, field: "status".to_string(), value: json!(status), fix: json!(fix) });
+        }
+        if let Some(provider) = row.provider.as_deref().filter(|provider| !is_variant::<Provider>(provider)) {
+            let fix = closest_variant::<Provider>(provider);
+            problems.push(Problem { kind: ProblemKind::InvalidProvider, field: "provider".to_string(), value: json!(provider), fix: json!(fix) });
+        }
+        #[allow(clippy::cast_possible_wrap)]
+        let max_rating = MAX_RATING as i32;
+        if let Some(rating) = row.rating.filter(|rating| !(0..=max_rating).contains(rating)) {
+            problems.push(Problem { kind: ProblemKind::InvalidRating, field: "rating".to_string(), value: json!(rating), fix: json!(rating.clamp(0, max_rating)) });
+        }
+        if !problems.is_empty() {
+            repairs.push(ColumnRepair { id: row.id, title: row.title.clone(), problems });
+        }
+    }
+    repairs
+}
+
+// the database only takes the variants spelled exactly
+fn is_variant<T: IntoEnumIterator + ToString>(value: &str) -> bool {
+    T::iter().any(|variant| variant.to_string() == value)
+}
+
+// matches ignoring case and spaces, like "Novelupdates" or "reading "; anything else is cleared
+fn closest_variant<T: IntoEnumIterator + ToString>(value: &str) -> Option<String> {
+    let normalize = |s: &str| s.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+    T::iter()
+        .map(|variant| variant.to_string())
+        .find(|variant| normalize(variant) == normalize(value))
+}
+
+pub fn examine(models: &[novels::Model], now: NaiveDateTime) -> Vec<Repair> {
+    let mut repairs = models.iter()
+        .map(|model| examine_row(model, now))
+        .collect::<Vec<_>>();
+    fix_duplicate_titles(&mut repairs, models);
+    repairs.retain(|repair| !repair.problems.is_empty());
+    repairs
+}
+
+fn examine_row(model: &novels::Model, now: NaiveDateTime) -> Repair {
+    let mut fixed = model.clone();
+    let mut problems = Vec::new();
+    let mut problem = |kind, field: &str, value: Value, fix: Value| {
+        problems.push(Problem { kind, field: field.to_string(), value, fix });
+    };
+
+    let tags = repair_tags(&model.tags);
+    if tags != model.tags {
+        problem(ProblemKind::InvalidTags, "tags", model.tags.clone(), tags.clone());
+        fixed.tags = tags;
+    }
+
+    if model.title.trim().is_empty() {
+        fixed.title = format!("Untitled {}", model.id);
+        problem(ProblemKind::EmptyTitle, "title", json!(model.title), json!(fixed.title));
+    }
+
+    // the dates were most likely entered the wrong way around
+    if let (Some(started), Some(completed)) = (model.date_started, model.date_completed) {
+        if completed < started {
+            fixed.date_started = Some(completed);
+            fixed.date_completed = Some(started);
+            problem(ProblemKind::ImpossibleDates, "date_started", json!(started), json!(completed));
+            problem(ProblemKind::ImpossibleDates, "date_completed", json!(completed), json!(started));
+        }
+    }
+    if model.date_modified > now {
+        fixed.date_modified = now;
+        problem(ProblemKind::ImpossibleDates, "date_modified", json!(model.date_modified), json!(now));
+    }
+
+    Repair { id: model.id, title: model.title.clone(), problems, fixed }
+}
+
+// the oldest novel keeps a duplicated title; the others get their id appended
+fn fix_duplicate_titles(repairs: &mut [Repair], models: &[novels::Model]) {
+    let mut holders: HashMap<String, Vec<usize>> = HashMap::new();
+    for (idx, repair) in repairs.iter().enumerate() {
+        if models[idx].deleted_at.is_none() {
+            holders.entry(title_key(&repair.fixed.title)).or_default().push(idx);
+        }
+    }
+
+    for mut indices in holders.into_values().filter(|indices| indices.len() > 1) {
+        indices.sort_by_key(|&idx| repairs[idx].id);
+        for &idx in &indices[1..] {
+            let repair = &mut repairs[idx];
+            let title = format!("{} ({})", repair.fixed.title.trim(), repair.id);
+            repair.problems.push(Problem {
+                kind: ProblemKind::DuplicateTitle,
+                field: "title".to_string(),
+                value: json!(repair.title),
+                fix: json!(title),
+            });
+            repair.fixed.title = title;
+        }
+    }
+}
+
+// tags must be an array of non-empty strings; older rows stored them as a comma separated string
+fn repair_tags(tags: &Value) -> Value {
+    let tags = match tags {
+        Value::Array(tags) => tags.iter()
+            .filter_map(|tag| match tag {
+                Value::String(tag) => Some(tag.clone()),
+                Value::Number(tag) => Some(tag.to_string()),
+                _ => None,
+            })
+            .collect(),
+        Value::String(tags) => NovelEntry::parse_tags(tags).iter().map(|tag| tag.trim().to_string()).collect(),
+        _ => Vec::new(),
+    };
+    json!(tags.into_iter().filter(|tag| !tag.trim().is_empty()).collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditAction, AuditSource};
+    use chrono::{TimeDelta, Utc};
+    use sea_orm::{ActiveModelTrait, IntoActiveModel, TryIntoModel};
+
+    fn model(id: i32, title: &str) -> novels::Model {
+        let mut model = NovelEntry { title: title.to_string(), ..NovelEntry::empty(id) }.to_active_model();
+        model.deleted_at = sea_orm::Set(None);
+        model.try_into_model().unwrap()
+    }
+
+    fn kinds(repair: &Repair) -> Vec<ProblemKind> {
+        repair.problems.iter().map(|problem| problem.kind).collect()
+    }
+
+    #[test]
+    fn examine_rows() {
+        // a little ahead so the rows made below are in the past
+        let now = Utc::now().naive_utc() + TimeDelta::minutes(1);
+        let rows = [
+            novels::Model { tags: json!(["Action", 7, null]), ..model(1, "One") },
+            novels::Model { tags: json!("Action, ,Fantasy"), ..model(2, " ") },
+            novels::Model {
+                date_started: Some(now),
+                date_completed: Some(now - TimeDelta::days(3)),
+                date_modified: now + TimeDelta::days(1),
+                ..model(3, "Three")
+            },
+            model(4, "one"),
+            novels::Model { deleted_at: Some(now), ..model(5, "One") },
+            model(6, "Fine"),
+        ];
+        let repairs = examine(&rows, now);
+        assert_eq!(repairs.iter().map(|repair| repair.id).collect::<Vec<_>>(), [1, 2, 3, 4]);
+
+        let [one, two, three, four] = repairs.as_slice() else { unreachable!() };
+        assert_eq!(kinds(one), [ProblemKind::InvalidTags]);
+        assert_eq!(one.fixed.tags, json!(["Action", "7"]));
+
+        assert_eq!(kinds(two), [ProblemKind::InvalidTags, ProblemKind::EmptyTitle]);
+        assert_eq!((two.fixed.tags.clone(), two.fixed.title.as_str()), (json!(["Action", "Fantasy"]), "Untitled 2"));
+
+        assert_eq!(kinds(three), [ProblemKind::ImpossibleDates; 3]);
+        assert!(three.fixed.date_started < three.fixed.date_completed);
+        assert_eq!(three.fixed.date_modified, now);
+
+        // trashed novels don't count towards duplicates
+        assert_eq!(kinds(four), [ProblemKind::DuplicateTitle]);
+        assert_eq!(four.fixed.title, "one (4)");
+    }
+
+    #[test]
+    fn examine_stored_columns() {
+        let row = |id, status: Option<&str>, provider: Option<&str>, rating| StoredColumns {
+            id,
+            title: format!("Novel {id}"),
+            status: status.map(ToOwned::to_owned),
+            provider: provider.map(ToOwned::to_owned),
+            rating: Some(rating),
+        };
+        let rows = [
+            row(1, Some("reading "), Some("Webnovel"), 5),
+            row(2, Some("Reading"), Some("royalroad"), 11),
+            row(3, None, None, -1),
+            row(4, Some("Completed"), Some("NovelUpdates"), 10),
+        ];
+        let repairs = examine_columns(&rows);
+        assert_eq!(repairs.iter().map(|repair| repair.id).collect::<Vec<_>>(), [1, 2, 3]);
+
+        // statuses and providers are matched ignoring case and spaces, and cleared otherwise
+        let fixes = |repair: &ColumnRepair| repair.problems.iter().map(|problem| (problem.kind, problem.fix.clone())).collect::<Vec<_>>();
+        assert_eq!(fixes(&repairs[0]), [(ProblemKind::InvalidStatus, json!("Reading")), (ProblemKind::InvalidProvider, json!(null))]);
+        assert_eq!(fixes(&repairs[1]), [(ProblemKind::InvalidProvider, json!("RoyalRoad")), (ProblemKind::InvalidRating, json!(10))]);
+        assert_eq!(fixes(&repairs[2]), [(ProblemKind::InvalidRating, json!(0))]);
+    }
+
+    #[tokio::test]
+    async fn fix_stored_rows() {
+        let conn = db::memory_db().await;
+        let bad = novels::Model { tags: json!({ "not": "tags" }), ..model(1, "One") };
+        bad.into_active_model().reset_all().insert(&conn).await.unwrap();
+
+        let e = db::fetch_novel_entries(&conn).await.unwrap_err();
+        assert_eq!(e.downcast::<novel_entry::InvalidNovelRow>().unwrap().field, "tags");
+
+        // lists skip the row until it is repaired, but fetching it alone still fails
+        model(2, "Two").into_active_model().reset_all().insert(&conn).await.unwrap();
+        let listed = db::list_novel_entries(&conn).await.unwrap();
+        assert_eq!(listed.iter().map(|novel| novel.id).collect::<Vec<_>>(), [2]);
+        let page = db::query_novel_entries(&conn, &novel_entry::NovelQuery::default(), &[]).await.unwrap();
+        assert_eq!((page.novels.len(), page.total, page.skipped), (1, 2, 1));
+        assert!(db::fetch_novel_by_id(&conn, 1).await.is_err());
+
+        let ctx = AuditContext::new("tester", AuditSource::Doctor);
+        assert_eq!(run_doctor(&conn, false, &ctx).await.unwrap().repairs.len(), 1);
+        assert!(db::fetch_novel_entries(&conn).await.is_err());
+
+        run_doctor(&conn, true, &ctx).await.unwrap();
+        let novels = db::fetch_novel_entries(&conn).await.unwrap();
+        assert!(novels[0].tags.is_empty());
+        assert_eq!(run_doctor(&conn, false, &ctx).await.unwrap().problems().count(), 0);
+
+        let history = db::fetch_novel_history(&conn, 1).await.unwrap();
+        assert_eq!((history[0].action, history[0].source), (AuditAction::Update, AuditSource::Doctor));
+        assert_eq!(history[0].changes["tags"], json!({ "before": { "not": "tags" }, "after": [] }));
+    }
+}
@@ -2,4 +2,8 @@
 
 pub mod prelude;
 
+pub mod audit_log;
 pub mod novels;
+pub mod sea_orm_active_enums;
+pub mod snapshots;
+pub mod tokens;
@@ -1,5 +1,6 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.2
 
+use super::sea_orm_active_enums::{Provider, Status};
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -7,17 +8,18 @@ use sea_orm::entity::prelude::*;
 pub struct Model {
     pub country: String,
     pub rating: Option<i32>,
-    pub status: Option<String>,
+    pub status: Option<Status>,
     pub notes: String,
     pub date_modified: DateTime,
     pub chapter: String,
     pub tags: Json,
-    #[sea_orm(primary_key, auto_increment = false, unique)]
+    #[sea_orm(primary_key, unique)]
     pub id: i32,
     pub title: String,
     pub date_started: Option<DateTime>,
     pub date_completed: Option<DateTime>,
-    pub provider: Option<String>,
+    pub provider: Option<Provider>,
+    pub deleted_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,3 +1,6 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.2
 
+pub use super::audit_log::Entity as AuditLog;
 pub use super::novels::Entity as Novels;
+pub use super::snapshots::Entity as Snapshots;
+pub use super::tokens::Entity as Tokens;
@@ -0,0 +1,513 @@
+use super::*;
+use crate::auth::{Claims, Role};
+use crate::backup::Backup;
+
+use std::sync::OnceLock;
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    http::{Method, Request},
+};
+use itertools::Itertools;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+/*
+Drives the router in-process against a fresh in-memory database per test.
+image_to_tetris runs a fake script that echoes the source image back instead of the real binary.
+*/
+
+const SECRET: &[u8] = b"http test secret";
+const BOUNDARY: &str = "http-test-boundary";
+
+// copies the source image to the output path; a board width of 0 fails like the real binary would
+const FAKE_IMAGE_TO_TETRIS: &str = r#"#!/bin/sh
+while [ "$1" != "approx-image" ]; do shift; done
+if [ "$4" = "0" ]; then
+    echo "board is too small" >&2
+    exit 1
+fi
+cp "$2" "$3"
+"#;
+
+fn fake_image_to_tetris() -> PathBuf {
+    static SCRIPT_DIR: OnceLock<TempDir> = OnceLock::new();
+    let dir = SCRIPT_DIR.get_or_init(|| {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("image-to-tetris");
+        std::fs::write(&script, FAKE_IMAGE_TO_TETRIS).unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        dir
+    });
+    dir.path().join("image-to-tetris")
+}
+
+struct TestApp {
+    router: Router,
+    conn: DatabaseConnection,
+}
+
+struct TestResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl TestResponse {
+    fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body).unwrap_or_else(|e| panic!("{e}: {}", String::from_utf8_lossy(&self.body)))
+    }
+
+    // the code and message of an error envelope
+    fn error(&self) -> (String, String) {
+        let body: Value = self.json();
+        (body["code"].as_str().unwrap().to_string(), body["message"].as_str().unwrap().to_string())
+    }
+
+    fn details<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_value(self.json::<Value>()["details"].clone()).unwrap()
+    }
+}
+
+fn session(role: Role) -> String {
+    let claims = Claims { sub: "tester@example.com".into(), role, exp: jsonwebtoken::get_current_timestamp() + 3600 };
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+}
+
+impl TestApp {
+    async fn new() -> Self {
+        Self::with_config(Config::default()).await
+    }
+
+    async fn with_config(config: Config) -> Self {
+        let conn = db::memory_db().await;
+        let state = AppState {
+            conn: conn.clone(),
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(0))),
+            auth_key: DecodingKey::from_secret(SECRET),
+            config: Arc::new(Config { image_to_tetris_path: fake_image_to_tetris(), ..config }),
+        };
+        Self { router: app(state), conn }
+    }
+
+    async fn send(&self, method: Method, path: &str, role: Option<Role>, content_type: Option<&str>, body: Body) -> TestResponse {
+        let mut request = Request::builder().method(method).uri(path);
+        if let Some(role) = role {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", session(role)));
+        }
+        if let Some(content_type) = content_type {
+            request = request.header(header::CONTENT_TYPE, content_type);
+        }
+
+        let response = self.router.clone().oneshot(request.body(body).unwrap()).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        TestResponse { status, headers, body }
+    }
+
+    async fn get(&self, path: &str) -> TestResponse {
+        self.send(Method::GET, path, Some(Role::Admin), None, Body::empty()).await
+    }
+
+    async fn json(&self, method: Method, path: &str, body: &Value) -> TestResponse {
+        self.send(method, path, Some(Role::Admin), Some("application/json"), Body::from(body.to_string())).await
+    }
+
+    async fn multipart(&self, path: &str, parts: &[Part<'_>]) -> TestResponse {
+        let content_type = format!("multipart/form-data; boundary={BOUNDARY}");
+        self.send(Method::POST, path, Some(Role::Admin), Some(&content_type), Body::from(multipart_body(parts))).await
+    }
+
+    async fn create(&self, title: &str) -> NovelEntry {
+        let res = self.json(Method::POST, "/api/novels", &json!({ "title": title })).await;
+        assert_eq!(res.status, StatusCode::CREATED);
+        res.json()
+    }
+}
+
+struct Part<'a> {
+    name: &'a str,
+    filename: Option<&'a str>,
+    data: &'a [u8],
+}
+
+fn multipart_body(parts: &[Part]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for part in parts {
+        body.extend(format!("--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{}\"", part.name).as_bytes());
+        if let Some(filename) = part.filename {
+            body.extend(format!("; filename=\"{filename}\"").as_bytes());
+        }
+        body.extend(b"\r\n\r\n");
+        body.extend(part.data);
+        body.extend(b"\r\n");
+    }
+    body.extend(format!("--{BOUNDARY}--\r\n").as_bytes());
+    body
+}
+
+#[tokio::test]
+async fn auth_required_for_writes() {
+    let app = TestApp::new().await;
+    let body = Body::from(json!({ "title": "One" }).to_string());
+    let res = app.send(Method::POST, "/api/novels", None, Some("application/json"), body).await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let body = Body::from(json!({ "title": "One" }).to_string());
+    let res = app.send(Method::POST, "/api/novels", Some(Role::User), Some("application/json"), body).await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_eq!(res.error().0, "forbidden");
+
+    // reads are public
+    let res = app.send(Method::GET, "/api/novels", None, None, Body::empty()).await;
+    assert_eq!(res.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn request_ids() {
+    let app = TestApp::new().await;
+    let with_id = |id: &str| Request::builder().uri("/api/novels").header(logging::REQUEST_ID_HEADER, id).body(Body::empty()).unwrap();
+
+    // every response gets one, errors included
+    let generated = app.get("/api/novels").await.headers[logging::REQUEST_ID_HEADER].clone();
+    assert_eq!(generated.len(), 16);
+    let res = app.get("/api/novels/404").await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    assert_ne!(res.headers[logging::REQUEST_ID_HEADER], generated);
+
+    // ids from upstream are kept so logs line up across services, unless they look off
+    let res = app.router.clone().oneshot(with_id("frontend-42")).await.unwrap();
+    assert_eq!(res.headers()[logging::REQUEST_ID_HEADER], "frontend-42");
+    let res = app.router.clone().oneshot(with_id("not a request id")).await.unwrap();
+    assert_ne!(res.headers()[logging::REQUEST_ID_HEADER], "not a request id");
+}
+
+#[tokio::test]
+async fn create_and_fetch_novels() {
+    let app = TestApp::new().await;
+    let one = app.create("One").await;
+    let res = app.get("/api/create_novel").await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(res.json::<NovelEntry>().id, one.id + 1);
+
+    let res = app.get("/api/novels?limit=1").await;
+    assert_eq!(res.status, StatusCode::OK);
+    let page: Value = res.json();
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["novels"][0]["title"], "One");
+    assert_eq!(page["next_cursor"], one.id);
+
+    let res = app.get("/api/novels?sort=bogus").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    let res = app.json(Method::POST, "/api/all_novels", &json!("All")).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json::<Vec<NovelEntry>>().len(), 2);
+
+    // the old list request on POST /api/novels still lists novels, even for callers who can't create them
+    let res = app.send(Method::POST, "/api/novels", Some(Role::User), Some("application/json"), Body::from(r#""All""#)).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json::<Vec<NovelEntry>>().len(), 2);
+    let res = app.json(Method::POST, "/api/novels", &json!({ "rating": "high" })).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(app.get("/api/novels").await.json::<Value>()["total"], 2);
+
+    let res = app.get(&format!("/api/novels/{}", one.id)).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.headers[header::ETAG], one.etag());
+    assert_eq!(res.json::<NovelEntry>(), one);
+
+    assert_eq!(app.get("/api/novels/42").await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn patch_novels() {
+    let app = TestApp::new().await;
+    let one = app.create("One").await;
+    let path = format!("/api/novels/{}", one.id);
+
+    let res = app.json(Method::PATCH, &path, &json!({ "rating": 8, "status": "Reading" })).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let patched: NovelEntry = res.json();
+    assert_eq!(patched.rating, 8);
+
+    // patching with the original etag is stale now
+    let body = Body::from(json!({ "rating": 1 }).to_string());
+    let request = Request::patch(&path)
+        .header(header::AUTHORIZATION, format!("Bearer {}", session(Role::Admin)))
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::IF_MATCH, one.etag())
+        .body(body)
+        .unwrap();
+    let res = app.router.clone().oneshot(request).await.unwrap();
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    let body: Value = serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body["code"], "conflict");
+    assert_eq!(serde_json::from_value::<Vec<NovelEntry>>(body["details"].clone()).unwrap(), [patched]);
+
+    let res = app.json(Method::PATCH, "/api/novels/42", &json!({ "rating": 1 })).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+    let res = app.json(Method::PATCH, &path, &json!({ "rating": "high" })).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn update_novels() {
+    let app = TestApp::new().await;
+    let one = app.create("One").await;
+    let two = app.create("Two").await;
+
+    let rows = json!([NovelEntry { rating: 5, ..one.clone() }, NovelEntry { notes: "good".into(), ..two.clone() }]);
+    let res = app.json(Method::POST, "/api/update_novels", &rows).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let updated: Vec<NovelEntry> = res.json();
+    assert_eq!((updated[0].rating, updated[1].notes.as_str()), (5, "good"));
+
+    // the bulk update above was snapshotted
+    let snapshots: Vec<Value> = app.get("/api/snapshots").await.json();
+    assert_eq!(snapshots[0]["reason"], "before bulk update");
+
+    let res = app.json(Method::POST, "/api/update_novels", &json!([NovelEntry { rating: 1, ..one }])).await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+    assert_eq!(res.details::<Vec<NovelEntry>>(), [updated[0].clone()]);
+
+    let res = app.json(Method::POST, "/api/update_novels", &json!([{ "id": 1 }])).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.error().0, "validation_failed");
+}
+
+#[tokio::test]
+async fn invalid_novels_rejected() {
+    let app = TestApp::new().await;
+    let one = app.create("One").await;
+    let two = app.create("Two").await;
+
+    // blank rows are named after their id so they don't collide
+    let blank: NovelEntry = app.get("/api/create_novel").await.json();
+    assert_eq!(blank.title, format!("Untitled {}", blank.id));
+
+    let res = app.json(Method::POST, "/api/novels", &json!({ "title": " one " })).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.details::<Value>()[0]["errors"][0], json!({ "field": "title", "message": format!("is already used by novel {}", one.id) }));
+
+    let path = format!("/api/novels/{}", one.id);
+    let res = app.json(Method::PATCH, &path, &json!({ "rating": 11 })).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.error().0, "validation_failed");
+
+    // every invalid row is reported and nothing is written
+    let rows = json!([
+        NovelEntry { title: String::new(), ..one.clone() },
+        NovelEntry { notes: "fine".into(), ..blank.clone() },
+        NovelEntry { date_started: Some(novel_entry::now()), date_completed: Some(two.date_modified - chrono::TimeDelta::days(1)), ..two.clone() },
+    ]);
+    let res = app.json(Method::POST, "/api/update_novels", &rows).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let invalid: Vec<Value> = res.details();
+    assert_eq!(invalid.iter().map(|row| row["id"].as_i64().unwrap() as i32).collect_vec(), [one.id, two.id]);
+    assert_eq!(invalid[1]["errors"][0]["field"], "date_completed");
+    assert_eq!(app.get(&format!("/api/novels/{}", blank.id)).await.json::<NovelEntry>(), blank);
+
+    let backup = serde_json::to_vec(&Backup::new(vec![one.clone(), NovelEntry { title: "Two".into(), ..NovelEntry::empty(9) }])).unwrap();
+    let file = [Part { name: "file", filename: Some("backup.json"), data: &backup }];
+    let res = app.multipart("/api/upload_novels_backup?mode=merge-by-id", &file).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(res.details::<Value>()["invalid"][0]["id"], 9);
+}
+
+#[tokio::test]
+async fn delete_and_restore_novels() {
+    let app = TestApp::new().await;
+    let one = app.create("One").await;
+    let two = app.create("Two").await;
+
+    let res = app.json(Method::DELETE, "/api/delete_novel", &json!(one.id)).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json::<i32>(), one.id);
+    let res = app.json(Method::DELETE, "/api/delete_novel", &json!(one.id)).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let path = format!("/api/novels/{}", two.id);
+    assert_eq!(app.send(Method::DELETE, &path, Some(Role::Admin), None, Body::empty()).await.status, StatusCode::OK);
+    assert_eq!(app.send(Method::DELETE, &path, Some(Role::Admin), None, Body::empty()).await.status, StatusCode::NOT_FOUND);
+    assert_eq!(app.get(&path).await.status, StatusCode::NOT_FOUND);
+
+    let trash: Vec<Value> = app.get("/api/trash").await.json();
+    assert_eq!(trash.len(), 2);
+    let res = app.send(Method::POST, &format!("/api/trash/{}/restore", one.id), Some(Role::Admin), None, Body::empty()).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json::<NovelEntry>(), one);
+    let res = app.send(Method::POST, &format!("/api/trash/{}/restore", one.id), Some(Role::Admin), None, Body::empty()).await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+
+    let history: Vec<Value> = app.get(&format!("/api/novels/{}/history", one.id)).await.json();
+    let actions = history.iter().map(|entry| entry["action"].as_str().unwrap()).collect::<Vec<_>>();
+    assert_eq!(actions, ["restore", "trash", "insert"]);
+}
+
+#[tokio::test]
+async fn upload_novels_backup() {
+    let app = TestApp::new().await;
+    let one = app.create("One").await;
+
+    let nine = NovelEntry { title: "Nine".into(), ..NovelEntry::empty(9) };
+    let backup = serde_json::to_vec(&Backup::new(vec![NovelEntry { rating: 4, ..one.clone() }, nine])).unwrap();
+    let file = [Part { name: "file", filename: Some("backup.json"), data: &backup }];
+
+    let res = app.multipart("/api/upload_novels_backup?dry_run=true", &file).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let report: Value = res.json();
+    assert_eq!((report["added"].as_array().unwrap().len(), report["updated"].as_array().unwrap().len()), (1, 1));
+    assert_eq!(app.get("/api/novels").await.json::<Value>()["total"], 1);
+
+    let res = app.multipart("/api/upload_novels_backup?mode=merge-by-id", &file).await;
+    assert_eq!(res.status, StatusCode::ACCEPTED);
+    let page: Value = app.get("/api/novels").await.json();
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["novels"][0]["rating"], 4);
+
+    // the download round trips through the upload
+    let res = app.get("/api/novels_backup").await;
+    assert_eq!(res.status, StatusCode::OK);
+    let file = [Part { name: "file", filename: Some("novels.json"), data: &res.body }];
+    let res = app.multipart("/api/upload_novels_backup", &file).await;
+    assert_eq!(res.status, StatusCode::ACCEPTED);
+    assert_eq!(res.json::<Value>()["unchanged"], 2);
+
+    let duplicate = serde_json::to_vec(&Backup::new(vec![NovelEntry::empty(3), NovelEntry::empty(3)])).unwrap();
+    let res = app.multipart("/api/upload_novels_backup", &[Part { name: "file", filename: Some("dup.json"), data: &duplicate }]).await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+    assert_eq!(res.details::<Value>()["conflicting"][0]["id"], 3);
+
+    let res = app.multipart("/api/upload_novels_backup", &[Part { name: "file", filename: Some("bad.json"), data: b"{" }]).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let res = app.json(Method::POST, "/api/upload_novels_backup", &json!([])).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    // the payload limit comes from the config
+    let app = TestApp::with_config(Config { payload_limit: 64, ..Config::default() }).await;
+    let res = app.multipart("/api/upload_novels_backup", &file).await;
+    assert_eq!(res.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(res.error().0, "validation_failed");
+}
+
+#[tokio::test]
+async fn database_errors_surface() {
+    let app = TestApp::new().await;
+    app.create("One").await;
+    app.conn.clone().close().await.unwrap();
+
+    for res in [
+        app.json(Method::POST, "/api/all_novels", &json!("All")).await,
+        app.json(Method::POST, "/api/random_novels", &json!("All")).await,
+        app.get("/api/novels_stats").await,
+    ] {
+        assert_eq!(res.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.error(), ("db_unavailable".into(), "The database is unavailable".into()));
+    }
+}
+
+#[tokio::test]
+async fn conflicting_snapshot_restore() {
+    let app = TestApp::new().await;
+    app.create("One").await;
+
+    // snapshots are restored as is, so one holding the same id twice is rejected like any other backup
+    let backup = Backup::new(vec![NovelEntry::empty(3), NovelEntry::empty(3)]);
+    let snapshot = db::insert_snapshot(&app.conn, "test", &backup).await.unwrap();
+    let res = app.send(Method::POST, &format!("/api/snapshots/{}/restore", snapshot.id), Some(Role::Admin), None, Body::empty()).await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+    assert_eq!(res.error().0, "conflict");
+    assert_eq!(res.details::<Value>()["conflicting"][0]["id"], 3);
+    assert_eq!(app.get("/api/novels").await.json::<Value>()["total"], 1);
+}
+
+#[tokio::test]
+async fn novels_stats() {
+    let app = TestApp::new().await;
+    let res = app.get("/api/novels_stats").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.json::<Value>()["novel_count"], 0);
+
+    let one = app.create("One").await;
+    app.json(Method::PATCH, &format!("/api/novels/{}", one.id), &json!({ "rating": 8, "chapter": "c30" })).await;
+    let stats: Value = app.get("/api/novels_stats").await.json();
+    assert_eq!(stats["novel_count"], 1);
+    assert_eq!(stats["chapter_count"], 30);
+    assert_eq!(stats["rating_dist"][7], 1);
+}
+
+#[tokio::test]
+async fn random_novels() {
+    let app = TestApp::new().await;
+    for i in 0..12 {
+        app.create(&format!("Novel {i}")).await;
+    }
+
+    let res = app.json(Method::POST, "/api/random_novels", &json!("All")).await;
+    assert_eq!(res.status, StatusCode::OK);
+    let novels: Vec<NovelEntry> = res.json();
+    assert_eq!(novels.len(), 10);
+    assert_eq!(novels.iter().map(|novel| novel.id).unique().count(), 10);
+
+    // untagged novels aren't vetted
+    let res = app.json(Method::POST, "/api/random_novels", &json!("NotSus")).await;
+    assert!(res.json::<Vec<NovelEntry>>().is_empty());
+}
+
+#[tokio::test]
+async fn image_to_tetris() {
+    let app = TestApp::new().await;
+    let image = b"not really a png";
+    let width = 10u32.to_le_bytes();
+    let height = 20u32.to_le_bytes();
+    let prioritize = 1u32.to_le_bytes();
+    let parts = [
+        Part { name: "image", filename: Some("source.png"), data: image },
+        Part { name: "board_width", filename: None, data: &width },
+        Part { name: "board_height", filename: None, data: &height },
+        Part { name: "prioritize_tetrominos", filename: None, data: &prioritize },
+    ];
+
+    let res = app.multipart("/api/image_to_tetris", &parts).await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.headers[header::CONTENT_TYPE], "image/png; charset=utf-8");
+    assert_eq!(&res.body[..], image);
+
+    // anonymous callers can run it too
+    let content_type = format!("multipart/form-data; boundary={BOUNDARY}");
+    let res = app.send(Method::POST, "/api/image_to_tetris", None, Some(&content_type), Body::from(multipart_body(&parts))).await;
+    assert_eq!(res.status, StatusCode::OK);
+
+    let res = app.multipart("/api/image_to_tetris", &parts[..3]).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.error(), ("validation_failed".into(), "No prioritize tetrominos field found".into()));
+
+    let short = [Part { name: "board_width", filename: None, data: b"1" }];
+    let res = app.multipart("/api/image_to_tetris", &short).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert_eq!(res.error().1, "Invalid integer");
+
+    let unknown = [Part { name: "board_depth", filename: None, data: &width }];
+    assert_eq!(app.multipart("/api/image_to_tetris", &unknown).await.status, StatusCode::BAD_REQUEST);
+
+    let zero = 0u32.to_le_bytes();
+    let failing = [
+        Part { name: "image", filename: Some("source.png"), data: image },
+        Part { name: "board_width", filename: None, data: &zero },
+        Part { name: "board_height", filename: None, data: &height },
+        Part { name: "prioritize_tetrominos", filename: None, data: &prioritize },
+    ];
+    let res = app.multipart("/api/image_to_tetris", &failing).await;
+    assert_eq!(res.status, StatusCode::BAD_GATEWAY);
+    let (code, message) = res.error();
+    assert_eq!(code, "upstream_failed");
+    assert!(message.contains("board is too small"));
+}
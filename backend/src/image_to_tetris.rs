@@ -1,14 +1,20 @@
 use anyhow::{Error, Result};
 use tokio::{fs, process::Command};
 use tempfile::tempdir;
+use tracing::{field::Empty, Span};
 
-use std::env;
+use std::{path::Path, time::Instant};
 
-pub async fn run(board_width: u32, board_height: u32, prioritize_tetrominos: bool, source_image: &[u8], image_format: &str) -> Result<Vec<u8>> {
+// the span records how the subprocess went, so a slow or failing request can be pinned on the binary
+#[tracing::instrument(
+    name = "image_to_tetris",
+    skip_all,
+    fields(board_width = board_width, board_height = board_height, prioritize_tetrominos = prioritize_tetrominos, image_format = image_format, image_bytes = source_image.len(), exit_status = Empty, duration_ms = Empty),
+)]
+pub async fn run(image_to_tetris_binary: &Path, board_width: u32, board_height: u32, prioritize_tetrominos: bool, source_image: &[u8], image_format: &str) -> Result<Vec<u8>> {
     let temp_dir = tempdir()?;
     let source_path = temp_dir.path().join("source").with_extension(image_format);
     let output_path = temp_dir.path().join("output.png");
-    let image_to_tetris_binary = env::var("IMAGE_TO_TETRIS_PATH")?;
 
     fs::write(&source_path, source_image).await?;
 
@@ -23,10 +29,17 @@ pub async fn run(board_width: u32, board_height: u32, prioritize_tetrominos: boo
         .arg(format!("{board_width}"))
         .arg(format!("{board_height}"));
 
+    let start = Instant::now();
     let res = command.output().await?;
+    let span = Span::current();
+    span.record("exit_status", res.status.to_string());
+    span.record("duration_ms", start.elapsed().as_millis() as u64);
     if !res.status.success() {
-        return Err(Error::msg(String::from_utf8_lossy(&res.stderr).to_string()));
+        let stderr = String::from_utf8_lossy(&res.stderr).to_string();
+        tracing::warn!("image-to-tetris failed: {}", stderr.trim_end());
+        return Err(Error::msg(stderr));
     }
+    tracing::info!("image-to-tetris finished");
 
     let output_img = fs::read(&output_path).await?;
     fs::remove_file(source_path).await?;
@@ -38,15 +51,16 @@ pub async fn run(board_width: u32, board_height: u32, prioritize_tetrominos: boo
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Config;
     use dotenv::dotenv;
-    use std::path::Path;
 
     #[tokio::test]
     #[ignore = "computationally heavy"]
     async fn approx_image() {
         dotenv().ok();
+        let config = Config::load(None).unwrap();
         let source = Path::new("test_assets/blank.jpeg");
-        let res = run(10, 10, true, &tokio::fs::read(source).await.unwrap(), "jpeg").await;
+        let res = run(&config.image_to_tetris_path, 10, 10, true, &tokio::fs::read(source).await.unwrap(), "jpeg").await;
         res.unwrap();
     }
 
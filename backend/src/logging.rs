@@ -0,0 +1,113 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use clap::ValueEnum;
+use strum::Display;
+use tracing::{field::Empty, Instrument};
+use tracing_subscriber::EnvFilter;
+
+/*
+Logs go to stderr so a command's output on stdout stays clean for scripts (see `--json`).
+The level only applies to this crate; dependencies only log warnings and errors, since sea-orm logs every statement at info.
+
+Every request runs in a `request` span, so whatever a handler logs carries the request's id, route and caller.
+The id comes from an incoming `x-request-id` header when it looks sane and is echoed back on the response.
+*/
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, ValueEnum, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+// json writes one object per line with the fields of every span the event happened in
+#[derive(Copy, Clone, Debug, Default, PartialEq, ValueEnum, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+pub fn init(level: LogLevel, format: LogFormat) {
+    let filter = EnvFilter::new(format!("warn,{}={level}", env!("CARGO_CRATE_NAME")));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match format {
+        LogFormat::Human => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
+
+// the caller is recorded by the auth extractors once they know who it is
+pub async fn trace_request(request: Request, next: Next) -> Response {
+    let request_id = request.headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| valid_request_id(id))
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(new_request_id);
+    let route = request.extensions()
+        .get::<MatchedPath>()
+        .map_or(request.uri().path(), MatchedPath::as_str)
+        .to_owned();
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        route,
+        request_id,
+        caller = Empty,
+        status = Empty,
+        latency_ms = Empty,
+    );
+
+    let start = Instant::now();
+    let mut response = next.run(request).instrument(span.clone()).await;
+    span.record("status", response.status().as_u16());
+    span.record("latency_ms", start.elapsed().as_millis() as u64);
+    span.in_scope(|| tracing::info!("Finished request"));
+
+    let request_id = HeaderValue::from_str(&request_id).expect("request ids are checked to be valid header values");
+    response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+    response
+}
+
+fn new_request_id() -> String {
+    hex::encode(rand::random::<[u8; 8]>())
+}
+
+// ids from proxies and clients end up in every log line, so only short plain ones are kept
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_ids() {
+        assert!(valid_request_id("5f0c6bb2-8a0e-4c3b-9d4e-2a1f0b7c9e11"));
+        assert!(valid_request_id(&new_request_id()));
+        assert_eq!(new_request_id().len(), 16);
+        assert!(!valid_request_id(""));
+        assert!(!valid_request_id("has spaces"));
+        assert!(!valid_request_id("line\nbreak"));
+        assert!(!valid_request_id(&"a".repeat(MAX_REQUEST_ID_LENGTH + 1)));
+    }
+}
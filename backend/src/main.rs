@@ -1,35 +1,57 @@
+mod api_error;
+mod audit;
+mod auth;
+mod backup;
 mod cli;
 mod chapter;
+mod config;
 mod data_ingestion;
 mod db;
+mod doctor;
 mod entity;
+#[cfg(test)]
+mod http_tests;
 mod image_to_tetris;
+mod logging;
+mod migrate;
 mod novel_entry;
+mod snapshot;
 mod stats;
+mod tui;
+mod validation;
 
-use std::{borrow::ToOwned, env, path::PathBuf, sync::Arc};
+use std::{borrow::ToOwned, path::PathBuf, process::ExitCode, sync::Arc};
 
 use anyhow::Result;
 use axum::{
     extract::{
-        multipart::{Field, Multipart, MultipartError},
+        multipart::{Field, Multipart, MultipartRejection},
         DefaultBodyLimit,
         State},
     http::{
         header,
+        HeaderMap,
         StatusCode},
-        response::{IntoResponse, Json},
+    middleware,
+    response::{IntoResponse, Json, Response},
     routing::{
         delete,
         get,
-        post
+        post,
     },
     Router
 };
+use api_error::{ApiError, ApiJson, ApiPath, ApiQuery};
+use audit::{AuditContext, AuditSource};
+use auth::{scope, Authorized, Caller};
+use backup::{AllowInvalidRows, RestoreOptions};
+use config::Config;
 use dotenv::dotenv;
-use novel_entry::{NovelEntry, NovelSubsets};
+use jsonwebtoken::DecodingKey;
+use novel_entry::{filter_subset, NovelEntry, NovelPatch, NovelQuery, NovelSubsets};
 use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
-use sea_orm::DatabaseConnection;
+use sea_orm::{DatabaseConnection, JsonValue};
+use serde::Deserialize;
 use tokio::sync::Mutex;
 
 // global state for routing
@@ -37,191 +59,310 @@ use tokio::sync::Mutex;
 struct AppState {
     conn: DatabaseConnection,
     rng: Arc<Mutex<StdRng>>,
+    auth_key: DecodingKey,
+    config: Arc<Config>,
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    // initialize everything; run scripts if applicable
+async fn main() -> ExitCode {
     dotenv().ok();
-    let rng = Arc::new(Mutex::new(StdRng::from_entropy()));
-    let conn = db::init().await?;
-    cli::run_cli(&conn).await?;
+    cli::run().await
+}
+
+// runs the HTTP server until the process is stopped; started by `serve`
+async fn serve(conn: DatabaseConnection, config: Arc<Config>) -> Result<()> {
+    // start scheduled backups if configured
+    if let Some(settings) = backup::schedule::BackupSettings::from_config(&config.backup) {
+        tokio::spawn(backup::schedule::run_schedule(conn.clone(), settings));
+    }
 
     // build our application with a route
-    let payload_limit = 5_000_000; // 5 megabytes
-    let state = AppState { conn, rng };
-    let domain = env::var("DOMAIN")?;
-    let app = Router::new()
-        .route("/api/novels", post(novels_handler))
+    let rng = Arc::new(Mutex::new(StdRng::from_entropy()));
+    let auth_key = DecodingKey::from_secret(config.auth_secret.as_bytes());
+    let domain = config.domain.clone();
+    let state = AppState { conn, rng, auth_key, config };
+    let app = app(state);
+
+    // run it
+    let listener = tokio::net::TcpListener::bind(domain.clone())
+        .await?;
+    tracing::info!("Listening on {domain}");
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+// every route the backend serves
+fn app(state: AppState) -> Router {
+    let payload_limit = state.config.payload_limit;
+    Router::new()
+        .route("/api/novels", get(query_novels_handler).post(create_novel_handler))
+        .route("/api/novels/:id", get(get_novel_handler).patch(patch_novel_handler).delete(delete_novel_by_id_handler))
+        .route("/api/novels/:id/history", get(novel_history_handler))
+        .route("/api/novels/:id/history/:entry/revert", post(revert_novel_handler))
+        .route("/api/trash", get(list_trash_handler))
+        .route("/api/trash/:id/restore", post(restore_trash_handler))
+        .route("/api/all_novels", post(novels_handler))
         .route("/api/update_novels", post(update_novels_handler))
+        .route("/api/novels_backup", get(download_novels_backup))
         .route("/api/upload_novels_backup", post(upload_novels_backup))
         .route("/api/create_novel", get(create_novel_row_handler))
         .route("/api/delete_novel", delete(delete_novel_handler))
+        .route("/api/snapshots", get(list_snapshots_handler))
+        .route("/api/snapshots/diff", get(diff_snapshots_handler))
+        .route("/api/snapshots/:id/restore", post(restore_snapshot_handler))
         .route("/api/novels_stats", get(get_novels_stats))
         .route("/api/random_novels", post(get_random_novels))
         .route("/api/image_to_tetris", post(image_to_tetris))
         .with_state(state)
-        .layer(DefaultBodyLimit::max(payload_limit));
-
-    // run it
-    let listener = tokio::net::TcpListener::bind(domain.clone())
-        .await?;
-    println!("Listening on {domain}");
-    axum::serve(listener, app).await?;
-
-    Ok(())
+        .layer(DefaultBodyLimit::max(payload_limit))
+        .layer(middleware::from_fn(logging::trace_request))
 }
 
 /* 
 all handlers must:
 return a non-null JSON
 return a status code (can be implicit)
+fail with an ApiError so clients always get the same error shape
 */
 
-async fn novels_handler(state: State<AppState>, subset: Json<NovelSubsets>) -> impl IntoResponse {
-    println!("Fetching novels: {subset:?}");
-    let novels = db::fetch_novel_entries(&state.conn, *subset).await.unwrap_or_default();
-    Json(novels)
+async fn novels_handler(state: State<AppState>, _auth: Authorized<scope::NovelsRead>, ApiJson(subset): ApiJson<NovelSubsets>) -> ApiResult<impl IntoResponse> {
+    tracing::debug!(?subset, "Fetching novels");
+    let novels = db::list_novel_entries(&state.conn).await?;
+    Ok(Json(filter_subset(novels, subset, &state.config.sus_tags)))
+}
+
+async fn query_novels_handler(state: State<AppState>, _auth: Authorized<scope::NovelsRead>, ApiQuery(query): ApiQuery<NovelQuery>) -> ApiResult<impl IntoResponse> {
+    tracing::debug!(?query, "Querying novels");
+    query.validate().map_err(|e| ApiError::validation(e.to_string()))?;
+    let page = db::query_novel_entries(&state.conn, &query, &state.config.sus_tags).await?;
+    Ok((StatusCode::OK, Json(page)))
+}
+
+async fn get_novel_handler(state: State<AppState>, _auth: Authorized<scope::NovelsRead>, ApiPath(id): ApiPath<i32>) -> ApiResult<impl IntoResponse> {
+    tracing::debug!(novel_id = id, "Fetching novel");
+    match db::fetch_novel_by_id(&state.conn, id).await? {
+        Some(novel) => Ok((StatusCode::OK, [(header::ETAG, novel.etag())], Json(novel))),
+        None => Err(novel_not_found(id)),
+    }
 }
 
-type UpdateNovelsResponse = Result<(StatusCode, Json<Vec<novel_entry::NovelEntry>>), (StatusCode, Json<String>)>;
-async fn update_novels_handler(state: State<AppState>, Json(rows): Json<Vec<novel_entry::NovelEntry>>) -> UpdateNovelsResponse {
-    println!("Updating novels {}", rows.len());
-    let res = db::update_novel_entries(&state.conn, &rows, db::UpdateDateModified::True).await;
-    match res {
-        Ok(novels) => Ok((StatusCode::OK, Json(novels))),
-        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(e.to_string()))),
+// POST /api/novels used to list novels by subset; a subset body still lists them, anything else creates a novel
+async fn create_novel_handler(state: State<AppState>, caller: Caller, ApiJson(body): ApiJson<JsonValue>) -> ApiResult<Response> {
+    if let Ok(subset) = serde_json::from_value::<NovelSubsets>(body.clone()) {
+        let novels = novels_handler(state, caller.authorize()?, ApiJson(subset)).await?;
+        return Ok(novels.into_response());
     }
+    let auth = caller.authorize::<scope::NovelsWrite>()?;
+    let initial: NovelPatch = serde_json::from_value(body).map_err(|e| {
+        ApiError::validation(format!("Failed to deserialize the JSON body into the target type: {e}")).with_status(StatusCode::UNPROCESSABLE_ENTITY)
+    })?;
+    tracing::info!("Creating novel");
+    let novel = db::create_empty_row(&state.conn, &initial, &AuditContext::from_caller(&auth.caller, AuditSource::Web)).await?;
+    Ok((StatusCode::CREATED, Json(novel)).into_response())
 }
 
-async fn upload_novels_backup(state: State<AppState>, mut multipart: Multipart) -> impl IntoResponse {
-    println!("Uploading novels backup");
+async fn patch_novel_handler(
+    state: State<AppState>,
+    auth: Authorized<scope::NovelsWrite>,
+    ApiPath(id): ApiPath<i32>,
+    headers: HeaderMap,
+    ApiJson(patch): ApiJson<NovelPatch>
+) -> ApiResult<impl IntoResponse> {
+    tracing::info!(novel_id = id, "Patching novel");
+
+    // an If-Match header makes the patch conditional on the caller's copy being current
+    let expected = match headers.get(header::IF_MATCH).map(|value| value.to_str().ok().and_then(NovelEntry::parse_etag)) {
+        Some(Some(expected)) => Some(expected),
+        Some(None) => return Err(ApiError::validation("Invalid If-Match header")),
+        None => None,
+    };
+
+    match db::patch_novel_entry(&state.conn, id, &patch, expected, &AuditContext::from_caller(&auth.caller, AuditSource::Web)).await? {
+        Some(novel) => Ok((StatusCode::OK, [(header::ETAG, novel.etag())], Json(novel))),
+        None => Err(novel_not_found(id)),
+    }
+}
+
+async fn delete_novel_by_id_handler(state: State<AppState>, auth: Authorized<scope::NovelsWrite>, ApiPath(id): ApiPath<i32>) -> ApiResult<impl IntoResponse> {
+    tracing::info!(novel_id = id, "Trashing novel");
+    match db::trash_novel_entry(&state.conn, id, &AuditContext::from_caller(&auth.caller, AuditSource::Web)).await? {
+        true => Ok((StatusCode::OK, Json(id))),
+        false => Err(novel_not_found(id)),
+    }
+}
+
+async fn list_trash_handler(state: State<AppState>, _auth: Authorized<scope::NovelsRead>) -> ApiResult<impl IntoResponse> {
+    tracing::debug!("Listing trashed novels");
+    let novels = db::fetch_trashed_novels(&state.conn).await?;
+    Ok((StatusCode::OK, Json(novels)))
+}
+
+async fn restore_trash_handler(state: State<AppState>, auth: Authorized<scope::NovelsWrite>, ApiPath(id): ApiPath<i32>) -> ApiResult<impl IntoResponse> {
+    tracing::info!(novel_id = id, "Restoring novel from the trash");
+    match db::restore_trashed_novel(&state.conn, id, &AuditContext::from_caller(&auth.caller, AuditSource::Web)).await? {
+        Some(novel) => Ok((StatusCode::OK, [(header::ETAG, novel.etag())], Json(novel))),
+        None => Err(ApiError::not_found(format!("Novel not in the trash: {id}"))),
+    }
+}
+
+async fn novel_history_handler(state: State<AppState>, _auth: Authorized<scope::NovelsRead>, ApiPath(id): ApiPath<i32>) -> ApiResult<impl IntoResponse> {
+    tracing::debug!(novel_id = id, "Fetching novel history");
+    let history = audit::novel_history(&state.conn, id).await?;
+    Ok((StatusCode::OK, Json(history)))
+}
+
+async fn revert_novel_handler(state: State<AppState>, auth: Authorized<scope::NovelsWrite>, ApiPath((id, entry)): ApiPath<(i32, i32)>) -> ApiResult<impl IntoResponse> {
+    tracing::info!(novel_id = id, entry, "Reverting novel to a history entry");
+    let novel = audit::revert_novel(&state.conn, id, entry, &AuditContext::from_caller(&auth.caller, AuditSource::Web)).await?;
+    Ok((StatusCode::OK, [(header::ETAG, novel.etag())], Json(novel)))
+}
+
+// stale rows are rejected with a 409 whose details are the server's current copies
+async fn update_novels_handler(state: State<AppState>, auth: Authorized<scope::NovelsWrite>, ApiJson(rows): ApiJson<Vec<NovelEntry>>) -> ApiResult<impl IntoResponse> {
+    tracing::info!(rows = rows.len(), "Updating novels");
+
+    // single row edits happen constantly, so only bulk updates are snapshotted
+    if rows.len() > 1 {
+        snapshot::take_snapshot(&state.conn, "before bulk update").await?;
+    }
+    let ctx = AuditContext::from_caller(&auth.caller, AuditSource::Web);
+    let rows = db::update_novel_entries(&state.conn, &rows, db::UpdateDateModified::True, db::CheckConflicts::True, &ctx).await?;
+    Ok((StatusCode::OK, Json(rows.into_iter().map(|row| row.novel).collect::<Vec<_>>())))
+}
+
+async fn download_novels_backup(state: State<AppState>, _auth: Authorized<scope::NovelsRead>) -> ApiResult<impl IntoResponse> {
+    tracing::debug!("Downloading novels backup");
+    let novels = db::fetch_novel_entries(&state.conn).await?;
+    Ok((StatusCode::OK, Json(backup::Backup::new(novels))))
+}
+
+// restores run in a single transaction; conflicts and invalid rows abort the restore unless it is a dry run
+async fn upload_novels_backup(
+    state: State<AppState>,
+    auth: Authorized<scope::BackupRestore>,
+    ApiQuery(options): ApiQuery<RestoreOptions>,
+    multipart: Result<Multipart, MultipartRejection>
+) -> ApiResult<impl IntoResponse> {
+    tracing::info!(?options, "Uploading novels backup");
 
     // parse the multipart form into novel entries
+    let mut multipart = multipart?;
     let mut rows = Vec::new();
-    while let Some(field) = multipart.next_field()
-        .await.map_err(|e| mtp_err(&e))?
-    {
+    while let Some(field) = multipart.next_field().await? {
         if let Some(filename) = field.file_name() {
             if filename.to_lowercase().ends_with(".json") {
-                let bytes = match field.bytes().await {
-                    Ok(bytes) => bytes,
-                    Err(e) => return Err(mtp_err(&e)),
-                };
-
-                match serde_json::from_slice::<Vec<NovelEntry>>(&bytes) {
-                    Ok(data) => rows.extend(data),
-                    Err(err) => return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(err.to_string()))),
-                }
+                let bytes = field.bytes().await?;
+                let data = backup::parse_backup(&bytes)
+                    .map_err(|e| ApiError::validation(e.to_string()).with_status(StatusCode::UNPROCESSABLE_ENTITY))?;
+                rows.extend(data.novels);
             }
         }
     }
 
-    // drop all existing novels to reset it
-    if let Err(err) = db::drop_all_novels(&state.conn).await {
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(err.to_string()),
-        ))
-    }
+    // planned and applied in one transaction; conflicts and invalid rows come back as errors with the report as details
+    let ctx = AuditContext::from_caller(&auth.caller, AuditSource::Restore);
+    let report = db::restore_novels(&state.conn, rows, &options, AllowInvalidRows::False, "before backup restore", &ctx).await?;
+    let status = if options.dry_run { StatusCode::OK } else { StatusCode::ACCEPTED };
+    Ok((status, Json(report)))
+}
 
-    // upload the backup novels
-    let res = db::insert_novel_entries(&state.conn, &rows).await;
-    match res {
-        Ok(()) => Ok((StatusCode::ACCEPTED, Json(rows.len().to_string()))),
-        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(e.to_string()))),
-    }
+async fn list_snapshots_handler(state: State<AppState>, _auth: Authorized<scope::BackupRestore>) -> ApiResult<impl IntoResponse> {
+    tracing::debug!("Listing snapshots");
+    let snapshots = db::fetch_snapshot_infos(&state.conn).await?;
+    Ok((StatusCode::OK, Json(snapshots)))
+}
 
+#[derive(Debug, Deserialize)]
+struct SnapshotDiffQuery {
+    from: i32,
+    to: i32,
 }
 
-async fn create_novel_row_handler(state: State<AppState>) -> impl IntoResponse {
-    println!("Creating novel row");
-    match db::create_empty_row(&state.conn).await {
-        Ok(novel) => Ok((StatusCode::CREATED, Json(novel))),
-        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(e.to_string())))
-    }
+async fn diff_snapshots_handler(state: State<AppState>, _auth: Authorized<scope::BackupRestore>, ApiQuery(query): ApiQuery<SnapshotDiffQuery>) -> ApiResult<impl IntoResponse> {
+    tracing::debug!(from = query.from, to = query.to, "Diffing snapshots");
+    let report = snapshot::diff_snapshots(&state.conn, query.from, query.to).await?;
+    Ok((StatusCode::OK, Json(report)))
 }
 
-type DeleteNovelResponse = Result<(StatusCode, Json<i32>), (StatusCode, Json<String>)>;
-async fn delete_novel_handler(state: State<AppState>, id: Json<i32>) -> DeleteNovelResponse{
-    println!("Deleting novels");
-    match db::delete_novel_entry(&state.conn, *id).await {
-        Ok(()) => Ok((StatusCode::OK, Json(*id))),
-        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(e.to_string()))),
-    }
+async fn restore_snapshot_handler(state: State<AppState>, auth: Authorized<scope::BackupRestore>, ApiPath(id): ApiPath<i32>) -> ApiResult<impl IntoResponse> {
+    tracing::info!(snapshot_id = id, "Restoring snapshot");
+    let report = snapshot::restore_snapshot(&state.conn, id, &AuditContext::from_caller(&auth.caller, AuditSource::Restore)).await?;
+    Ok((StatusCode::ACCEPTED, Json(report)))
+}
+
+async fn create_novel_row_handler(state: State<AppState>, auth: Authorized<scope::NovelsWrite>) -> ApiResult<impl IntoResponse> {
+    tracing::info!("Creating novel row");
+    let novel = db::create_empty_row(&state.conn, &NovelPatch::default(), &AuditContext::from_caller(&auth.caller, AuditSource::Web)).await?;
+    Ok((StatusCode::CREATED, Json(novel)))
 }
 
-async fn get_novels_stats(state: State<AppState>) -> impl IntoResponse {
-    println!("Getting novels stats");
-    match stats::get_stats(&state.conn).await {
-        Ok(res) => Ok((StatusCode::OK, Json(res))),
-        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(e.to_string()))),
+async fn delete_novel_handler(state: State<AppState>, auth: Authorized<scope::NovelsWrite>, ApiJson(id): ApiJson<i32>) -> ApiResult<impl IntoResponse> {
+    tracing::info!(novel_id = id, "Trashing novel");
+    match db::trash_novel_entry(&state.conn, id, &AuditContext::from_caller(&auth.caller, AuditSource::Web)).await? {
+        true => Ok((StatusCode::OK, Json(id))),
+        false => Err(novel_not_found(id)),
     }
 }
 
-async fn get_random_novels(state: State<AppState>, subset: Json<NovelSubsets>) -> impl IntoResponse {
-    println!("Fetching random novels: {subset:?}");
+async fn get_novels_stats(state: State<AppState>, _auth: Authorized<scope::StatsRead>) -> ApiResult<impl IntoResponse> {
+    tracing::debug!("Getting novels stats");
+    let stats = stats::get_stats(&state.conn).await?;
+    Ok((StatusCode::OK, Json(stats)))
+}
+
+async fn get_random_novels(state: State<AppState>, _auth: Authorized<scope::NovelsRead>, ApiJson(subset): ApiJson<NovelSubsets>) -> ApiResult<impl IntoResponse> {
+    tracing::debug!(?subset, "Fetching random novels");
 
     let num_novels: usize = 10;
-    let novels = db::fetch_novel_entries(&state.conn, *subset).await.unwrap_or_default();
+    let novels = filter_subset(db::list_novel_entries(&state.conn).await?, subset, &state.config.sus_tags);
     let amount = num_novels.min(novels.len());
 
     // access the rng in a thread-safe way
     let mut rng = state.rng.lock().await;
     let random_novels: Vec<NovelEntry> = novels
         .choose_multiple(&mut *rng, amount).cloned().collect();
-    Json(random_novels)
+    Ok(Json(random_novels))
 }
 
-async fn image_to_tetris(mut multipart: Multipart) -> impl IntoResponse {
-    println!("Performing image to tetris");
+async fn image_to_tetris(state: State<AppState>, _auth: Authorized<scope::TetrisRun>, multipart: Result<Multipart, MultipartRejection>) -> ApiResult<impl IntoResponse> {
+    tracing::info!("Performing image to tetris");
 
     // parse the multipart into the arguments
+    let mut multipart = multipart?;
     let mut image: Option<Vec<u8>> = None;
     let mut image_format: Option<String> = None;
     let mut board_width: Option<u32> = None;
     let mut board_height: Option<u32> = None;
     let mut prioritize_tetrominos: Option<bool> = None;
-    while let Some(field) = multipart.next_field().await
-        .map_err(|e| mtp_err(&e))?
-    {
+    while let Some(field) = multipart.next_field().await? {
         match field.name() {
             Some("image") => {
                 image_format = PathBuf::from(
                         &field.file_name()
-                        .ok_or((StatusCode::BAD_REQUEST, Json("No file name found".to_string())))?
+                        .ok_or(ApiError::validation("No file name found"))?
                     )
                     .extension()
                     .and_then(|ext| ext.to_str())
                     .map(ToOwned::to_owned);
-                image = Some(field.bytes().await.map_err(|e| mtp_err(&e))?.to_vec());
+                image = Some(field.bytes().await?.to_vec());
             },
             Some("board_width") => board_width = parse_mtp_int(field).await?,
             Some("board_height") => board_height = parse_mtp_int(field).await?,
             Some("prioritize_tetrominos") => prioritize_tetrominos = parse_mtp_int(field).await?.map(|f| f != 0),
-            _ => return Err((StatusCode::BAD_REQUEST, Json("Invalid field".to_string())))
+            _ => return Err(ApiError::validation("Invalid field"))
         }
     }
 
-    let Some(image) = image else {
-        return Err((StatusCode::BAD_REQUEST, Json("No image field found".to_string())));
-    };
-    let Some(image_format) = image_format else {
-        return Err((StatusCode::BAD_REQUEST, Json("No image format found (ex: png, jpg)".to_string())));
-    };
-    let Some(board_width) = board_width else {
-        return Err((StatusCode::BAD_REQUEST, Json("No board width field found".to_string())));
-    };
-    let Some(board_height) = board_height else {
-        return Err((StatusCode::BAD_REQUEST, Json("No board height field found".to_string())));
-    };
-    let Some(prioritize_tetrominos) = prioritize_tetrominos else {
-        return Err((StatusCode::BAD_REQUEST, Json("No prioritize tetrominos field found".to_string())));
-    };
+    let image = image.ok_or(ApiError::validation("No image field found"))?;
+    let image_format = image_format.ok_or(ApiError::validation("No image format found (ex: png, jpg)"))?;
+    let board_width = board_width.ok_or(ApiError::validation("No board width field found"))?;
+    let board_height = board_height.ok_or(ApiError::validation("No board height field found"))?;
+    let prioritize_tetrominos = prioritize_tetrominos.ok_or(ApiError::validation("No prioritize tetrominos field found"))?;
 
-    // then process the image
-    let body = image_to_tetris::run(board_width, board_height, prioritize_tetrominos, &image, &image_format).await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(e.to_string())))?;
+    // then process the image; failures come from the image-to-tetris binary
+    let body = image_to_tetris::run(&state.config.image_to_tetris_path, board_width, board_height, prioritize_tetrominos, &image, &image_format).await
+        .map_err(|e| ApiError::upstream(e.to_string()))?;
 
     let headers = [
         (header::CONTENT_TYPE, "image/png; charset=utf-8"),
@@ -235,18 +376,17 @@ async fn image_to_tetris(mut multipart: Multipart) -> impl IntoResponse {
 }
 
 // function helpers for routes
-type ErrorRes = (StatusCode, Json<String>);
-fn mtp_err(e: &MultipartError) -> ErrorRes {
-    (e.status(), Json(e.to_string()))
+type ApiResult<T> = Result<T, ApiError>;
+
+fn novel_not_found(id: i32) -> ApiError {
+    ApiError::not_found(format!("Novel not found: {id}"))
 }
 
-async fn parse_mtp_int(field: Field<'_>) -> Result<Option<u32>, ErrorRes> {
-    let data = match field.bytes().await {
-        Ok(data) => data,
-        Err(e) => return Err(mtp_err(&e)),
-    };
-    Ok(Some(u32::from_le_bytes(data[0..4]
-        .try_into()
-        .map_err(|_| (StatusCode::BAD_REQUEST, Json("Invalid integer".to_string())))?
-    )))
+async fn parse_mtp_int(field: Field<'_>) -> ApiResult<Option<u32>> {
+    let data = field.bytes().await?;
+    // integers are sent as 4 little endian bytes
+    let bytes = data.get(0..4)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(ApiError::validation("Invalid integer"))?;
+    Ok(Some(u32::from_le_bytes(bytes)))
 }
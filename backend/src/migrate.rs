@@ -0,0 +1,118 @@
+use std::fmt;
+
+use anyhow::Result;
+use migration::{MigrationStatus, Migrator, MigratorTrait};
+use sea_orm::DatabaseConnection;
+
+/*
+The migrations from the `migration` crate are linked into the binary, so deploying doesn't need sea-orm-cli.
+The server and the other cli commands refuse to run against a database with pending migrations,
+unless they're started with `--auto-migrate`, which applies them first.
+*/
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AutoMigrate {
+    True,
+    False,
+}
+
+// returned when the database is behind the migrations this binary was built with
+#[derive(Debug)]
+pub struct PendingMigrations {
+    pub names: Vec<String>,
+}
+
+impl fmt::Display for PendingMigrations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "The database has {} pending migrations ({}); run `migrate up` or start with --auto-migrate",
+            self.names.len(),
+            self.names.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for PendingMigrations {}
+
+pub async fn pending_migrations(conn: &DatabaseConnection) -> Result<Vec<String>> {
+    let pending = Migrator::get_pending_migrations(conn).await?;
+    Ok(pending.iter().map(|migration| migration.name().to_string()).collect())
+}
+
+// every migration this binary knows about, oldest first, with whether it has been applied
+pub async fn migration_status(conn: &DatabaseConnection) -> Result<Vec<(String, MigrationStatus)>> {
+    let migrations = Migrator::get_migration_with_status(conn).await?;
+    Ok(migrations.iter().map(|migration| (migration.name().to_string(), migration.status())).collect())
+}
+
+// applies `steps` pending migrations, or all of them; returns the names of the ones applied
+pub async fn migrate_up(conn: &DatabaseConnection, steps: Option<u32>) -> Result<Vec<String>> {
+    let mut names = pending_migrations(conn).await?;
+    if let Some(steps) = steps {
+        names.truncate(steps as usize);
+    }
+    Migrator::up(conn, steps).await?;
+    Ok(names)
+}
+
+// rolls back the newest `steps` applied migrations; returns their names, newest first
+pub async fn migrate_down(conn: &DatabaseConnection, steps: u32) -> Result<Vec<String>> {
+    let names = migration_status(conn).await?
+        .into_iter()
+        .filter(|(_, status)| *status == MigrationStatus::Applied)
+        .map(|(name, _)| name)
+        .rev()
+        .take(steps as usize)
+        .collect();
+    Migrator::down(conn, Some(steps)).await?;
+    Ok(names)
+}
+
+// fails with `PendingMigrations` if the schema is out of date, unless `auto_migrate` applies them
+pub async fn ensure_migrated(conn: &DatabaseConnection, auto_migrate: AutoMigrate) -> Result<()> {
+    let names = pending_migrations(conn).await?;
+    if names.is_empty() {
+        return Ok(());
+    }
+    if auto_migrate == AutoMigrate::False {
+        return Err(PendingMigrations { names }.into());
+    }
+
+    for name in migrate_up(conn, None).await? {
+        tracing::info!("Applied migration {name}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PoolConfig;
+    use crate::db;
+
+    #[tokio::test]
+    async fn pending_migrations_block_startup() {
+        let conn = db::connect("sqlite::memory:", &PoolConfig::default()).await.unwrap();
+        let all = migration_status(&conn).await.unwrap();
+        assert!(all.iter().all(|(_, status)| *status == MigrationStatus::Pending));
+
+        let e = ensure_migrated(&conn, AutoMigrate::False).await.unwrap_err();
+        assert_eq!(e.downcast::<PendingMigrations>().unwrap().names.len(), all.len());
+
+        ensure_migrated(&conn, AutoMigrate::True).await.unwrap();
+        assert!(pending_migrations(&conn).await.unwrap().is_empty());
+        ensure_migrated(&conn, AutoMigrate::False).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn migrate_up_and_down() {
+        let conn = db::memory_db().await;
+        let newest = migration_status(&conn).await.unwrap().pop().unwrap().0;
+
+        assert_eq!(migrate_down(&conn, 1).await.unwrap(), std::slice::from_ref(&newest));
+        assert_eq!(pending_migrations(&conn).await.unwrap(), std::slice::from_ref(&newest));
+        assert_eq!(migrate_up(&conn, None).await.unwrap(), [newest]);
+        assert!(migrate_up(&conn, None).await.unwrap().is_empty());
+    }
+}
@@ -1,39 +1,113 @@
-use std::str::FromStr;
+use std::fmt;
 
 use crate::entity::novels;
+// stored as database enums, so an unknown status or provider can't be written at all
+pub use crate::entity::sea_orm_active_enums::{Provider, Status};
 use crate::chapter::Chapter;
 
 use anyhow::{Result, Error};
-use chrono::{DateTime, Local, Utc};
+use chrono::{DateTime, SubsecRound, Utc};
+use clap::ValueEnum;
 use itertools::Itertools;
-use sea_orm::{IntoActiveModel, JsonValue};
-use serde::{Deserialize, Serialize};
-use strum::{Display, EnumString};
+use sea_orm::{ActiveValue::NotSet, IntoActiveModel, JsonValue};
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, ValueEnum)]
 pub enum NovelSubsets {
     All,
     NotSus,
 }
 
-#[derive(Clone, Debug, PartialEq, Display, EnumString, Deserialize, Serialize)]
-pub enum Provider {
-    NovelUpdates,
-    RoyalRoad,
+// which column the server should sort a page of novels by
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum NovelSort {
+    #[default]
+    Id,
+    Title,
+    Country,
+    Rating,
+    Status,
+    Provider,
+    DateModified,
+    DateStarted,
+    DateCompleted,
 }
 
-#[derive(Clone, Debug, PartialEq, Display, EnumString, Deserialize, Serialize)]
-pub enum Status {
-    Completed,
-    Dropped,
-    Hiatus,
-    Planning,
-    Reading,
-    Waiting,
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
 }
 
+pub const DEFAULT_PAGE_LIMIT: u64 = 50;
+pub const MAX_PAGE_LIMIT: u64 = 500;
+
+// query parameters for fetching a filtered page of novels; every field is optional
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NovelQuery {
+    // pages are 0-indexed; ignored when a cursor is given
+    pub page: u64,
+    pub limit: Option<u64>,
+    // the id of the last novel from the previous page; only supported when sorting by id
+    pub cursor: Option<i32>,
+    pub sort: NovelSort,
+    pub direction: SortDirection,
+    pub subset: Option<NovelSubsets>,
+    pub status: Option<Status>,
+    pub provider: Option<Provider>,
+    pub country: Option<String>,
+    pub min_rating: Option<u32>,
+    pub max_rating: Option<u32>,
+    // comma separated lists of tags
+    pub tags: Option<String>,
+    pub exclude_tags: Option<String>,
+    pub title: Option<String>,
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NovelPage {
+    pub novels: Vec<NovelEntry>,
+    pub total: u64,
+    pub page: u64,
+    pub limit: u64,
+    pub next_cursor: Option<i32>,
+    // rows on this page that couldn't be read and were left out; `novels doctor` finds them
+    pub skipped: u64,
+}
+
+impl NovelQuery {
+    pub fn limit(&self) -> u64 {
+        self.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT)
+    }
+
+    pub fn include_tags(&self) -> Vec<String> {
+        self.tags.as_deref().map(NovelEntry::parse_tags).unwrap_or_default()
+    }
+
+    pub fn exclude_tags(&self) -> Vec<String> {
+        self.exclude_tags.as_deref().map(NovelEntry::parse_tags).unwrap_or_default()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.cursor.is_some() && self.sort != NovelSort::Id {
+            return Err(Error::msg("A cursor can only be used when sorting by id"));
+        }
+        if let (Some(min), Some(max)) = (self.min_rating, self.max_rating) {
+            if min > max {
+                return Err(Error::msg(format!("min_rating ({min}) is greater than max_rating ({max})")));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct NovelEntry {
     pub id: i32,
     pub country: String,
@@ -49,6 +123,77 @@ pub struct NovelEntry {
     pub date_completed: Option<DateTime<Utc>>,
 }
 
+// a novel in the trash, waiting to be restored or purged
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrashedNovel {
+    #[serde(flatten)]
+    pub novel: NovelEntry,
+    pub deleted_at: DateTime<Utc>,
+}
+
+// a partial novel entry; missing fields are left alone
+// nullable fields use a double option so an explicit null clears them
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NovelPatch {
+    pub country: Option<String>,
+    pub title: Option<String>,
+    pub chapter: Option<Chapter>,
+    pub rating: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub status: Option<Option<Status>>,
+    pub tags: Option<Vec<String>>,
+    pub notes: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub provider: Option<Option<Provider>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub date_started: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub date_completed: Option<Option<DateTime<Utc>>>,
+}
+
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+impl NovelPatch {
+    pub fn apply(&self, novel: &mut NovelEntry) {
+        if let Some(country) = &self.country {
+            novel.country.clone_from(country);
+        }
+        if let Some(title) = &self.title {
+            novel.title.clone_from(title);
+        }
+        if let Some(chapter) = &self.chapter {
+            novel.chapter = chapter.clone();
+        }
+        if let Some(rating) = self.rating {
+            novel.rating = rating;
+        }
+        if let Some(status) = &self.status {
+            novel.status.clone_from(status);
+        }
+        if let Some(tags) = &self.tags {
+            novel.tags.clone_from(tags);
+        }
+        if let Some(notes) = &self.notes {
+            novel.notes.clone_from(notes);
+        }
+        if let Some(provider) = &self.provider {
+            novel.provider.clone_from(provider);
+        }
+        if let Some(date_started) = self.date_started {
+            novel.date_started = date_started;
+        }
+        if let Some(date_completed) = self.date_completed {
+            novel.date_completed = date_completed;
+        }
+    }
+}
+
 // used when importing from csv
 #[derive(Debug)]
 pub struct NovelTagsRecordParsed {
@@ -68,53 +213,94 @@ impl NovelEntry {
             tags: Vec::new(),
             notes: String::new(),
             provider: None,
-            date_modified: Local::now().to_utc(),
+            date_modified: now(),
             date_started: None,
             date_completed: None,
         }
     }
 
+    // `date_modified` doubles as the row's version for optimistic concurrency
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.date_modified.timestamp_micros())
+    }
+
+    pub fn parse_etag(etag: &str) -> Option<DateTime<Utc>> {
+        let micros = etag.trim().trim_start_matches("W/").trim_matches('"').parse().ok()?;
+        DateTime::from_timestamp_micros(micros)
+    }
+
     pub fn parse_tags(s: &str) -> Vec<String> {
         s.split_terminator(',').map(String::from).collect()
     }
 
+    // `deleted_at` is left unset so writes never move a novel in or out of the trash
     #[allow(clippy::cast_possible_wrap)]
     pub fn to_active_model(&self) -> novels::ActiveModel {
-        novels::Model {
+        let mut active_model = novels::Model {
             id: self.id,
             country: self.country.clone(),
             title: self.title.clone(),
             chapter: self.chapter.to_string(),
             rating: Some(self.rating as i32),
-            status: self.status.as_ref().map(ToString::to_string),
+            status: self.status.clone(),
             tags: serde_json::to_value(self.tags.clone()).unwrap(),
             notes: self.notes.clone(),
-            provider: self.provider.as_ref().map(ToString::to_string),
+            provider: self.provider.clone(),
             date_modified: self.date_modified.naive_utc(),
             date_started: self.date_started.map(|date| date.naive_utc()),
             date_completed: self.date_completed.map(|date| date.naive_utc()),
-        }.into_active_model()
+            deleted_at: None,
+        }.into_active_model();
+        active_model.deleted_at = NotSet;
+        active_model
     }
 
-    #[allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
-    pub fn from_model(model: novels::Model) -> Self {
-        Self {
+    // fails instead of guessing when a stored value is invalid; `doctor --fix` repairs those rows
+    pub fn from_model(model: novels::Model) -> Result<Self> {
+        let invalid = |field: &str, value: &dyn fmt::Display| InvalidNovelRow { id: model.id, field: field.to_string(), value: value.to_string() };
+        let rating = u32::try_from(model.rating.unwrap_or_default())
+            .map_err(|_| invalid("rating", &model.rating.unwrap_or_default()))?;
+        let tags = json_value_to_vec_str(&model.tags)
+            .map_err(|_| invalid("tags", &model.tags))?;
+
+        Ok(Self {
             id: model.id,
             country: model.country,
             title: model.title,
             chapter: Chapter::from(&model.chapter),
-            rating: model.rating.unwrap_or_default() as u32,
-            status: model.status.as_ref().map(|status| Status::from_str(status).unwrap()),
-            tags: json_value_to_vec_str(&model.tags).unwrap_or_default(),
+            rating,
+            status: model.status,
+            tags,
             notes: model.notes,
-            provider: model.provider.as_ref().map(|provider| Provider::from_str(provider).unwrap()),
+            provider: model.provider,
             date_modified: model.date_modified.and_utc(),
             date_started: model.date_started.map(|date| date.and_utc()),
             date_completed: model.date_completed.map(|date| date.and_utc()),
-        }
+        })
+    }
+}
+
+// a stored novel with a value that doesn't fit its column's type, like tags that aren't a list of strings
+#[derive(Debug)]
+pub struct InvalidNovelRow {
+    pub id: i32,
+    pub field: String,
+    pub value: String,
+}
+
+impl fmt::Display for InvalidNovelRow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Novel {} has an invalid {}: {} (run `doctor --fix` to repair it)", self.id, self.field, self.value)
     }
 }
 
+impl std::error::Error for InvalidNovelRow {}
+
+// postgres only stores microseconds, so anything finer would never compare equal after a round trip
+pub fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(6)
+}
+
 fn json_value_to_vec_str(val: &JsonValue) -> Result<Vec<String>> {
     match val {
         JsonValue::Array(arr) => {
@@ -134,31 +320,44 @@ fn json_value_to_vec_str(val: &JsonValue) -> Result<Vec<String>> {
 }
 
 // some things should not be shown to the public
-pub fn filter_sus_novels(novels: &[NovelEntry]) -> Vec<NovelEntry> {
+pub fn filter_sus_novels(novels: &[NovelEntry], sus_tags: &[String]) -> Vec<NovelEntry> {
     novels
         .iter()
-        .filter(|&novel| novel.tags.iter().all(|tag| !is_sus(tag)))
+        .filter(|&novel| novel.tags.iter().all(|tag| !is_sus(tag, sus_tags)))
         // novels without tags aren't vetted
         .filter(|&novel| !novel.tags.is_empty())
         .cloned()
         .collect_vec()
 }
 
-fn is_sus(tag: &str) -> bool {
-    const SUS_TAGS: &[&str] = &[
-        "Adult",
-        "Ecchi",
-        "F*llatio",
-        "Pe*verted Protagonist"
-    ];
+pub fn filter_subset(novels: Vec<NovelEntry>, subset: NovelSubsets, sus_tags: &[String]) -> Vec<NovelEntry> {
+    match subset {
+        NovelSubsets::All => novels,
+        NovelSubsets::NotSus => filter_sus_novels(&novels, sus_tags),
+    }
+}
+
+// the default `sus_tags` in the config
+pub const SUS_TAGS: &[&str] = &[
+    "Adult",
+    "Ecchi",
+    "F*llatio",
+    "Pe*verted Protagonist"
+];
 
+fn is_sus(tag: &str, sus_tags: &[String]) -> bool {
     // we don't need to worry about casing
-    SUS_TAGS.contains(&tag)
+    sus_tags.iter().any(|sus_tag| sus_tag == tag)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
+
+    fn sus_tags() -> Vec<String> {
+        SUS_TAGS.iter().map(ToString::to_string).collect()
+    }
 
     #[test]
     fn display_status() {
@@ -176,26 +375,95 @@ mod tests {
         assert_eq!(Status::from_str("Waiting").unwrap(), Status::Waiting);
         assert_eq!(Status::from_str("Dropped").unwrap(), Status::Dropped);
         assert_eq!(Status::from_str("Hiatus").unwrap(), Status::Hiatus);
+        assert_eq!(Status::from_str("reading").unwrap(), Status::Reading);
+        assert_eq!(Provider::from_str("royalroad").unwrap(), Provider::RoyalRoad);
+        assert!(Status::from_str("Read").is_err());
     }
 
     #[test]
-    fn sus_tags() {
-        assert!(!is_sus("One"));
-        assert!(!is_sus("Two"));
-        assert!(!is_sus("Three"));
+    fn sus() {
+        let sus_tags = sus_tags();
+        assert!(!is_sus("One", &sus_tags));
+        assert!(!is_sus("Two", &sus_tags));
+        assert!(!is_sus("Three", &sus_tags));
 
-        assert!(is_sus("Adult"));
-        assert!(is_sus("Pe*verted Protagonist"));
+        assert!(is_sus("Adult", &sus_tags));
+        assert!(is_sus("Pe*verted Protagonist", &sus_tags));
     }
 
     #[test]
     fn filter_empty_tags() {
         let mut novels = [NovelEntry::empty(0), NovelEntry::empty(1)];
-        assert_eq!(filter_sus_novels(&novels).len(), 0);
+        assert_eq!(filter_sus_novels(&novels, &sus_tags()).len(), 0);
 
         novels[0].tags.push("Safe".into());
         novels[1].tags.push("Innocent".into());
-        assert_eq!(filter_sus_novels(&novels).len(), 2);
+        assert_eq!(filter_sus_novels(&novels, &sus_tags()).len(), 2);
+    }
+
+    #[test]
+    fn query_defaults() {
+        let query: NovelQuery = serde_json::from_str("{}").unwrap();
+        assert_eq!(query.page, 0);
+        assert_eq!(query.limit(), DEFAULT_PAGE_LIMIT);
+        assert_eq!(query.sort, NovelSort::Id);
+        assert_eq!(query.direction, SortDirection::Asc);
+        assert!(query.include_tags().is_empty());
+        query.validate().unwrap();
+    }
+
+    #[test]
+    fn query_limit_clamped() {
+        let query = NovelQuery { limit: Some(100_000), ..Default::default() };
+        assert_eq!(query.limit(), MAX_PAGE_LIMIT);
+        let query = NovelQuery { limit: Some(0), ..Default::default() };
+        assert_eq!(query.limit(), 1);
+    }
+
+    #[test]
+    fn query_validation() {
+        let query = NovelQuery { cursor: Some(10), sort: NovelSort::Title, ..Default::default() };
+        assert!(query.validate().is_err());
+        let query = NovelQuery { min_rating: Some(8), max_rating: Some(3), ..Default::default() };
+        assert!(query.validate().is_err());
+        let query = NovelQuery { cursor: Some(10), min_rating: Some(3), max_rating: Some(8), ..Default::default() };
+        query.validate().unwrap();
+    }
+
+    #[test]
+    fn apply_patch() {
+        let mut novel = NovelEntry::empty(3);
+        novel.status = Some(Status::Reading);
+        novel.notes = "keep me".into();
+
+        let patch: NovelPatch = serde_json::from_str(r#"{"title": "Lord of the Mysteries", "rating": 9, "chapter": "v2c10"}"#).unwrap();
+        patch.apply(&mut novel);
+        assert_eq!(novel.id, 3);
+        assert_eq!(novel.title, "Lord of the Mysteries");
+        assert_eq!(novel.rating, 9);
+        assert_eq!(novel.chapter.count_chapters(), 10);
+        assert_eq!(novel.status, Some(Status::Reading));
+        assert_eq!(novel.notes, "keep me");
+    }
+
+    #[test]
+    fn patch_explicit_null() {
+        let mut novel = NovelEntry::empty(0);
+        novel.status = Some(Status::Reading);
+        novel.provider = Some(Provider::RoyalRoad);
+
+        let patch: NovelPatch = serde_json::from_str(r#"{"status": null}"#).unwrap();
+        patch.apply(&mut novel);
+        assert_eq!(novel.status, None);
+        assert_eq!(novel.provider, Some(Provider::RoyalRoad));
+    }
+
+    #[test]
+    fn etag_round_trip() {
+        let novel = NovelEntry::empty(0);
+        assert_eq!(NovelEntry::parse_etag(&novel.etag()), Some(novel.date_modified));
+        assert_eq!(NovelEntry::parse_etag(&format!("W/{}", novel.etag())), Some(novel.date_modified));
+        assert_eq!(NovelEntry::parse_etag("\"not a version\""), None);
     }
 
     #[test]
@@ -203,9 +471,11 @@ mod tests {
         let mut novels = [NovelEntry::empty(0), NovelEntry::empty(1)];
         novels[0].tags.push("Safe".into());
         novels[1].tags.push("Innocent".into());
-        assert_eq!(filter_sus_novels(&novels).len(), 2);
+        assert_eq!(filter_sus_novels(&novels, &sus_tags()).len(), 2);
 
         novels[1].tags.push("Pe*verted Protagonist".to_string());
-        assert_eq!(filter_sus_novels(&novels).len(), 1);
+        assert_eq!(filter_sus_novels(&novels, &sus_tags()).len(), 1);
+        assert_eq!(filter_subset(novels.to_vec(), NovelSubsets::All, &sus_tags()).len(), 2);
+        assert_eq!(filter_subset(novels.to_vec(), NovelSubsets::NotSus, &["Safe".to_string()]).len(), 1);
     }
 }
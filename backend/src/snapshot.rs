@@ -0,0 +1,103 @@
+use crate::audit::AuditContext;
+use crate::backup::{self, AllowInvalidRows, Backup, RestoreMode, RestoreOptions, RestoreReport};
+use crate::db;
+
+use std::fmt;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sea_orm::{ConnectionTrait, DatabaseConnection};
+use serde::{Deserialize, Serialize};
+
+/*
+Snapshots are copies of the whole novel list taken right before anything destructive happens.
+They are stored as backups so older snapshots get upgraded the same way old backup files do.
+*/
+
+// the oldest snapshots past this count are pruned whenever a new one is taken
+const MAX_SNAPSHOTS: u64 = 50;
+
+// a snapshot without its data
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub id: i32,
+    pub created_at: DateTime<Utc>,
+    pub reason: String,
+    pub novel_count: i32,
+}
+
+#[derive(Debug)]
+pub struct SnapshotNotFound(pub i32);
+
+impl fmt::Display for SnapshotNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Snapshot not found: {}", self.0)
+    }
+}
+
+impl std::error::Error for SnapshotNotFound {}
+
+pub async fn take_snapshot<C: ConnectionTrait>(conn: &C, reason: &str) -> Result<SnapshotInfo> {
+    let novels = db::fetch_novel_entries(conn).await?;
+    let info = db::insert_snapshot(conn, reason, &Backup::new(novels)).await?;
+    let pruned = db::prune_snapshots(conn, MAX_SNAPSHOTS).await?;
+    tracing::info!("Took snapshot {} with {} novels ({reason}); pruned {pruned}", info.id, info.novel_count);
+    Ok(info)
+}
+
+pub async fn load_snapshot(conn: &DatabaseConnection, id: i32) -> Result<Backup> {
+    let Some(data) = db::fetch_snapshot_data(conn, id).await? else {
+        return Err(SnapshotNotFound(id).into());
+    };
+    backup::parse_backup_value(data)
+}
+
+// everything that changed going from one snapshot to the other
+pub async fn diff_snapshots(conn: &DatabaseConnection, from: i32, to: i32) -> Result<RestoreReport> {
+    let from = load_snapshot(conn, from).await?;
+    let to = load_snapshot(conn, to).await?;
+    let options = RestoreOptions { mode: RestoreMode::Replace, dry_run: true };
+    Ok(backup::plan_restore(&from.novels, to.novels, &options))
+}
+
+// invalid rows are restored anyway; they were in the database when the snapshot was taken
+pub async fn restore_snapshot(conn: &DatabaseConnection, id: i32, ctx: &AuditContext) -> Result<RestoreReport> {
+    let snapshot = load_snapshot(conn, id).await?;
+    let options = RestoreOptions { mode: RestoreMode::Replace, dry_run: false };
+    db::restore_novels(conn, snapshot.novels, &options, AllowInvalidRows::True, &format!("before restoring snapshot {id}"), ctx).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditAction, AuditSource};
+    use crate::novel_entry::NovelPatch;
+
+    #[tokio::test]
+    async fn diff_and_restore() {
+        let conn = db::memory_db().await;
+        let ctx = AuditContext::new("tester", AuditSource::Restore);
+        let patch = NovelPatch { title: Some("One".into()), ..Default::default() };
+        let one = db::create_empty_row(&conn, &patch, &ctx).await.unwrap();
+        let before = take_snapshot(&conn, "test").await.unwrap();
+
+        db::trash_novel_entry(&conn, one.id, &ctx).await.unwrap();
+        let patch = NovelPatch { title: Some("Two".into()), ..Default::default() };
+        db::create_empty_row(&conn, &patch, &ctx).await.unwrap();
+        let after = take_snapshot(&conn, "test").await.unwrap();
+
+        let diff = diff_snapshots(&conn, before.id, after.id).await.unwrap();
+        assert_eq!((diff.added.len(), diff.removed.len()), (1, 1));
+
+        // the trashed novel comes back out of the trash under its old id
+        let report = restore_snapshot(&conn, before.id, &ctx).await.unwrap();
+        assert_eq!((report.added.len(), report.restored.len(), report.removed.len()), (0, 1, 1));
+        assert_eq!(db::fetch_novel_entries(&conn).await.unwrap(), std::slice::from_ref(&one));
+        // and the novel the snapshot doesn't have goes to the trash in its place
+        let trashed = db::fetch_trashed_novels(&conn).await.unwrap();
+        assert_eq!(trashed.iter().map(|novel| novel.novel.title.as_str()).collect::<Vec<_>>(), ["Two"]);
+        let actions = db::fetch_novel_history(&conn, one.id).await.unwrap().into_iter().map(|entry| entry.action).collect::<Vec<_>>();
+        assert_eq!(actions, [AuditAction::Restore, AuditAction::Trash, AuditAction::Insert]);
+        assert!(load_snapshot(&conn, 42).await.unwrap_err().is::<SnapshotNotFound>());
+    }
+}
@@ -1,9 +1,10 @@
-use crate::{db, novel_entry::NovelSubsets};
+use crate::db;
 use crate::novel_entry::NovelEntry;
 
 use std::collections::HashMap;
 
 use anyhow::Result;
+use itertools::Itertools;
 use sea_orm::DatabaseConnection;
 use serde::{Deserialize, Serialize};
 
@@ -38,9 +39,28 @@ pub struct Stats {
     pub country_dist: HashMap<String, u32>,
 }
 
+impl Stats {
+    // chapter buckets from the fewest chapters to the most; they're named after their range, like 21-50 or 400+
+    pub fn chapter_buckets(&self) -> Vec<(&str, u32)> {
+        let start = |bucket: &str| bucket.split(['-', '+']).next().and_then(|start| start.parse::<u32>().ok());
+        self.chapter_dist.iter()
+            .map(|(bucket, count)| (bucket.as_str(), *count))
+            .sorted_by_key(|(bucket, _)| start(bucket))
+            .collect()
+    }
+}
+
+// a distribution from most to least common, with ties in name order
+pub fn most_common(dist: &HashMap<String, u32>) -> Vec<(&str, u32)> {
+    dist.iter()
+        .map(|(name, count)| (name.as_str(), *count))
+        .sorted_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)))
+        .collect()
+}
+
 #[allow(clippy::cast_precision_loss)]
 pub async fn get_stats(db: &DatabaseConnection) -> Result<Stats> {
-    let novels = db::fetch_novel_entries(db, NovelSubsets::All).await?;
+    let novels = db::list_novel_entries(db).await?;
     let novel_count = u32::try_from(novels.len())?;
     let chapter_count = novels.iter().map(|novel| novel.chapter.count_chapters()).sum();
     let volumes_completed: u32 = novels.iter().map(|novel| novel.chapter.count_volumes()).sum();
@@ -126,8 +146,60 @@ fn find_chapter_country_dist(novels: &[NovelEntry]) -> (HashMap<String, u32>, Ha
     // change the largest key to not include the higher bound
     let last_bucket = CHAPTER_COUNT_BUCKETS[CHAPTER_COUNT_BUCKETS.len() - 1];
     let last_key = dist_to_string(last_bucket.0, last_bucket.1);
-    let last_value = chapter_dist.remove(&last_key).expect("chapter_dist should contain the last key");
-    chapter_dist.insert(format!("{}+", last_bucket.0), last_value);
+    if let Some(last_value) = chapter_dist.remove(&last_key) {
+        chapter_dist.insert(format!("{}+", last_bucket.0), last_value);
+    }
 
     (country_dist, chapter_dist)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{AuditContext, AuditSource};
+    use crate::chapter::Chapter;
+    use crate::db::{CheckConflicts, UpdateDateModified};
+    use crate::novel_entry::Status;
+
+    fn novel(id: i32, country: &str, chapter: &str, rating: u32, status: Option<Status>) -> NovelEntry {
+        NovelEntry {
+            country: country.into(),
+            chapter: Chapter::from(chapter),
+            rating,
+            status,
+            title: format!("Novel {id}"),
+            ..NovelEntry::empty(id)
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_stats() {
+        let db = db::memory_db().await;
+        let stats = get_stats(&db).await.unwrap();
+        assert_eq!(stats.novel_count, 0);
+        assert!(stats.chapter_dist.is_empty());
+    }
+
+    #[tokio::test]
+    async fn novel_stats() {
+        let db = db::memory_db().await;
+        let novels = [
+            novel(1, "CN", "c10", 8, Some(Status::Reading)),
+            novel(2, "cn", "v2c500", 6, Some(Status::Completed)),
+            novel(3, "KR", "", 0, None),
+        ];
+        let ctx = AuditContext::new("tester", AuditSource::Cli);
+        db::update_novel_entries(&db, &novels, UpdateDateModified::False, CheckConflicts::False, &ctx).await.unwrap();
+        db::trash_novel_entry(&db, 3, &ctx).await.unwrap();
+
+        let stats = get_stats(&db).await.unwrap();
+        assert_eq!(stats.novel_count, 2);
+        assert_eq!(stats.chapter_count, 510);
+        assert!((stats.average_rating - 7.0).abs() < f32::EPSILON);
+        assert_eq!(stats.rating_dist[7], 1);
+        assert_eq!(stats.status_dist["Reading"], 1);
+        assert_eq!(stats.country_dist["cn"], 2);
+        assert_eq!(stats.chapter_dist["1-20"], 1);
+        assert_eq!(stats.chapter_dist["400+"], 1);
+    }
+}
@@ -0,0 +1,530 @@
+mod view;
+
+use crate::audit::{AuditContext, AuditSource};
+use crate::chapter::Chapter;
+use crate::db::{self, CheckConflicts, NovelConflict, UpdateDateModified};
+use crate::novel_entry::{NovelEntry, SortDirection, Status};
+use crate::stats::{self, Stats};
+
+use std::cmp::Ordering;
+
+use anyhow::{Error, Result};
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures::StreamExt;
+use ratatui::widgets::TableState;
+use sea_orm::{DatabaseConnection, Iterable};
+
+/*
+A full-screen view of the novel list for quick edits over SSH.
+Edits go through `db::update_novel_entries` with conflict checks, so a novel edited from the web in the meantime
+is reloaded instead of overwritten. Everything else is local: sorting and searching never touch the database.
+*/
+
+// the columns of the novel table, in order; the number keys sort by them
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Column {
+    Id,
+    Title,
+    Chapter,
+    Rating,
+    Status,
+    Provider,
+    Country,
+    Tags,
+}
+
+const COLUMNS: [Column; 8] = [
+    Column::Id,
+    Column::Title,
+    Column::Chapter,
+    Column::Rating,
+    Column::Status,
+    Column::Provider,
+    Column::Country,
+    Column::Tags,
+];
+
+impl Column {
+    fn name(self) -> &'static str {
+        match self {
+            Column::Id => "ID",
+            Column::Title => "Title",
+            Column::Chapter => "Chapter",
+            Column::Rating => "Rating",
+            Column::Status => "Status",
+            Column::Provider => "Provider",
+            Column::Country => "Country",
+            Column::Tags => "Tags",
+        }
+    }
+
+    fn compare(self, a: &NovelEntry, b: &NovelEntry) -> Ordering {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        match self {
+            Column::Id => a.id.cmp(&b.id),
+            Column::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+            Column::Chapter => a.chapter.count_chapters().cmp(&b.chapter.count_chapters()),
+            Column::Rating => a.rating.cmp(&b.rating),
+            Column::Status => optional(a.status.as_ref().map(ToString::to_string)).cmp(&optional(b.status.as_ref().map(ToString::to_string))),
+            Column::Provider => optional(a.provider.as_ref().map(ToString::to_string)).cmp(&optional(b.provider.as_ref().map(ToString::to_string))),
+            Column::Country => a.country.to_lowercase().cmp(&b.country.to_lowercase()),
+            Column::Tags => a.tags.join(",").to_lowercase().cmp(&b.tags.join(",").to_lowercase()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum EditField {
+    Chapter,
+    Status,
+    Rating,
+    Notes,
+}
+
+const EDIT_FIELDS: [EditField; 4] = [EditField::Chapter, EditField::Status, EditField::Rating, EditField::Notes];
+
+// the fields that can be edited inline, as typed so far
+#[derive(Clone, Debug)]
+struct EditForm {
+    novel: NovelEntry,
+    field: EditField,
+    chapter: String,
+    status: Option<Status>,
+    rating: String,
+    notes: String,
+}
+
+impl EditForm {
+    fn new(novel: NovelEntry) -> Self {
+        Self {
+            field: EditField::Chapter,
+            chapter: novel.chapter.to_string(),
+            status: novel.status.clone(),
+            rating: novel.rating.to_string(),
+            notes: novel.notes.clone(),
+            novel,
+        }
+    }
+
+    fn move_field(&mut self, steps: isize) {
+        let idx = EDIT_FIELDS.iter().position(|field| *field == self.field).unwrap_or(0);
+        self.field = EDIT_FIELDS[(idx as isize + steps).rem_euclid(EDIT_FIELDS.len() as isize) as usize];
+    }
+
+    // cycles through no status and then every status
+    fn cycle_status(&mut self, forward: bool) {
+        let options = std::iter::once(None).chain(Status::iter().map(Some)).collect::<Vec<_>>();
+        let idx = options.iter().position(|status| *status == self.status).unwrap_or(0);
+        let next = if forward { idx + 1 } else { idx + options.len() - 1 };
+        self.status = options[next % options.len()].clone();
+    }
+
+    fn text_mut(&mut self) -> Option<&mut String> {
+        match self.field {
+            EditField::Chapter => Some(&mut self.chapter),
+            EditField::Rating => Some(&mut self.rating),
+            EditField::Notes => Some(&mut self.notes),
+            EditField::Status => None,
+        }
+    }
+
+    // the novel with the form applied; the database checks everything else
+    fn apply(&self) -> Result<NovelEntry> {
+        let rating = self.rating.trim().parse()
+            .map_err(|_| Error::msg(format!("Rating must be a whole number, got [{}]", self.rating)))?;
+        Ok(NovelEntry {
+            chapter: Chapter::from(self.chapter.trim()),
+            status: self.status.clone(),
+            rating,
+            notes: self.notes.clone(),
+            ..self.novel.clone()
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Mode {
+    Browse,
+    Search,
+    Edit(Box<EditForm>),
+}
+
+// what the event loop should do after a key press
+#[derive(Clone, Debug, PartialEq)]
+enum Action {
+    None,
+    Quit,
+    Save(NovelEntry),
+    Reload,
+}
+
+struct App {
+    novels: Vec<NovelEntry>,
+    // indices into `novels` that match the search, in sorted order
+    visible: Vec<usize>,
+    table: TableState,
+    sort: Column,
+    direction: SortDirection,
+    search: String,
+    mode: Mode,
+    stats: Option<Stats>,
+    show_stats: bool,
+    message: String,
+}
+
+impl App {
+    fn new(novels: Vec<NovelEntry>, stats: Option<Stats>) -> Self {
+        let mut app = Self {
+            novels,
+            visible: Vec::new(),
+            table: TableState::default(),
+            sort: Column::Id,
+            direction: SortDirection::Asc,
+            search: String::new(),
+            mode: Mode::Browse,
+            stats,
+            show_stats: false,
+            message: String::new(),
+        };
+        app.refresh();
+        app
+    }
+
+    // recomputes the visible rows, keeping the same novel selected if it's still visible
+    fn refresh(&mut self) {
+        let selected_id = self.selected().map(|novel| novel.id);
+        let search = self.search.to_lowercase();
+        self.visible = (0..self.novels.len())
+            .filter(|&idx| matches_search(&self.novels[idx], &search))
+            .collect();
+        let (sort, novels) = (self.sort, &self.novels);
+        self.visible.sort_by(|&a, &b| sort.compare(&novels[a], &novels[b]).then(novels[a].id.cmp(&novels[b].id)));
+        if self.direction == SortDirection::Desc {
+            self.visible.reverse();
+        }
+
+        let position = selected_id.and_then(|id| self.visible.iter().position(|&idx| self.novels[idx].id == id));
+        let fallback = (!self.visible.is_empty()).then_some(0);
+        self.table.select(position.or(fallback));
+    }
+
+    fn selected(&self) -> Option<&NovelEntry> {
+        let idx = self.visible.get(self.table.selected()?)?;
+        self.novels.get(*idx)
+    }
+
+    fn visible_novels(&self) -> impl Iterator<Item = &NovelEntry> {
+        self.visible.iter().map(|&idx| &self.novels[idx])
+    }
+
+    // swaps in a novel as the database has it now
+    fn replace(&mut self, novel: NovelEntry) {
+        match self.novels.iter_mut().find(|current| current.id == novel.id) {
+            Some(current) => *current = novel,
+            None => self.novels.push(novel),
+        }
+        self.refresh();
+    }
+
+    fn move_selection(&mut self, steps: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let current = self.table.selected().unwrap_or(0) as isize;
+        let last = self.visible.len() as isize - 1;
+        self.table.select(Some((current + steps).clamp(0, last) as usize));
+    }
+
+    // picking the sorted column again flips the direction
+    fn sort_by(&mut self, column: Column) {
+        if self.sort == column {
+            self.direction = match self.direction {
+                SortDirection::Asc => SortDirection::Desc,
+                SortDirection::Desc => SortDirection::Asc,
+            };
+        } else {
+            self.sort = column;
+            self.direction = SortDirection::Asc;
+        }
+        self.refresh();
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Action::Quit;
+        }
+        match &mut self.mode {
+            Mode::Browse => self.handle_browse_key(key),
+            Mode::Search => {
+                match key.code {
+                    KeyCode::Enter => self.mode = Mode::Browse,
+                    KeyCode::Esc => {
+                        self.search.clear();
+                        self.mode = Mode::Browse;
+                    },
+                    KeyCode::Backspace => {
+                        self.search.pop();
+                    },
+                    KeyCode::Char(c) => self.search.push(c),
+                    _ => return Action::None,
+                }
+                self.refresh();
+                Action::None
+            },
+            Mode::Edit(form) => {
+                match key.code {
+                    KeyCode::Esc => self.mode = Mode::Browse,
+                    KeyCode::Enter => match form.apply() {
+                        Ok(novel) => {
+                            self.mode = Mode::Browse;
+                            return Action::Save(novel);
+                        },
+                        Err(e) => self.message = e.to_string(),
+                    },
+                    KeyCode::Tab | KeyCode::Down => form.move_field(1),
+                    KeyCode::BackTab | KeyCode::Up => form.move_field(-1),
+                    KeyCode::Left if form.field == EditField::Status => form.cycle_status(false),
+                    KeyCode::Right | KeyCode::Char(' ') if form.field == EditField::Status => form.cycle_status(true),
+                    KeyCode::Backspace => {
+                        if let Some(text) = form.text_mut() {
+                            text.pop();
+                        }
+                    },
+                    KeyCode::Char(c) => {
+                        if let Some(text) = form.text_mut() {
+                            text.push(c);
+                        }
+                    },
+                    _ => {},
+                }
+                Action::None
+            },
+        }
+    }
+
+    fn handle_browse_key(&mut self, key: KeyEvent) -> Action {
+        self.message.clear();
+        match key.code {
+            KeyCode::Char('q') => return Action::Quit,
+            KeyCode::Esc if !self.search.is_empty() => {
+                self.search.clear();
+                self.refresh();
+            },
+            KeyCode::Esc => return Action::Quit,
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::PageDown => self.move_selection(20),
+            KeyCode::PageUp => self.move_selection(-20),
+            KeyCode::Home | KeyCode::Char('g') => self.move_selection(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX / 2),
+            KeyCode::Char('/') => self.mode = Mode::Search,
+            KeyCode::Tab => self.show_stats = !self.show_stats,
+            KeyCode::Char('r') => return Action::Reload,
+            KeyCode::Enter | KeyCode::Char('e') => {
+                if let Some(novel) = self.selected() {
+                    self.mode = Mode::Edit(Box::new(EditForm::new(novel.clone())));
+                }
+            },
+            KeyCode::Char('+') => {
+                let Some(novel) = self.selected() else {
+                    return Action::None;
+                };
+                match novel.chapter.next_chapter() {
+                    Some(chapter) => return Action::Save(NovelEntry { chapter, ..novel.clone() }),
+                    None => self.message = format!("Can't tell what comes after chapter [{}]", novel.chapter),
+                }
+            },
+            KeyCode::Char(c @ '1'..='8') => {
+                let idx = c as usize - '1' as usize;
+                self.sort_by(COLUMNS[idx]);
+            },
+            _ => {},
+        }
+        Action::None
+    }
+}
+
+// searches ignore case and match either the title or any tag
+fn matches_search(novel: &NovelEntry, search: &str) -> bool {
+    search.is_empty()
+        || novel.title.to_lowercase().contains(search)
+        || novel.tags.iter().any(|tag| tag.to_lowercase().contains(search))
+}
+
+pub async fn run(conn: &DatabaseConnection) -> Result<()> {
+    let novels = db::list_novel_entries(conn).await?;
+    let stats = stats::get_stats(conn).await?;
+    let mut app = App::new(novels, Some(stats));
+
+    let mut terminal = ratatui::init();
+    let res = event_loop(conn, &mut terminal, &mut app).await;
+    ratatui::restore();
+    res
+}
+
+async fn event_loop(conn: &DatabaseConnection, terminal: &mut ratatui::DefaultTerminal, app: &mut App) -> Result<()> {
+    let ctx = AuditContext::local(AuditSource::Cli);
+    let mut events = EventStream::new();
+    loop {
+        terminal.draw(|frame| view::draw(frame, app))?;
+        let Some(event) = events.next().await else {
+            return Ok(());
+        };
+        let Event::Key(key) = event? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match app.handle_key(key) {
+            Action::None => {},
+            Action::Quit => return Ok(()),
+            Action::Reload => {
+                app.novels = db::list_novel_entries(conn).await?;
+                app.stats = Some(stats::get_stats(conn).await?);
+                app.refresh();
+                app.message = format!("Reloaded {} novels", app.novels.len());
+            },
+            Action::Save(novel) => {
+                match db::update_novel_entries(conn, std::slice::from_ref(&novel), UpdateDateModified::True, CheckConflicts::True, &ctx).await {
+                    Ok(saved) => {
+                        for row in saved {
+                            app.message = format!("Saved [{}]", row.novel.title);
+                            app.replace(row.novel);
+                        }
+                        app.stats = Some(stats::get_stats(conn).await?);
+                    },
+                    Err(e) => match e.downcast::<NovelConflict>() {
+                        Ok(conflict) => {
+                            for current in conflict.current {
+                                app.replace(current);
+                            }
+                            app.message = format!("[{}] was changed elsewhere; reloaded it, so try again", novel.title);
+                        },
+                        Err(e) => app.message = format!("Failed to save [{}]: {e}", novel.title),
+                    },
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn novel(id: i32, title: &str, rating: u32, tags: &[&str]) -> NovelEntry {
+        NovelEntry {
+            title: title.to_string(),
+            rating,
+            tags: tags.iter().map(ToString::to_string).collect(),
+            chapter: Chapter::from("c10"),
+            ..NovelEntry::empty(id)
+        }
+    }
+
+    fn app() -> App {
+        App::new(vec![
+            novel(1, "Omniscient Reader", 9, &["Apocalypse"]),
+            novel(2, "Lord of the Mysteries", 10, &["Mystery", "Gods"]),
+            novel(3, "Mushoku Tensei", 7, &["Magic"]),
+        ], None)
+    }
+
+    fn press(app: &mut App, code: KeyCode) -> Action {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        for c in text.chars() {
+            press(app, KeyCode::Char(c));
+        }
+    }
+
+    fn visible_ids(app: &App) -> Vec<i32> {
+        app.visible_novels().map(|novel| novel.id).collect()
+    }
+
+    #[test]
+    fn sort_columns() {
+        let mut app = app();
+        assert_eq!(visible_ids(&app), [1, 2, 3]);
+
+        press(&mut app, KeyCode::Char('4'));
+        assert_eq!(visible_ids(&app), [3, 1, 2]);
+        press(&mut app, KeyCode::Char('4'));
+        assert_eq!(visible_ids(&app), [2, 1, 3]);
+
+        // the selection follows the novel, not the row
+        assert_eq!(app.selected().unwrap().id, 1);
+        press(&mut app, KeyCode::Down);
+        assert_eq!(app.selected().unwrap().id, 3);
+        press(&mut app, KeyCode::Char('2'));
+        assert_eq!(visible_ids(&app), [2, 3, 1]);
+        assert_eq!(app.selected().unwrap().id, 3);
+    }
+
+    #[test]
+    fn incremental_search() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('/'));
+        type_text(&mut app, "MYST");
+        assert_eq!(visible_ids(&app), [2]);
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Backspace);
+        assert_eq!(visible_ids(&app), [1, 2, 3]);
+
+        // tags match too, and the search stays after leaving search mode until Esc
+        press(&mut app, KeyCode::Backspace);
+        type_text(&mut app, "gic");
+        assert_eq!(visible_ids(&app), [3]);
+        press(&mut app, KeyCode::Enter);
+        assert!(matches!(app.mode, Mode::Browse));
+        assert_eq!(visible_ids(&app), [3]);
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(visible_ids(&app), [1, 2, 3]);
+    }
+
+    #[test]
+    fn edit_form() {
+        let mut app = app();
+        press(&mut app, KeyCode::Char('e'));
+        press(&mut app, KeyCode::Backspace);
+        type_text(&mut app, "2");
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Right);
+        press(&mut app, KeyCode::Tab);
+        type_text(&mut app, "x");
+
+        // a rating that isn't a number keeps the form open
+        assert_eq!(press(&mut app, KeyCode::Enter), Action::None);
+        assert!(matches!(app.mode, Mode::Edit(_)));
+        press(&mut app, KeyCode::Backspace);
+        press(&mut app, KeyCode::Tab);
+        type_text(&mut app, "reread");
+        press(&mut app, KeyCode::Backspace);
+        type_text(&mut app, "x");
+
+        let Action::Save(saved) = press(&mut app, KeyCode::Enter) else {
+            panic!("expected a save");
+        };
+        assert_eq!(saved.chapter.to_string(), "c12");
+        assert_eq!(saved.status, Status::iter().next());
+        assert_eq!(saved.rating, 9);
+        assert_eq!(saved.notes, "rereax");
+        assert!(matches!(app.mode, Mode::Browse));
+    }
+
+    #[test]
+    fn bump_chapter() {
+        let mut app = app();
+        let Action::Save(saved) = press(&mut app, KeyCode::Char('+')) else {
+            panic!("expected a save");
+        };
+        assert_eq!(saved.chapter.to_string(), "c11");
+        app.replace(saved);
+        assert_eq!(app.selected().unwrap().chapter.to_string(), "c11");
+    }
+}
@@ -0,0 +1,217 @@
+use super::{App, Column, EditField, EditForm, Mode, COLUMNS, EDIT_FIELDS};
+use crate::novel_entry::SortDirection;
+use crate::stats::{self, Stats};
+
+use ratatui::{
+    layout::{Constraint, Direction, Flex, Layout, Rect},
+    style::{Modifier, Style, Stylize},
+    text::Line,
+    widgets::{Bar, BarChart, BarGroup, Block, Clear, Paragraph, Row, Table},
+    Frame,
+};
+
+const BROWSE_HELP: &str = "q quit  ↑↓ scroll  1-8 sort  / search  e edit  + next chapter  Tab stats  r reload";
+const SEARCH_HELP: &str = "Enter keeps the search  Esc clears it";
+const EDIT_HELP: &str = "Tab next field  ←→ status  Enter save  Esc cancel";
+
+// the most countries the stats pane has room for
+const MAX_COUNTRIES: usize = 10;
+
+pub fn draw(frame: &mut Frame, app: &mut App) {
+    let [main, footer] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+    let table_area = match app.stats.as_ref().filter(|_| app.show_stats) {
+        Some(stats) => {
+            let [table_area, stats_area] = Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(main);
+            draw_stats(frame, stats_area, stats);
+            table_area
+        },
+        None => main,
+    };
+    draw_table(frame, table_area, app);
+    draw_footer(frame, footer, app);
+    if let Mode::Edit(form) = &app.mode {
+        draw_edit_form(frame, form);
+    }
+}
+
+fn draw_table(frame: &mut Frame, area: Rect, app: &mut App) {
+    let arrow = match app.direction {
+        SortDirection::Asc => "▲",
+        SortDirection::Desc => "▼",
+    };
+    let header = COLUMNS.iter()
+        .enumerate()
+        .map(|(idx, column)| match *column == app.sort {
+            true => format!("{} {}{arrow}", idx + 1, column.name()),
+            false => format!("{} {}", idx + 1, column.name()),
+        })
+        .collect::<Row>()
+        .bold();
+    let rows = app.visible_novels()
+        .map(|novel| Row::new(COLUMNS.map(|column| match column {
+            Column::Id => novel.id.to_string(),
+            Column::Title => novel.title.clone(),
+            Column::Chapter => novel.chapter.to_string(),
+            Column::Rating => novel.rating.to_string(),
+            Column::Status => novel.status.as_ref().map(ToString::to_string).unwrap_or_default(),
+            Column::Provider => novel.provider.as_ref().map(ToString::to_string).unwrap_or_default(),
+            Column::Country => novel.country.clone(),
+            Column::Tags => novel.tags.join(", "),
+        })))
+        .collect::<Vec<_>>();
+    let widths = [
+        Constraint::Length(6),
+        Constraint::Fill(3),
+        Constraint::Length(11),
+        Constraint::Length(8),
+        Constraint::Length(11),
+        Constraint::Length(14),
+        Constraint::Length(9),
+        Constraint::Fill(2),
+    ];
+
+    let mut title = format!(" Novels ({}/{}) ", app.visible.len(), app.novels.len());
+    if !app.search.is_empty() {
+        title.push_str(&format!("matching [{}] ", app.search));
+    }
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(Block::bordered().title(title))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(table, area, &mut app.table);
+}
+
+fn draw_footer(frame: &mut Frame, area: Rect, app: &App) {
+    let line = if !app.message.is_empty() {
+        Line::from(app.message.as_str()).yellow()
+    } else {
+        match app.mode {
+            Mode::Browse => Line::from(BROWSE_HELP).dim(),
+            Mode::Search => Line::from(format!("/{}▏  {SEARCH_HELP}", app.search)),
+            Mode::Edit(_) => Line::from(EDIT_HELP).dim(),
+        }
+    };
+    frame.render_widget(Paragraph::new(line), area);
+}
+
+fn draw_stats(frame: &mut Frame, area: Rect, stats: &Stats) {
+    let ratings = stats.rating_dist.iter()
+        .enumerate()
+        .map(|(idx, count)| ((idx + 1).to_string(), *count))
+        .collect::<Vec<_>>();
+    let statuses = stats::most_common(&stats.status_dist);
+    let chapters = stats.chapter_buckets();
+    let countries = stats::most_common(&stats.country_dist).into_iter().take(MAX_COUNTRIES).collect::<Vec<_>>();
+
+    // each chart is one row per bar plus its borders
+    let height = |bars: usize| Constraint::Length(bars as u16 + 2);
+    let [totals_area, ratings_area, statuses_area, chapters_area, countries_area] = Layout::vertical([
+        Constraint::Length(4),
+        height(ratings.len()),
+        height(statuses.len()),
+        height(chapters.len()),
+        Constraint::Min(3),
+    ]).areas(area);
+
+    let totals = format!(
+        "{} novels, {} chapters, {} volumes completed\n{} not started, average rating {:.2}",
+        stats.novel_count, stats.chapter_count, stats.volumes_completed, stats.novels_not_started, stats.average_rating,
+    );
+    frame.render_widget(Paragraph::new(totals).block(Block::bordered().title(" Stats ")), totals_area);
+    frame.render_widget(bar_chart(" Ratings ", ratings), ratings_area);
+    frame.render_widget(bar_chart(" Statuses ", to_owned(statuses)), statuses_area);
+    frame.render_widget(bar_chart(" Chapters ", to_owned(chapters)), chapters_area);
+    frame.render_widget(bar_chart(" Countries ", to_owned(countries)), countries_area);
+}
+
+fn to_owned(dist: Vec<(&str, u32)>) -> Vec<(String, u32)> {
+    dist.into_iter().map(|(name, count)| (name.to_string(), count)).collect()
+}
+
+// horizontal bars, so long labels like country names fit
+fn bar_chart(title: &str, dist: Vec<(String, u32)>) -> BarChart<'_> {
+    let bars = dist.into_iter()
+        .map(|(label, count)| Bar::default().label(Line::from(label)).value(u64::from(count)))
+        .collect::<Vec<_>>();
+    BarChart::default()
+        .block(Block::bordered().title(title))
+        .direction(Direction::Horizontal)
+        .bar_width(1)
+        .bar_gap(0)
+        .data(BarGroup::default().bars(&bars))
+}
+
+fn draw_edit_form(frame: &mut Frame, form: &EditForm) {
+    let [area] = Layout::horizontal([Constraint::Length(60)]).flex(Flex::Center).areas(frame.area());
+    let [area] = Layout::vertical([Constraint::Length(EDIT_FIELDS.len() as u16 + 2)]).flex(Flex::Center).areas(area);
+
+    let lines = EDIT_FIELDS.map(|field| {
+        let (name, value) = match field {
+            EditField::Chapter => ("Chapter", form.chapter.clone()),
+            EditField::Status => ("Status", format!("◀ {} ▶", form.status.as_ref().map(ToString::to_string).unwrap_or_default())),
+            EditField::Rating => ("Rating", form.rating.clone()),
+            EditField::Notes => ("Notes", form.notes.clone()),
+        };
+        let line = Line::from(format!("{name:<8} {value}"));
+        if field == form.field {
+            line.reversed()
+        } else {
+            line
+        }
+    });
+    let block = Block::bordered().title(format!(" Edit [{}] ", form.novel.title));
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines.to_vec()).block(block), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chapter::Chapter;
+    use crate::novel_entry::NovelEntry;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use ratatui::{backend::TestBackend, Terminal};
+    use std::collections::HashMap;
+
+    fn screen(terminal: &Terminal<TestBackend>) -> String {
+        let buffer = terminal.backend().buffer();
+        buffer.content().chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn draw_every_pane() {
+        let novel = NovelEntry { title: "Lord of the Mysteries".to_string(), chapter: Chapter::from("c1432"), rating: 10, ..NovelEntry::empty(7) };
+        let stats = Stats {
+            novel_count: 1,
+            chapter_count: 1432,
+            average_rating: 10.0,
+            volumes_completed: 0,
+            novels_not_started: 0,
+            rating_dist: [0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+            status_dist: HashMap::from([("Completed".to_string(), 1)]),
+            chapter_dist: HashMap::from([("400+".to_string(), 1)]),
+            country_dist: HashMap::from([("cn".to_string(), 1)]),
+        };
+        let mut app = App::new(vec![novel], Some(stats));
+        let mut terminal = Terminal::new(TestBackend::new(160, 50)).unwrap();
+
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+        let text = screen(&terminal);
+        assert!(text.contains("Lord of the Mysteries") && text.contains("c1432"));
+        assert!(text.contains("1 ID▲"));
+        assert!(!text.contains("Countries"));
+
+        app.handle_key(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+        let text = screen(&terminal);
+        assert!(text.contains("Countries") && text.contains("1432 chapters"));
+
+        app.handle_key(KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE));
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+        let text = screen(&terminal);
+        assert!(text.contains("Edit [Lord of the Mysteries]"));
+    }
+}
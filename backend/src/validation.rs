@@ -0,0 +1,182 @@
+use crate::novel_entry::NovelEntry;
+
+use std::{collections::HashMap, fmt};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/*
+Rules a novel has to follow before it is written, whichever path the write comes from.
+Only the fields a write changes are checked, so rows saved before a rule existed can still be edited.
+Titles must be unique (ignoring case and surrounding whitespace) among the novels that aren't trashed.
+*/
+pub const MAX_RATING: u32 = 10;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+// everything wrong with one row of a write
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RowError {
+    pub id: i32,
+    pub title: String,
+    pub errors: Vec<FieldError>,
+}
+
+// returned when any row of a write is invalid; nothing is written
+#[derive(Debug)]
+pub struct InvalidNovels {
+    pub rows: Vec<RowError>,
+}
+
+impl fmt::Display for InvalidNovels {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows = self.rows.iter()
+            .map(|row| format!("{} [{}]: {}", row.id, row.title, row.errors.iter().map(|e| format!("{} {}", e.field, e.message)).join(", ")))
+            .join("; ");
+        write!(f, "{} novels are invalid: {rows}", self.rows.len())
+    }
+}
+
+impl std::error::Error for InvalidNovels {}
+
+fn field_error(field: &str, message: impl Into<String>) -> FieldError {
+    FieldError { field: field.to_string(), message: message.into() }
+}
+
+pub fn title_key(title: &str) -> String {
+    title.trim().to_lowercase()
+}
+
+// the checks that only need the row itself; `before` is the stored version, if there is one
+pub fn validate_novel(novel: &NovelEntry, before: Option<&NovelEntry>) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    if before.is_none_or(|before| before.title != novel.title) && novel.title.trim().is_empty() {
+        errors.push(field_error("title", "must not be empty"));
+    }
+    if before.is_none_or(|before| before.rating != novel.rating) && novel.rating > MAX_RATING {
+        errors.push(field_error("rating", format!("must be at most {MAX_RATING}, got {}", novel.rating)));
+    }
+    if before.is_none_or(|before| before.tags != novel.tags) && novel.tags.iter().any(|tag| tag.trim().is_empty()) {
+        errors.push(field_error("tags", "must not contain empty tags"));
+    }
+
+    let dates_changed = before.is_none_or(|before| before.date_started != novel.date_started || before.date_completed != novel.date_completed);
+    if let (true, Some(started), Some(completed)) = (dates_changed, novel.date_started, novel.date_completed) {
+        if completed < started {
+            errors.push(field_error("date_completed", format!("must not be before date_started ({started})")));
+        }
+    }
+
+    errors
+}
+
+/*
+Validates a batch of rows as it will be written.
+`before` has the stored versions of rows that already exist.
+`others` are stored novels that may hold one of the rows' titles; stored versions of the rows themselves are skipped.
+*/
+pub fn validate_novels(rows: &[NovelEntry], before: &HashMap<i32, NovelEntry>, others: &[NovelEntry]) -> Result<(), InvalidNovels> {
+    // who holds each title once the write is done
+    let mut holders: HashMap<String, Vec<i32>> = HashMap::new();
+    for row in rows {
+        holders.entry(title_key(&row.title)).or_default().push(row.id);
+    }
+    for novel in others.iter().filter(|novel| !rows.iter().any(|row| row.id == novel.id)) {
+        holders.entry(title_key(&novel.title)).or_default().push(novel.id);
+    }
+
+    let mut invalid = Vec::new();
+    for row in rows {
+        let previous = before.get(&row.id);
+        let mut errors = validate_novel(row, previous);
+
+        let title_changed = previous.is_none_or(|previous| title_key(&previous.title) != title_key(&row.title));
+        if title_changed && !row.title.trim().is_empty() {
+            let taken_by = holders[&title_key(&row.title)].iter().filter(|&&id| id != row.id).collect_vec();
+            if let Some(id) = taken_by.first() {
+                errors.push(field_error("title", format!("is already used by novel {id}")));
+            }
+        }
+
+        if !errors.is_empty() {
+            invalid.push(RowError { id: row.id, title: row.title.clone(), errors });
+        }
+    }
+
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(InvalidNovels { rows: invalid })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeDelta, Utc};
+
+    fn novel(id: i32, title: &str) -> NovelEntry {
+        NovelEntry { title: title.to_string(), ..NovelEntry::empty(id) }
+    }
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|e| e.field.as_str()).collect()
+    }
+
+    #[test]
+    fn invalid_fields() {
+        let now = Utc::now();
+        let row = NovelEntry {
+            rating: 11,
+            tags: vec!["Action".into(), " ".into()],
+            date_started: Some(now),
+            date_completed: Some(now - TimeDelta::days(1)),
+            ..novel(1, "  ")
+        };
+        assert_eq!(fields(&validate_novel(&row, None)), ["title", "rating", "tags", "date_completed"]);
+
+        let row = NovelEntry { rating: 10, date_started: Some(now), date_completed: Some(now), ..novel(1, "Title") };
+        assert!(validate_novel(&row, None).is_empty());
+    }
+
+    #[test]
+    fn unchanged_fields_are_not_checked() {
+        let stored = NovelEntry { rating: 11, ..novel(1, "") };
+        let edited = NovelEntry { notes: "still reading".into(), ..stored.clone() };
+        assert!(validate_novel(&edited, Some(&stored)).is_empty());
+
+        let edited = NovelEntry { rating: 12, ..stored.clone() };
+        assert_eq!(fields(&validate_novel(&edited, Some(&stored))), ["rating"]);
+    }
+
+    #[test]
+    fn duplicate_titles() {
+        let others = [novel(1, "Overgeared"), novel(2, "Shadow Slave")];
+
+        // a new row can't take an existing title, even with different casing
+        let err = validate_novels(&[novel(3, " overgeared")], &HashMap::new(), &others).unwrap_err();
+        assert_eq!(err.rows[0].errors, [field_error("title", "is already used by novel 1")]);
+
+        // two rows can swap titles in one write
+        let before = others.iter().map(|novel| (novel.id, novel.clone())).collect();
+        let swapped = [novel(1, "Shadow Slave"), novel(2, "Overgeared")];
+        assert!(validate_novels(&swapped, &before, &others).is_ok());
+
+        // rows in the same write can't share a new title
+        let err = validate_novels(&[novel(3, "Lord of the Mysteries"), novel(4, "Lord of the Mysteries")], &HashMap::new(), &others).unwrap_err();
+        assert_eq!(err.rows.iter().map(|row| row.id).collect_vec(), [3, 4]);
+    }
+
+    #[test]
+    fn existing_duplicates_are_not_checked() {
+        let others = [novel(1, "Overgeared"), novel(2, "Overgeared")];
+        let before = others.iter().map(|novel| (novel.id, novel.clone())).collect();
+        let edited = NovelEntry { rating: 7, ..novel(1, "Overgeared") };
+        assert!(validate_novels(&[edited], &before, &others).is_ok());
+    }
+}
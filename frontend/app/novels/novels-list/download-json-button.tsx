@@ -1,7 +1,7 @@
 import * as React from "react"
 import { Button } from "../../../components/ui/button"
 import { NovelEntry } from "./novel-types"
-import { entry_to_api } from "./novel-types"
+import { entry_to_api, BACKUP_SCHEMA_VERSION } from "./novel-types"
 
 interface DownloadJsonButtonProps {
   tableData: NovelEntry[]
@@ -16,7 +16,13 @@ export function DownloadJsonButton({ tableData }: DownloadJsonButtonProps) {
         const novelTableData = tableData.map(entry_to_api);
 
         const now = new Date();
-        const blob = new Blob([JSON.stringify(novelTableData)], { type: "application/json" });
+        const backup = {
+          schema_version: BACKUP_SCHEMA_VERSION,
+          exported_at: now.toISOString(),
+          app_version: "frontend",
+          novels: novelTableData,
+        };
+        const blob = new Blob([JSON.stringify(backup)], { type: "application/json" });
         const url = URL.createObjectURL(blob);
         const link = document.createElement("a");
         link.setAttribute("href", url);
@@ -4,6 +4,9 @@ export const NovelSubsets = {
 } as const;
 export type NovelSubset = typeof NovelSubsets[keyof typeof NovelSubsets];
 
+// must match backup::SCHEMA_VERSION in the backend
+export const BACKUP_SCHEMA_VERSION = 1;
+
 export type NovelEntryApi = {
   id: number,
   country: string,
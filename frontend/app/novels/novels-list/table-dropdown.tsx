@@ -32,7 +32,7 @@ const num_rand_novels = 10;
 const table_dropdown_config: DropdownConfig[] = [
   {
     label: "My Webnovels List",
-    path: "/api/novels",
+    path: "/api/all_novels",
     method: "POST",
   },
   {
@@ -3,9 +3,11 @@
 import { getServerSession } from "next-auth";
 import { authOptions } from "@/app/api/auth/[...nextauth]/options";
 import { z } from "zod";
+import { createHmac } from "crypto";
 
 const public_routes: string[] = [
   "/api/novels",
+  "/api/all_novels",
   "/api/novels_stats",
   "/api/random_novels",
 ];
@@ -30,6 +32,15 @@ export type BackendRequestResponse = {
   error: unknown;
 }
 
+// the backend verifies this HS256 token using the same BACKEND_AUTH_SECRET
+function sign_backend_token(subject: string, role: string, secret: string): string {
+  const encode = (obj: object) => Buffer.from(JSON.stringify(obj)).toString("base64url");
+  const header = encode({alg: "HS256", typ: "JWT"});
+  const payload = encode({sub: subject, role: role, exp: Math.floor(Date.now() / 1000) + 60});
+  const signature = createHmac("sha256", secret).update(`${header}.${payload}`).digest("base64url");
+  return `${header}.${payload}.${signature}`;
+}
+
 export async function fetch_backend(input: BackendRequest): Promise<BackendRequestResponse> {
   // validate input using zod
   const res = BackendRequestSchema.safeParse(input);
@@ -53,23 +64,41 @@ export async function fetch_backend(input: BackendRequest): Promise<BackendReque
     return {data: null, error: "Unauthorized"};
   }
 
+  // forward the session to the backend so it can authorize the request itself
+  const headers: Record<string, string> = {};
+  if (contentType !== undefined) {
+    headers["Content-Type"] = contentType;
+  }
+  if (session?.user?.email && process.env.BACKEND_AUTH_SECRET !== undefined) {
+    const token = sign_backend_token(session.user.email, session.user.role ?? "user", process.env.BACKEND_AUTH_SECRET);
+    headers["Authorization"] = `Bearer ${token}`;
+  }
+
   // try block is needed because fetch will throw errors for network issues
   try {
     const init: RequestInit = {
       method: method,
-      headers: contentType === undefined ? undefined : {
-        "Content-Type": contentType,
-      },
+      headers: headers,
       body: body as BodyInit,
     };
     const response = await fetch(backend_url, init);
 
     // response will also not be ok for other issues like backend issues
     if (!response.ok) {
-      return {data: null, error: await response.text()};
+      return {data: null, error: error_message(await response.text())};
     }
     return {data: await response.json(), error: null}
   } catch (e) {
     return {data: null, error: JSON.stringify(e)};
   }
 }
+
+// the backend sends errors as {code, message, details}
+function error_message(body: string): string {
+  try {
+    const error = JSON.parse(body);
+    return typeof error?.message === "string" ? error.message : body;
+  } catch {
+    return body;
+  }
+}